@@ -1,7 +1,9 @@
 #![feature(test)]
 
 extern crate test;
-use bft_json_crdt::{keypair::make_author, list_crdt::ListCrdt, op::Op, op::ROOT_ID, json_crdt::Value};
+use bft_json_crdt::{
+    json_crdt::Value, keypair::make_author, list_crdt::ListCrdt, op::Op, op::ROOT_ID,
+};
 use rand::seq::SliceRandom;
 use test::Bencher;
 
@@ -47,7 +49,7 @@ fn bench_insert_many_agents_conflicts(b: &mut Bencher) {
         for op in logs {
             for c in &mut crdts {
                 if op.author() != c.our_id {
-                    c.apply(op.clone());
+                    let _ = c.apply(op.clone());
                 }
             }
         }
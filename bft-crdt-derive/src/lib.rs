@@ -6,7 +6,7 @@ use syn::{
     parse::{self, Parser},
     parse_macro_input,
     spanned::Spanned,
-    Data, DeriveInput, Field, Fields, ItemStruct, LitStr, Type
+    Data, DataEnum, DeriveInput, Field, Fields, FieldsNamed, Item, Lit, LitStr, Meta, NestedMeta, Type
 };
 
 /// Helper to get tokenstream representing the parent crate
@@ -22,24 +22,37 @@ fn get_crate_name() -> TokenStream {
     }
 }
 
-/// Proc macro to insert a keypair and path field on a given struct
+/// Proc macro to insert a keypair and path field on a given struct. On enums every variant gets
+/// an `EnumState` instead, unit variants are turned into struct variants to hold it
 #[proc_macro_attribute]
 pub fn add_crdt_fields(args: OgTokenStream, input: OgTokenStream) -> OgTokenStream {
-    let mut input = parse_macro_input!(input as ItemStruct);
+    let mut input = parse_macro_input!(input as Item);
     let crate_name = get_crate_name();
     let _ = parse_macro_input!(args as parse::Nothing);
 
-    if let syn::Fields::Named(ref mut fields) = input.fields {
-        fields.named.push(
-            Field::parse_named
-                .parse2(quote! { path: Vec<#crate_name::op::PathSegment> })
-                .unwrap(),
-        );
-        fields.named.push(
-            Field::parse_named
-                .parse2(quote! { id: #crate_name::keypair::AuthorId })
-                .unwrap(),
-        );
+    match &mut input {
+        Item::Struct(input) => {
+            if let syn::Fields::Named(ref mut fields) = input.fields {
+                fields.named.push(
+                    Field::parse_named
+                        .parse2(quote! { path: Vec<#crate_name::op::PathSegment> })
+                        .unwrap(),
+                );
+                fields.named.push(
+                    Field::parse_named
+                        .parse2(quote! { id: #crate_name::keypair::AuthorId })
+                        .unwrap(),
+                );
+            }
+        }
+        Item::Enum(input) => {
+            input.attrs.extend(enum_attrs());
+            let state = Ident::new("state", Span::call_site());
+            add_enum_state(input.variants.iter_mut(), &state, &crate_name);
+        }
+        _ => {
+            return quote_spanned! { input.span() => compile_error!("add_crdt_fields can only be used on structs and enums"); }.into()
+        }
     }
 
     return quote! {
@@ -48,61 +61,209 @@ pub fn add_crdt_fields(args: OgTokenStream, input: OgTokenStream) -> OgTokenStre
     .into();
 }
 
+/// Variants hold CRDTs of very different sizes, boxing them would only get in the way
+fn enum_attrs() -> Vec<syn::Attribute> {
+    syn::Attribute::parse_outer
+        .parse2(quote! { #[allow(clippy::large_enum_variant)] })
+        .unwrap()
+}
+
+/// Add an `EnumState` field called `name` to every variant
+fn add_enum_state<'a>(variants: impl Iterator<Item = &'a mut syn::Variant>, name: &Ident, crate_name: &TokenStream) {
+    for variant in variants {
+        let state = Field::parse_named
+            .parse2(quote! { #name: #crate_name::enum_crdt::EnumState<Self> })
+            .unwrap();
+        match &mut variant.fields {
+            Fields::Named(fields) => fields.named.push(state),
+            Fields::Unit => {
+                let mut fields: FieldsNamed = syn::parse2(quote! { {} }).unwrap();
+                fields.named.push(state);
+                variant.fields = Fields::Named(fields);
+            }
+            // the derive reports these
+            Fields::Unnamed(_) => {}
+        }
+    }
+}
+
+/// Turn a struct or enum into a CRDT in one go: adds the metadata it needs, derives `Clone` and
+/// implements `CrdtNode` along with the traits it depends on. Takes the same options as the
+/// derive, e.g. `#[crdt(tag = "kind")]` on an enum or `#[crdt(rename = "x")]` on a field.
+/// Unlike `#[add_crdt_fields]`, every field name is available to the struct itself
+#[proc_macro_attribute]
+pub fn crdt(args: OgTokenStream, input: OgTokenStream) -> OgTokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let args = TokenStream::from(args);
+    let crate_name = get_crate_name();
+    let meta = Ident::new(META_FIELD, Span::call_site());
+
+    match &mut input.data {
+        Data::Struct(data) => {
+            if let Fields::Named(fields) = &mut data.fields {
+                let field = Field::parse_named
+                    .parse2(quote! { #meta: #crate_name::json_crdt::NodeMeta })
+                    .unwrap();
+                fields.named.push(field);
+            }
+        }
+        Data::Enum(data) => {
+            input.attrs.extend(enum_attrs());
+            add_enum_state(data.variants.iter_mut(), &meta, &crate_name);
+        }
+        Data::Union(_) => {}
+    }
+
+    // the options are read from the same place as the derive's, then removed again as there is
+    // no derive to claim them
+    let mut with_options = input.clone();
+    if !args.is_empty() {
+        with_options
+            .attrs
+            .extend(syn::Attribute::parse_outer.parse2(quote! { #[crdt(#args)] }).unwrap());
+    }
+    let impls = expand(&with_options, crate_name);
+    let is_crdt = |attr: &syn::Attribute| attr.path.is_ident("crdt");
+    input.attrs.retain(|attr| !is_crdt(attr));
+    match &mut input.data {
+        Data::Struct(data) => data.fields.iter_mut().for_each(|f| f.attrs.retain(|a| !is_crdt(a))),
+        Data::Enum(data) => data
+            .variants
+            .iter_mut()
+            .flat_map(|v| v.fields.iter_mut())
+            .for_each(|f| f.attrs.retain(|a| !is_crdt(a))),
+        Data::Union(_) => {}
+    }
+
+    // compare the last segment so `std::clone::Clone` counts but another derive like `CloneFrom` doesn't
+    let derives_clone = input.attrs.iter().filter(|attr| attr.path.is_ident("derive")).any(|attr| match attr.parse_meta() {
+        Ok(Meta::List(list)) => list.nested.iter().any(|nested| {
+            matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.segments.last().is_some_and(|s| s.ident == "Clone"))
+        }),
+        _ => false,
+    });
+    let clone = if derives_clone { quote! {} } else { quote! { #[derive(Clone)] } };
+    quote! {
+        #clone
+        #input
+        #impls
+    }
+    .into()
+}
+
 /// Proc macro to automatically derive the CRDTNode trait
-#[proc_macro_derive(CrdtNode)]
+#[proc_macro_derive(CrdtNode, attributes(crdt))]
 pub fn derive_json_crdt(input: OgTokenStream) -> OgTokenStream {
     // parse the input tokens into a syntax tree
     let input = parse_macro_input!(input as DeriveInput);
-    let crate_name = get_crate_name();
+    expand(&input, get_crate_name()).into()
+}
 
+/// Name of the metadata field that `#[crdt]` adds, instead of the `path` and `id` fields (or the
+/// `state` field of enum variants) that `#[add_crdt_fields]` adds
+const META_FIELD: &str = "__crdt";
+
+/// Generate all the trait impls for a struct or enum
+fn expand(input: &DeriveInput, crate_name: TokenStream) -> TokenStream {
     // used in the quasi-quotation below as `#name`
-    let ident = input.ident;
+    let ident = input.ident.clone();
     let ident_str = LitStr::new(&*ident.to_string(), ident.span());
 
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
-    match input.data {
+    let (debug_generics, debug_where_clause) = debug_view_generics(input, &crate_name);
+    let (debug_impl_generics, _, _) = debug_generics.split_for_impl();
+    let container = match ContainerAttrs::parse(input) {
+        Ok(container) => container,
+        Err(err) => return err,
+    };
+    match &input.data {
         Data::Struct(data) => match &data.fields {
             Fields::Named(fields) => {
+                // where the metadata lives depends on which macro added it
+                let meta = fields.named.iter().any(|f| f.ident.as_ref().unwrap() == META_FIELD);
+                let (self_path, meta_init) = if meta {
+                    let meta_field = Ident::new(META_FIELD, Span::call_site());
+                    (
+                        quote! { self.#meta_field.path },
+                        quote! { #meta_field: #crate_name::json_crdt::NodeMeta { path: path.clone(), id }, },
+                    )
+                } else {
+                    (quote! { self.path }, quote! { path: path.clone(), id, })
+                };
                 let mut field_impls = vec![];
+                let mut from_impls = vec![];
                 let mut ident_literals = vec![];
                 let mut ident_strings = vec![];
                 let mut tys = vec![];
+                let mut field_tys = vec![];
+                let mut skipped = vec![];
                 // parse all named fields
                 for field in &fields.named {
                     let ident = field.ident.as_ref().expect("Failed to get struct field identifier");
-                    if ident != "path" && ident != "id" {
+                    let is_meta = if meta { ident == META_FIELD } else { ident == "path" || ident == "id" };
+                    if !is_meta {
+                        let attrs = match FieldAttrs::parse(field, &container) {
+                            Ok(attrs) => attrs,
+                            Err(err) => return err,
+                        };
+                        if attrs.skip {
+                            skipped.push(ident.clone());
+                            continue;
+                        }
                         let ty = match &field.ty {
                             Type::Path(t) => t.to_token_stream(),
-                            _ => return quote_spanned! { field.span() => compile_error!("Field should be a primitive or struct which implements CRDTNode") }.into(),
+                            _ => return quote_spanned! { field.span() => compile_error!("Field should be a primitive or struct which implements CRDTNode"); },
+                        };
+                        let str_literal = attrs.key(ident);
+                        if let Some(err) = duplicate_key(&ident_strings, &str_literal) {
+                            return err;
+                        }
+                        let field_path = quote! {
+                            #crate_name::op::join_path(path.clone(), #crate_name::op::PathSegment::Field(#str_literal.to_string()))
+                        };
+                        let missing = match attrs.default_node(&crate_name, &field_path) {
+                            Some(default) => quote! { (#default)? },
+                            None => quote! { return Err(format!("missing field {:?} in {}", #str_literal, #ident_str)) },
                         };
-                        let str_literal = LitStr::new(&*ident.to_string(), ident.span());
+                        from_impls.push(quote! {
+                            #ident: match obj.remove(#str_literal) {
+                                Some(value) => #crate_name::json_crdt::IntoCrdtNode::into_node(value, id, #field_path)?,
+                                None => #missing,
+                            }
+                        });
                         ident_strings.push(str_literal.clone());
                         ident_literals.push(ident.clone());
                         tys.push(ty.clone());
-                        field_impls.push(quote! {
-                            #ident: <#ty as CrdtNode>::new(
-                                id,
-                                #crate_name::op::join_path(path.clone(), #crate_name::op::PathSegment::Field(#str_literal.to_string()))
-                            )
+                        field_tys.push(&field.ty);
+                        // spanned so a type that is not a CRDT is reported at the field
+                        field_impls.push(quote_spanned! { field.ty.span() =>
+                            #ident: <#ty as #crate_name::json_crdt::CrdtNode>::new(id, #field_path)
                         });
                     }
                 }
 
+                let field_count = ident_literals.len();
+                let field_indices = (0..field_count).map(proc_macro2::Literal::usize_unsuffixed).collect::<Vec<_>>();
+
+                // report fields that are not CRDTs at the field before anything else trips over them
+                let assertions = field_tys.iter().map(|ty| {
+                    quote_spanned! { ty.span() => #crate_name::json_crdt::assert_crdt_node::<#ty>(); }
+                });
                 let expanded = quote! {
+                    const _: () = {
+                        #[allow(dead_code)]
+                        fn assert_fields #impl_generics () #where_clause {
+                            #(#assertions)*
+                        }
+                    };
+
                     impl #impl_generics #crate_name::json_crdt::CrdtNodeFromValue for #ident #ty_generics #where_clause {
                         fn node_from(value: #crate_name::json_crdt::Value, id: #crate_name::keypair::AuthorId, path: Vec<#crate_name::op::PathSegment>) -> Result<Self, String> {
                             if let #crate_name::json_crdt::Value::Object(mut obj) = value {
                                 Ok(#ident {
-                                    path: path.clone(),
-                                    id,
-                                    #(#ident_literals: obj.remove(#ident_strings)
-                                        .unwrap()
-                                        .into_node(
-                                            id,
-                                            #crate_name::op::join_path(path.clone(), #crate_name::op::PathSegment::Field(#ident_strings.to_string()))
-                                        )
-                                        .unwrap()
-                                    ),*
+                                    #meta_init
+                                    #(#from_impls,)*
+                                    #(#skipped: Default::default(),)*
                                 })
                             } else {
                                 Err(format!("failed to convert {:?} -> {}<T>", value, #ident_str.to_string()))
@@ -119,52 +280,91 @@ pub fn derive_json_crdt(input: OgTokenStream) -> OgTokenStream {
                     } 
 
                     impl #impl_generics #crate_name::json_crdt::CrdtNode for #ident #ty_generics #where_clause {
-                        fn apply(&mut self, op: #crate_name::op::Op<#crate_name::json_crdt::Value>) -> #crate_name::json_crdt::OpState {
-                            let path = op.path.clone();
-                            let author = op.id.clone();
-                            if !#crate_name::op::ensure_subpath(&self.path, &op.path) {
-                                #crate_name::debug::debug_path_mismatch(self.path.to_owned(), op.path);
-                                return #crate_name::json_crdt::OpState::ErrPathMismatch;
+                        fn apply_observed(&mut self, op: #crate_name::op::Op<#crate_name::json_crdt::Value>, changes: &mut Vec<#crate_name::json_crdt::Change>) -> #crate_name::json_crdt::ApplyResult {
+                            match #crate_name::json_crdt::field_of(&#self_path, &op, #ident_str, &[#(#ident_strings),*])? {
+                                #(#field_indices => #crate_name::json_crdt::CrdtNode::apply_observed(&mut self.#ident_literals, op, changes),)*
+                                _ => unreachable!("field_of only returns the index of one of our fields"),
                             }
+                        }
 
-                            if self.path.len() == op.path.len() {
-                                return #crate_name::json_crdt::OpState::ErrApplyOnStruct; 
-                            } else {
-                                let idx = self.path.len();
-                                if let #crate_name::op::PathSegment::Field(path_seg) = &op.path[idx] {
+                        fn check_all(&self, ops: &[#crate_name::op::Op<#crate_name::json_crdt::Value>]) -> Result<Option<#crate_name::op::OpId>, #crate_name::json_crdt::ApplyError> {
+                            // ops for different fields don't affect each other, so each field only checks its own
+                            let mut by_field = vec![vec![]; #field_count];
+                            for op in ops {
+                                by_field[#crate_name::json_crdt::field_of(&#self_path, op, #ident_str, &[#(#ident_strings),*])?].push(op.clone());
+                            }
+                            #(if !by_field[#field_indices].is_empty() {
+                                if let Some(missing) = #crate_name::json_crdt::CrdtNode::check_all(&self.#ident_literals, &by_field[#field_indices])? {
+                                    return Ok(Some(missing));
+                                }
+                            })*
+                            Ok(None)
+                        }
+
+                        fn edit(&mut self, path: &[#crate_name::op::PathSegment], edit: #crate_name::json_crdt::Edit) -> Result<(#crate_name::op::Op<#crate_name::json_crdt::Value>, #crate_name::json_crdt::Edit), String> {
+                            if path.len() > #self_path.len() && path.starts_with(&#self_path) {
+                                if let #crate_name::op::PathSegment::Field(path_seg) = &path[#self_path.len()] {
                                     match &path_seg[..] {
                                         #(#ident_strings => {
-                                            return self.#ident_literals.apply(op.into());
+                                            return #crate_name::json_crdt::CrdtNode::edit(&mut self.#ident_literals, path, edit);
                                         }),*
                                         _ => {},
                                     };
-                                };
-                                return #crate_name::json_crdt::OpState::ErrPathMismatch 
+                                }
+                            }
+                            Err(format!("{} has no field at /{}", #ident_str, #crate_name::op::print_path(path.to_vec())))
+                        }
+
+                        fn locate(&self, tokens: &[String]) -> Result<#crate_name::json_crdt::Location, String> {
+                            match tokens.split_first() {
+                                None => Ok(#crate_name::json_crdt::Location::Object { path: #self_path.to_owned() }),
+                                #(Some((token, rest)) if token == #ident_strings => #crate_name::json_crdt::CrdtNode::locate(&self.#ident_literals, rest),)*
+                                Some((token, _)) => Err(format!("{} has no field {:?}", #ident_str, token)),
                             }
                         }
 
                         fn view(&self) -> #crate_name::json_crdt::Value {
-                            let mut view_map = std::collections::HashMap::new();
-                            #(view_map.insert(#ident_strings.to_string(), self.#ident_literals.view().into());)*
+                            let mut view_map = std::collections::BTreeMap::new();
+                            #(view_map.insert(#ident_strings.to_string(), #crate_name::json_crdt::CrdtNode::view(&self.#ident_literals));)*
                             #crate_name::json_crdt::Value::Object(view_map)
                         }
 
+                        fn view_at(&self, ops: &std::collections::HashSet<#crate_name::op::OpId>) -> #crate_name::json_crdt::Value {
+                            let mut view_map = std::collections::BTreeMap::new();
+                            #(view_map.insert(#ident_strings.to_string(), #crate_name::json_crdt::CrdtNode::view_at(&self.#ident_literals, ops));)*
+                            #crate_name::json_crdt::Value::Object(view_map)
+                        }
+
+                        fn blame(&self) -> #crate_name::json_crdt::Blame {
+                            let mut blame_map = std::collections::BTreeMap::new();
+                            #(blame_map.insert(#ident_strings.to_string(), #crate_name::json_crdt::CrdtNode::blame(&self.#ident_literals));)*
+                            #crate_name::json_crdt::Blame::Object(blame_map)
+                        }
+
+                        fn max_seq(&self, op: &#crate_name::op::Op<#crate_name::json_crdt::Value>, justified: #crate_name::op::SequenceNumber) -> Option<#crate_name::op::SequenceNumber> {
+                            match #crate_name::json_crdt::field_of(&#self_path, op, #ident_str, &[#(#ident_strings),*]) {
+                                #(Ok(#field_indices) => #crate_name::json_crdt::CrdtNode::max_seq(&self.#ident_literals, op, justified),)*
+                                // not for one of our fields, applying it fails anyway
+                                _ => <#crate_name::lww_crdt::Lamport as #crate_name::lww_crdt::LwwOrder>::max_seq(justified),
+                            }
+                        }
+
                         fn new(id: #crate_name::keypair::AuthorId, path: Vec<#crate_name::op::PathSegment>) -> Self {
                             Self {
-                                path: path.clone(),
-                                id,
-                                #(#field_impls),*
+                                #meta_init
+                                #(#field_impls,)*
+                                #(#skipped: Default::default(),)*
                             }
                         }
                     }
 
-                    impl #crate_name::debug::DebugView for #ident {
+                    impl #debug_impl_generics #crate_name::debug::DebugView for #ident #ty_generics #debug_where_clause {
                         #[cfg(feature = "logging-base")]
                         fn debug_view(&self, indent: usize) -> String {
                             let inner_spacing = " ".repeat(indent + 2);
-                            let path_str = #crate_name::op::print_path(self.path.clone());
+                            let path_str = #crate_name::op::print_path(#self_path.clone());
                             let mut inner = vec![];
-                            #(inner.push(format!("{}\"{}\": {}", inner_spacing, #ident_strings, self.#ident_literals.debug_view(indent + 4)));)*
+                            #(inner.push(format!("{}\"{}\": {}", inner_spacing, #ident_strings, #crate_name::debug::DebugView::debug_view(&self.#ident_literals, indent + 4)));)*
                             let inner_str = inner.join("\n");
                             format!("{} @ /{}\n{}", #ident_str, path_str, inner_str)
                         }
@@ -177,13 +377,426 @@ pub fn derive_json_crdt(input: OgTokenStream) -> OgTokenStream {
                 };
 
                 // Hand the output tokens back to the compiler
-                expanded.into()
-            }
-            _ => {
-                return quote_spanned! { ident.span() => compile_error!("Cannot derive CRDT on tuple or unit structs"); }
-                    .into()
+                expanded
             }
+            _ => quote_spanned! { ident.span() => compile_error!("Cannot derive CRDT on tuple or unit structs"); },
         },
-        _ => return quote_spanned! { ident.span() => compile_error!("Cannot derive CRDT on enums or unions"); }.into(),
+        Data::Enum(data) => derive_enum(input, data, crate_name),
+        _ => quote_spanned! { ident.span() => compile_error!("Cannot derive CRDT on unions"); },
+    }
+}
+
+/// Generics for the `DebugView` impl, which needs every type parameter to implement it too
+fn debug_view_generics(input: &DeriveInput, crate_name: &TokenStream) -> (syn::Generics, Option<syn::WhereClause>) {
+    let mut generics = input.generics.clone();
+    let params = generics.type_params().map(|p| p.ident.clone()).collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause
+            .predicates
+            .push(syn::parse2(quote! { #param: #crate_name::debug::DebugView }).unwrap());
+    }
+    let where_clause = generics.where_clause.clone();
+    (generics, where_clause)
+}
+
+/// Per field options set with `#[crdt(...)]`
+#[derive(Default)]
+struct FieldAttrs {
+    /// Key used in the JSON view and in op paths instead of the field name
+    rename: Option<LitStr>,
+    /// Local-only field that is left out of the view and never receives ops. It is initialised
+    /// with `Default::default()`
+    skip: bool,
+    /// What to use if the field is missing from a JSON value: `Some(None)` for an empty node
+    /// and `Some(Some(f))` for the value returned by `f()`
+    default: Option<Option<syn::Path>>,
+}
+
+impl FieldAttrs {
+    fn parse(field: &Field, container: &ContainerAttrs) -> Result<Self, TokenStream> {
+        let mut attrs = FieldAttrs::default();
+        // like serde, an `Option` that is missing is null
+        if container.default || is_option(&field.ty) {
+            attrs.default = Some(None);
+        }
+        for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("crdt")) {
+            let meta = attr.parse_meta().map_err(|e| e.to_compile_error())?;
+            let Meta::List(list) = meta else {
+                return Err(quote_spanned! { attr.span() => compile_error!("expected #[crdt(...)]"); });
+            };
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => attrs.skip = true,
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("default") => attrs.default = Some(None),
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("rename") => match nv.lit {
+                        Lit::Str(lit) => attrs.rename = Some(lit),
+                        lit => return Err(quote_spanned! { lit.span() => compile_error!("rename should be a string"); }),
+                    },
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("default") => match nv.lit {
+                        Lit::Str(lit) => attrs.default = Some(Some(lit.parse().map_err(|e| e.to_compile_error())?)),
+                        lit => return Err(quote_spanned! { lit.span() => compile_error!("default should be the path of a function as a string"); }),
+                    },
+                    nested => return Err(quote_spanned! { nested.span() => compile_error!("unknown crdt attribute, expected `rename`, `skip` or `default`"); }),
+                }
+            }
+        }
+        Ok(attrs)
+    }
+
+    /// The key of the field in views and paths
+    fn key(&self, ident: &Ident) -> LitStr {
+        self.rename
+            .clone()
+            .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()))
+    }
+
+    /// Expression creating the node for a field that is missing from a JSON value as a
+    /// `Result`, if it has a default. `field_path` is the expression for the path of the field
+    fn default_node(&self, crate_name: &TokenStream, field_path: &TokenStream) -> Option<TokenStream> {
+        match &self.default {
+            None => None,
+            Some(None) => Some(quote! {
+                Ok::<_, String>(#crate_name::json_crdt::CrdtNode::new(id, #field_path))
+            }),
+            Some(Some(f)) => Some(quote! {
+                #crate_name::json_crdt::IntoCrdtNode::into_node(#crate_name::json_crdt::Value::from(#f()), id, #field_path)
+            }),
+        }
+    }
+}
+
+/// Error for a field whose key is already used by another field, which would otherwise make
+/// one of them unreachable
+fn duplicate_key(keys: &[LitStr], key: &LitStr) -> Option<TokenStream> {
+    if !keys.iter().any(|k| k.value() == key.value()) {
+        return None;
+    }
+    let msg = LitStr::new(&format!("duplicate field key {:?}, rename one of the fields", key.value()), key.span());
+    Some(quote_spanned! { key.span() => compile_error!(#msg); })
+}
+
+/// Whether a field is an `Option<_>`, going by its name like serde does
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(ty) => ty.path.segments.last().is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+/// Options set with `#[crdt(...)]` on the struct or enum itself
+#[derive(Default)]
+struct ContainerAttrs {
+    /// Key holding the variant, which makes an enum internally tagged
+    tag: Option<LitStr>,
+    /// Fill every field that is missing from a JSON value with an empty node
+    default: bool,
+}
+
+impl ContainerAttrs {
+    fn parse(input: &DeriveInput) -> Result<Self, TokenStream> {
+        let mut attrs = ContainerAttrs::default();
+        for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("crdt")) {
+            let meta = attr.parse_meta().map_err(|e| e.to_compile_error())?;
+            let Meta::List(list) = meta else {
+                return Err(quote_spanned! { attr.span() => compile_error!("expected #[crdt(...)]"); });
+            };
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("default") => attrs.default = true,
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("tag") => {
+                        if !matches!(input.data, Data::Enum(_)) {
+                            return Err(quote_spanned! { nv.span() => compile_error!("only enums can have a tag"); });
+                        }
+                        match nv.lit {
+                            Lit::Str(lit) => attrs.tag = Some(lit),
+                            lit => return Err(quote_spanned! { lit.span() => compile_error!("the tag should be a string"); }),
+                        }
+                    }
+                    nested => return Err(quote_spanned! { nested.span() => compile_error!("unknown crdt attribute, expected `tag` or `default`"); }),
+                }
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+/// Derive CrdtNode for an enum. The variant is held by an LWW register in every variant's
+/// `EnumState`, see `bft_json_crdt::enum_crdt` for how ops are routed
+fn derive_enum(input: &DeriveInput, data: &DataEnum, crate_name: TokenStream) -> TokenStream {
+    let ident = &input.ident;
+    let ident_str = LitStr::new(&ident.to_string(), ident.span());
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (debug_generics, debug_where_clause) = debug_view_generics(input, &crate_name);
+    let (debug_impl_generics, _, _) = debug_generics.split_for_impl();
+    let container = match ContainerAttrs::parse(input) {
+        Ok(container) => container,
+        Err(err) => return err,
+    };
+    let tag = match &container.tag {
+        Some(tag) => quote! { Some(#tag) },
+        None => quote! { None },
+    };
+    if data.variants.is_empty() {
+        return quote_spanned! { ident.span() => compile_error!("Cannot derive CRDT on enums without variants"); };
+    }
+
+    let variant_idents = data.variants.iter().map(|v| &v.ident).collect::<Vec<_>>();
+    // `#[crdt]` adds the state under a name that cannot clash with the fields of a variant
+    let meta = data.variants.iter().any(|v| v.fields.iter().any(|f| f.ident.as_ref().is_some_and(|i| i == META_FIELD)));
+    let state_field = Ident::new(if meta { META_FIELD } else { "state" }, Span::call_site());
+    let mut variant_strs = vec![];
+    let mut make_arms = vec![];
+    let mut apply_arms = vec![];
+    let mut edit_arms = vec![];
+    let mut locate_arms = vec![];
+    let mut fields_arms = vec![];
+    let mut fields_at_arms = vec![];
+    let mut blame_arms = vec![];
+    let mut seq_arms = vec![];
+    let mut debug_arms = vec![];
+    for variant in &data.variants {
+        let name = &variant.ident;
+        let name_str = LitStr::new(&name.to_string(), name.span());
+        let Fields::Named(fields) = &variant.fields else {
+            return quote_spanned! { variant.span() => compile_error!("Tuple variants are not supported, use named fields"); };
+        };
+        if !fields.named.iter().any(|f| f.ident.as_ref().unwrap() == &state_field) {
+            return quote_spanned! { variant.span() => compile_error!("Missing `state` field, add #[crdt] to the enum"); };
+        }
+        let mut idents = vec![];
+        let mut field_strs = vec![];
+        let mut from_impls = vec![];
+        let mut skipped = vec![];
+        for field in &fields.named {
+            let field_ident = field.ident.as_ref().unwrap();
+            if field_ident == &state_field {
+                continue;
+            }
+            let attrs = match FieldAttrs::parse(field, &container) {
+                Ok(attrs) => attrs,
+                Err(err) => return err,
+            };
+            if attrs.skip {
+                skipped.push(field_ident);
+                continue;
+            }
+            let key = attrs.key(field_ident);
+            if let Some(err) = duplicate_key(&field_strs, &key) {
+                return err;
+            }
+            let default = match attrs.default_node(&crate_name, &quote! { path }) {
+                Some(default) => quote! { Some(|id, path| #default) },
+                None => quote! { None },
+            };
+            from_impls.push(quote! {
+                #field_ident: #crate_name::enum_crdt::field_from(
+                    &mut fields,
+                    #key,
+                    id,
+                    #crate_name::op::join_path(path.clone(), #crate_name::op::PathSegment::Field(#key.to_string())),
+                    #default,
+                )?
+            });
+            idents.push(field_ident);
+            field_strs.push(key);
+        }
+        let fields = idents;
+
+        variant_strs.push(name_str.clone());
+        make_arms.push(quote! {
+            #name_str => {
+                let path = state.variant_path(#name_str);
+                Ok(Self::#name {
+                    #(#from_impls,)*
+                    #(#skipped: Default::default(),)*
+                    #state_field: state,
+                })
+            }
+        });
+        apply_arms.push(quote! {
+            Self::#name { #(#fields,)* .. } => match field {
+                #(#field_strs => Some(#crate_name::json_crdt::CrdtNode::apply_observed(#fields, op, changes)),)*
+                _ => None,
+            }
+        });
+        edit_arms.push(quote! {
+            Self::#name { #(#fields,)* .. } => match field {
+                #(#field_strs => Some(#crate_name::json_crdt::CrdtNode::edit(#fields, path, edit)),)*
+                _ => None,
+            }
+        });
+        locate_arms.push(quote! {
+            Self::#name { #(#fields,)* .. } => match field {
+                #(#field_strs => Some(#crate_name::json_crdt::CrdtNode::locate(#fields, tokens)),)*
+                _ => None,
+            }
+        });
+        fields_arms.push(quote! {
+            Self::#name { #(#fields,)* .. } => vec![#((#field_strs, #crate_name::json_crdt::CrdtNode::view(#fields)),)*]
+        });
+        fields_at_arms.push(quote! {
+            Self::#name { #(#fields,)* .. } => vec![#((#field_strs, #crate_name::json_crdt::CrdtNode::view_at(#fields, ops)),)*]
+        });
+        blame_arms.push(quote! {
+            Self::#name { #(#fields,)* .. } => vec![#((#field_strs, #crate_name::json_crdt::CrdtNode::blame(#fields)),)*]
+        });
+        seq_arms.push(quote! {
+            Self::#name { #(#fields,)* .. } => match field {
+                #(#field_strs => Some(#crate_name::json_crdt::CrdtNode::max_seq(#fields, op, justified)),)*
+                _ => None,
+            }
+        });
+        debug_arms.push(quote! {
+            Self::#name { #(#fields,)* .. } => {
+                #(inner.push(format!("{}\"{}\": {}", inner_spacing, #field_strs, #crate_name::debug::DebugView::debug_view(#fields, indent + 4)));)*
+            }
+        });
+    }
+
+    quote! {
+        impl #impl_generics #crate_name::enum_crdt::CrdtEnum for #ident #ty_generics #where_clause {
+            const VARIANTS: &'static [&'static str] = &[#(#variant_strs),*];
+            const TAG: Option<&'static str> = #tag;
+
+            fn variant(&self) -> &'static str {
+                match self {
+                    #(Self::#variant_idents { .. } => #variant_strs,)*
+                }
+            }
+
+            fn state(&self) -> &#crate_name::enum_crdt::EnumState<Self> {
+                match self {
+                    #(Self::#variant_idents { #state_field: state, .. } => state,)*
+                }
+            }
+
+            fn state_mut(&mut self) -> &mut #crate_name::enum_crdt::EnumState<Self> {
+                match self {
+                    #(Self::#variant_idents { #state_field: state, .. } => state,)*
+                }
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn make_variant(variant: &str, state: #crate_name::enum_crdt::EnumState<Self>, mut fields: Option<#crate_name::json_crdt::Value>) -> Result<Self, String> {
+                let id = state.id;
+                match variant {
+                    #(#make_arms)*
+                    _ => Err(format!("{} has no variant {:?}", #ident_str, variant)),
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn apply_field(&mut self, field: &str, op: #crate_name::op::Op<#crate_name::json_crdt::Value>, changes: &mut Vec<#crate_name::json_crdt::Change>) -> Option<#crate_name::json_crdt::ApplyResult> {
+                match self {
+                    #(#apply_arms,)*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn edit_field(&mut self, field: &str, path: &[#crate_name::op::PathSegment], edit: #crate_name::json_crdt::Edit) -> Option<Result<(#crate_name::op::Op<#crate_name::json_crdt::Value>, #crate_name::json_crdt::Edit), String>> {
+                match self {
+                    #(#edit_arms,)*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn locate_field(&self, field: &str, tokens: &[String]) -> Option<Result<#crate_name::json_crdt::Location, String>> {
+                match self {
+                    #(#locate_arms,)*
+                }
+            }
+
+            fn fields(&self) -> Vec<(&'static str, #crate_name::json_crdt::Value)> {
+                match self {
+                    #(#fields_arms,)*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn fields_at(&self, ops: &std::collections::HashSet<#crate_name::op::OpId>) -> Vec<(&'static str, #crate_name::json_crdt::Value)> {
+                match self {
+                    #(#fields_at_arms,)*
+                }
+            }
+
+            fn fields_blame(&self) -> Vec<(&'static str, #crate_name::json_crdt::Blame)> {
+                match self {
+                    #(#blame_arms,)*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn max_seq_field(&self, field: &str, op: &#crate_name::op::Op<#crate_name::json_crdt::Value>, justified: #crate_name::op::SequenceNumber) -> Option<Option<#crate_name::op::SequenceNumber>> {
+                match self {
+                    #(#seq_arms,)*
+                }
+            }
+        }
+
+        impl #impl_generics #crate_name::json_crdt::CrdtNodeFromValue for #ident #ty_generics #where_clause {
+            fn node_from(value: #crate_name::json_crdt::Value, id: #crate_name::keypair::AuthorId, path: Vec<#crate_name::op::PathSegment>) -> Result<Self, String> {
+                #crate_name::enum_crdt::node_from(value, id, path)
+            }
+        }
+
+        impl #impl_generics std::fmt::Debug for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}::{}", #ident_str, #crate_name::enum_crdt::CrdtEnum::variant(self))
+            }
+        }
+
+        impl #impl_generics #crate_name::json_crdt::CrdtNode for #ident #ty_generics #where_clause {
+            fn apply_observed(&mut self, op: #crate_name::op::Op<#crate_name::json_crdt::Value>, changes: &mut Vec<#crate_name::json_crdt::Change>) -> #crate_name::json_crdt::ApplyResult {
+                #crate_name::enum_crdt::apply(self, op, changes)
+            }
+
+            fn edit(&mut self, path: &[#crate_name::op::PathSegment], edit: #crate_name::json_crdt::Edit) -> Result<(#crate_name::op::Op<#crate_name::json_crdt::Value>, #crate_name::json_crdt::Edit), String> {
+                #crate_name::enum_crdt::edit(self, path, edit)
+            }
+
+            fn locate(&self, tokens: &[String]) -> Result<#crate_name::json_crdt::Location, String> {
+                #crate_name::enum_crdt::locate(self, tokens)
+            }
+
+            fn view(&self) -> #crate_name::json_crdt::Value {
+                #crate_name::enum_crdt::view(self)
+            }
+
+            fn view_at(&self, ops: &std::collections::HashSet<#crate_name::op::OpId>) -> #crate_name::json_crdt::Value {
+                #crate_name::enum_crdt::view_at(self, ops)
+            }
+
+            fn blame(&self) -> #crate_name::json_crdt::Blame {
+                #crate_name::enum_crdt::blame(self)
+            }
+
+            fn max_seq(&self, op: &#crate_name::op::Op<#crate_name::json_crdt::Value>, justified: #crate_name::op::SequenceNumber) -> Option<#crate_name::op::SequenceNumber> {
+                #crate_name::enum_crdt::max_seq(self, op, justified)
+            }
+
+            fn new(id: #crate_name::keypair::AuthorId, path: Vec<#crate_name::op::PathSegment>) -> Self {
+                #crate_name::enum_crdt::new(id, path)
+            }
+        }
+
+        impl #debug_impl_generics #crate_name::debug::DebugView for #ident #ty_generics #debug_where_clause {
+            #[cfg(feature = "logging-base")]
+            fn debug_view(&self, indent: usize) -> String {
+                let inner_spacing = " ".repeat(indent + 2);
+                let path_str = #crate_name::op::print_path(#crate_name::enum_crdt::CrdtEnum::state(self).path.clone());
+                let mut inner: Vec<String> = vec![];
+                match self {
+                    #(#debug_arms)*
+                }
+                let inner_str = inner.join("\n");
+                format!("{}::{} @ /{}\n{}", #ident_str, #crate_name::enum_crdt::CrdtEnum::variant(self), path_str, inner_str)
+            }
+
+            #[cfg(not(feature = "logging-base"))]
+            fn debug_view(&self, _indent: usize) -> String {
+                "".to_string()
+            }
+        }
     }
 }
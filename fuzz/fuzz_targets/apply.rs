@@ -0,0 +1,9 @@
+#![no_main]
+
+use bft_json_crdt_fuzz::{Message, Shape};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Shape, Vec<Message>)| {
+    let (shape, messages) = input;
+    shape.run(&messages);
+});
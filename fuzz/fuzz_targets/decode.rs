@@ -0,0 +1,9 @@
+#![no_main]
+
+use bft_json_crdt_fuzz::{Content, Shape};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Shape, Content)| {
+    let (shape, content) = input;
+    shape.decode(&content);
+});
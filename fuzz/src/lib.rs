@@ -0,0 +1,368 @@
+//! Inputs for the fuzz targets, built from raw fuzzer bytes with [`Arbitrary`]. Unless the input
+//! asks otherwise, fuzzed ops are hashed and signed properly and point at ops and fields that
+//! exist, so the fuzzer gets past the signature, hash and path checks and into the CRDTs
+//! themselves. The receiver must never panic, whatever it is sent.
+
+use arbitrary::Arbitrary;
+use bft_json_crdt::{
+    debug::DebugView,
+    json_crdt::{crdt, BaseCrdt, CrdtNode, CrdtNodeFromValue, SignedOp, Value},
+    keypair::{Ed25519KeyPair, KeyPair, SignedDigest},
+    list_crdt::ListCrdt,
+    lww_crdt::LwwRegisterCrdt,
+    op::{Op, OpId, PathSegment, ROOT_ID},
+    transaction::{SignedTransaction, Transaction},
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Field names of the documents below
+const FIELDS: [&str; 8] = [
+    "text", "grid", "title", "owner", "contacts", "name", "tags", "profile",
+];
+
+#[crdt]
+pub struct Text {
+    text: ListCrdt<char>,
+}
+
+#[crdt]
+pub struct Grid {
+    grid: ListCrdt<ListCrdt<LwwRegisterCrdt<bool>>>,
+}
+
+#[crdt]
+pub struct Contact {
+    name: LwwRegisterCrdt<String>,
+    tags: ListCrdt<String>,
+}
+
+#[crdt]
+pub struct Book {
+    title: LwwRegisterCrdt<Value>,
+    owner: Contact,
+    contacts: ListCrdt<Contact>,
+    profile: LwwRegisterCrdt<Option<Contact>>,
+}
+
+/// The document the receiver holds
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum Shape {
+    Text,
+    Grid,
+    Book,
+}
+
+/// An op ID or signed digest
+#[derive(Arbitrary, Debug)]
+pub enum Ref {
+    Root,
+    /// One made earlier by the same input
+    Earlier(u8),
+    /// Made up
+    Raw(u8),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Segment {
+    Field(u8),
+    Named(String),
+    Index(Ref),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Key {
+    Field(u8),
+    Named(String),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Content {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Char(char),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Content>),
+    Object(Vec<(Key, Content)>),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Seq {
+    /// One past the highest sequence number the input has used
+    Next,
+    Raw(u64),
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct FuzzOp {
+    /// Whether the op claims to be by whoever signs it or by the other author
+    own: bool,
+    origin: Ref,
+    path: Vec<Segment>,
+    /// Append the ID of the op to its path like list inserts do
+    index_self: bool,
+    seq: Seq,
+    is_deleted: bool,
+    content: Option<Content>,
+    /// Use this ID instead of the hash of the op
+    id: Option<[u8; 32]>,
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Signature {
+    /// As the next link in the hash chain of the author, through [`BaseCrdt::sign`]
+    Chained,
+    /// Outside of the hash chain, with the given dependencies
+    Unchained(Vec<Ref>),
+    /// Made up
+    Garbage(u8),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Message {
+    Op {
+        /// Which of the two authors signs
+        signer: bool,
+        signature: Signature,
+        op: FuzzOp,
+    },
+    Transaction {
+        signer: bool,
+        signature: Signature,
+        ops: Vec<FuzzOp>,
+    },
+    /// Send a message again
+    Replay(u8),
+}
+
+/// Sent messages, along with the IDs and digests they made for later messages to refer to
+struct Fuzzer<T: CrdtNode> {
+    keys: [Ed25519KeyPair; 2],
+    /// Only used to sign messages as the next link in the hash chain of each author
+    chains: [BaseCrdt<T>; 2],
+    receiver: BaseCrdt<T>,
+    ids: Vec<OpId>,
+    digests: Vec<SignedDigest>,
+    sent: Vec<Sent>,
+    seq: u64,
+}
+
+enum Sent {
+    Op(SignedOp),
+    Transaction(SignedTransaction),
+}
+
+fn keypair(seed: u64) -> Ed25519KeyPair {
+    Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(seed))
+}
+
+fn pick<T: Copy>(items: &[T], i: u8) -> Option<T> {
+    match items.len() {
+        0 => None,
+        len => Some(items[i as usize % len]),
+    }
+}
+
+impl Content {
+    fn value(&self) -> Value {
+        match self {
+            Content::Null => Value::Null,
+            Content::Bool(b) => (*b).into(),
+            Content::Int(i) => (*i).into(),
+            Content::Float(f) => (*f).into(),
+            Content::Char(c) => (*c).into(),
+            Content::String(s) => s.clone().into(),
+            Content::Bytes(b) => b.clone().into(),
+            Content::Array(items) => Value::Array(items.iter().map(Content::value).collect()),
+            Content::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, value)| (key.name(), value.value()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Key {
+    fn name(&self) -> String {
+        match self {
+            Key::Field(i) => FIELDS[*i as usize % FIELDS.len()].to_string(),
+            Key::Named(name) => name.clone(),
+        }
+    }
+}
+
+impl<T: CrdtNode + DebugView> Fuzzer<T> {
+    fn new() -> Self {
+        let keys = [keypair(0), keypair(1)];
+        Self {
+            chains: [BaseCrdt::new(&keys[0]), BaseCrdt::new(&keys[1])],
+            receiver: BaseCrdt::new(&keypair(2)),
+            keys,
+            ids: vec![],
+            digests: vec![],
+            sent: vec![],
+            seq: 0,
+        }
+    }
+
+    fn id(&self, r: &Ref) -> OpId {
+        match r {
+            Ref::Root => ROOT_ID,
+            Ref::Earlier(i) => pick(&self.ids, *i).unwrap_or(ROOT_ID),
+            Ref::Raw(b) => [*b; 32],
+        }
+    }
+
+    fn digest(&self, r: &Ref) -> SignedDigest {
+        match r {
+            Ref::Root => [0; 64],
+            Ref::Earlier(i) => pick(&self.digests, *i).unwrap_or([0; 64]),
+            Ref::Raw(b) => [*b; 64],
+        }
+    }
+
+    fn op(&mut self, op: &FuzzOp, signer: usize) -> Op<Value> {
+        let author = if op.own { signer } else { 1 - signer };
+        let seq = match op.seq {
+            Seq::Next => {
+                self.seq += 1;
+                self.seq
+            }
+            Seq::Raw(seq) => seq,
+        };
+        let mut new = Op {
+            origin: self.id(&op.origin),
+            author: self.chains[author].id,
+            seq,
+            path: op
+                .path
+                .iter()
+                .map(|segment| match segment {
+                    Segment::Field(i) => PathSegment::Field(Key::Field(*i).name()),
+                    Segment::Named(name) => PathSegment::Field(name.clone()),
+                    Segment::Index(r) => PathSegment::Index(self.id(r)),
+                })
+                .collect(),
+            is_deleted: op.is_deleted,
+            content: op.content.as_ref().map(Content::value),
+            id: ROOT_ID,
+        };
+        new.id = op.id.unwrap_or_else(|| new.hash_to_id());
+        if op.index_self {
+            new.path.push(PathSegment::Index(new.id));
+        }
+        self.ids.push(new.id);
+        new
+    }
+
+    fn send(&mut self, message: &Message) {
+        let sent = match message {
+            Message::Op {
+                signer,
+                signature,
+                op,
+            } => {
+                let signer = *signer as usize;
+                let op = self.op(op, signer);
+                let mut signed = match signature {
+                    Signature::Chained => self.chains[signer].sign(op, &self.keys[signer]),
+                    Signature::Unchained(deps) => {
+                        let deps = deps.iter().map(|r| self.digest(r)).collect();
+                        SignedOp::from_op(op, &self.keys[signer], deps)
+                    }
+                    Signature::Garbage(_) => SignedOp::from_op(op, &self.keys[signer], vec![]),
+                };
+                if let Signature::Garbage(b) = signature {
+                    signed.signed_digest = [*b; 64];
+                }
+                self.digests.push(signed.signed_digest);
+                Sent::Op(signed)
+            }
+            Message::Transaction {
+                signer,
+                signature,
+                ops,
+            } => {
+                let signer = *signer as usize;
+                let tx = ops
+                    .iter()
+                    .fold(Transaction::new(), |tx, op| tx.with(self.op(op, signer)));
+                let mut signed = match signature {
+                    Signature::Chained => {
+                        self.chains[signer].sign_transaction(tx, &self.keys[signer])
+                    }
+                    Signature::Unchained(deps) => deps
+                        .iter()
+                        .fold(tx, |tx, r| tx.depends_on(self.digest(r)))
+                        .sign(&self.keys[signer]),
+                    Signature::Garbage(_) => tx.sign(&self.keys[signer]),
+                };
+                if let Signature::Garbage(b) = signature {
+                    signed.signed_digest = [*b; 64];
+                }
+                self.digests.push(signed.signed_digest);
+                Sent::Transaction(signed)
+            }
+            Message::Replay(i) => match self.sent.len() {
+                0 => return,
+                len => match &self.sent[*i as usize % len] {
+                    Sent::Op(op) => Sent::Op(op.clone()),
+                    Sent::Transaction(tx) => Sent::Transaction(tx.clone()),
+                },
+            },
+        };
+        let _ = match &sent {
+            Sent::Op(op) => self.receiver.apply(op.clone()),
+            Sent::Transaction(tx) => self.receiver.apply_transaction(tx.clone()),
+        };
+        self.sent.push(sent);
+    }
+
+    /// Look at the document every way a user could
+    fn inspect(&self) {
+        let _ = self.receiver.doc.view();
+        let _ = self.receiver.blame();
+        let _ = self.receiver.heads();
+        let _ = self.receiver.missing_dependencies();
+    }
+}
+
+impl Shape {
+    /// Send the messages to a fresh receiver holding this kind of document
+    pub fn run(self, messages: &[Message]) {
+        match self {
+            Shape::Text => run::<Text>(messages),
+            Shape::Grid => run::<Grid>(messages),
+            Shape::Book => run::<Book>(messages),
+        }
+    }
+
+    /// Build this kind of document out of content, like the receiver does for the content of
+    /// an op
+    pub fn decode(self, content: &Content) {
+        match self {
+            Shape::Text => decode::<Text>(content),
+            Shape::Grid => decode::<Grid>(content),
+            Shape::Book => decode::<Book>(content),
+        }
+    }
+}
+
+fn run<T: CrdtNode + DebugView>(messages: &[Message]) {
+    let mut fuzzer = Fuzzer::<T>::new();
+    for message in messages {
+        fuzzer.send(message);
+    }
+    fuzzer.inspect();
+}
+
+fn decode<T: CrdtNode + CrdtNodeFromValue>(content: &Content) {
+    if let Ok(node) = T::node_from(content.value(), [0; 32], vec![]) {
+        let _ = node.view();
+        let _ = node.blame();
+    }
+}
@@ -1,29 +1,40 @@
+//! Logging for the CRDTs. Everything is emitted as [`tracing`] events so it can be filtered at
+//! runtime with any subscriber (e.g. `RUST_LOG=bft_json_crdt=debug` with `tracing-subscriber`).
+//! Events carry the replica, author, op id and path as structured fields.
+//!
+//! The coloured tree views of the document and of list internals are expensive to build, so they
+//! are compiled in only when the `logging-json` and `logging-list` features are enabled; there is
+//! no way to turn them on at runtime without those features. They are emitted as `trace` level
+//! events on the `bft_json_crdt::json` and `bft_json_crdt::list` targets and are only built when
+//! a subscriber is interested in those events.
+
 use crate::{
-    json_crdt::{BaseCrdt, CrdtNode, SignedOp},
-    keypair::SignedDigest,
+    json_crdt::{BaseCrdt, ChainLink, CrdtNode, SignedOp, Value},
+    keypair::{lsb_32, AuthorId, SignedDigest},
     list_crdt::ListCrdt,
-    op::{Op, OpId, PathSegment},
+    op::{print_hex, print_path, Op, OpId, PathSegment, SequenceNumber},
+    transaction::SignedTransaction,
 };
+use tracing::{debug, debug_span, warn, Span};
 
 #[cfg(feature = "logging-base")]
 use {
-    crate::{
-        keypair::{lsb_32, AuthorId},
-        op::{print_hex, print_path, ROOT_ID},
-    },
+    crate::op::ROOT_ID,
     colored::Colorize,
     random_color::{Luminosity, RandomColor},
 };
 
 #[cfg(feature = "logging-list")]
 use std::collections::HashMap;
-use std::fmt::Display;
 
-#[cfg(feature = "logging-base")]
 fn author_to_hex(author: AuthorId) -> String {
     format!("{:#010x}", lsb_32(author))
 }
 
+fn short_hex<const N: usize>(bytes: &[u8; N]) -> String {
+    print_hex(bytes)[..6].to_string()
+}
+
 #[cfg(feature = "logging-base")]
 fn display_op_id<T: CrdtNode>(op: &Op<T>) -> String {
     let [r, g, b] = RandomColor::new()
@@ -37,46 +48,20 @@ fn display_op_id<T: CrdtNode>(op: &Op<T>) -> String {
     )
 }
 
-pub fn debug_type_mismatch(_msg: String) {
-    #[cfg(feature = "logging-base")]
-    {
-        println!("  {}\n  {_msg}", "type mismatch! ignoring this node".red(),);
-    }
-}
-
-pub fn debug_path_mismatch(_our_path: Vec<PathSegment>, _op_path: Vec<PathSegment>) {
-    #[cfg(feature = "logging-base")]
-    {
-        println!(
-            "  {}\n  current path: {}\n  op path: {}",
-            "path mismatch!".red(),
-            print_path(_our_path),
-            print_path(_op_path),
-        );
-    }
+pub fn debug_type_mismatch(msg: String) {
+    debug!(reason = %msg, "type mismatch, ignoring this node");
 }
 
-pub fn debug_op_on_primitive(_op_path: Vec<PathSegment>) {
-    #[cfg(feature = "logging-base")]
-    {
-        println!(
-            "  {} this is an error, ignoring op.\n  op path: {}",
-            "trying to apply() on a primitive!".red(),
-            print_path(_op_path),
-        );
-    }
+pub fn debug_path_mismatch(our_path: Vec<PathSegment>, op_path: Vec<PathSegment>) {
+    debug!(
+        current_path = %print_path(our_path),
+        path = %print_path(op_path),
+        "path mismatch"
+    );
 }
 
-#[cfg(feature = "logging-base")]
-fn display_author(author: AuthorId) -> String {
-    let [r, g, b] = RandomColor::new()
-        .luminosity(Luminosity::Light)
-        .seed(lsb_32(author))
-        .to_rgb_array();
-    format!(" {} ", author_to_hex(author))
-        .black()
-        .on_truecolor(r, g, b)
-        .to_string()
+pub fn debug_op_on_primitive(op_path: Vec<PathSegment>) {
+    warn!(path = %print_path(op_path), "trying to apply() on a primitive, ignoring op");
 }
 
 pub trait DebugView {
@@ -86,46 +71,114 @@ pub trait DebugView {
 impl<T: CrdtNode + DebugView> BaseCrdt<T> {
     pub fn debug_view(&self) {
         #[cfg(feature = "logging-json")]
-        println!("document is now:\n{}", self.doc.debug_view(0));
+        tracing::trace!(
+            target: "bft_json_crdt::json",
+            "document is now:\n{}",
+            self.doc.debug_view(0)
+        );
     }
 
-    pub fn log_try_apply(&self, _op: &SignedOp) {
-        #[cfg(feature = "logging-json")]
-        println!(
-            "{} trying to apply operation {} from {}",
-            display_author(self.id),
-            &print_hex(&_op.signed_digest)[..6],
-            display_author(_op.inner.author())
+    /// Span that all events from delivering a single message are recorded under
+    pub fn apply_span(&self, digest: &SignedDigest, author: AuthorId) -> Span {
+        debug_span!(
+            "apply",
+            replica = %author_to_hex(self.id),
+            digest = %short_hex(digest),
+            author = %author_to_hex(author),
+        )
+    }
+
+    pub fn log_try_apply(&self, op: &SignedOp) {
+        debug!(
+            op_id = %short_hex(&op.id()),
+            path = %print_path(op.inner.path.clone()),
+            "trying to apply operation"
         );
     }
 
-    pub fn debug_digest_failure(&self, _op: SignedOp) {
-        #[cfg(feature = "logging-json")]
-        println!(
-            "  {} cannot confirm signed_digest from {}",
-            "digest failure!".red(),
-            display_author(_op.author())
+    pub fn debug_digest_failure(&self, op: SignedOp) {
+        warn!(
+            author = %author_to_hex(op.author()),
+            op_id = %short_hex(&op.id()),
+            "digest failure, cannot confirm signed_digest"
         );
     }
 
-    pub fn log_missing_causal_dep(&self, _missing: &SignedDigest) {
-        #[cfg(feature = "logging-json")]
-        println!(
-            "  {} haven't received op with digest {}",
-            "missing causal dependency".red(),
-            print_hex(_missing)
+    pub fn log_missing_causal_dep(&self, missing: &SignedDigest) {
+        debug!(
+            missing = %print_hex(missing),
+            "missing causal dependency, queueing message"
+        );
+    }
+
+    pub fn log_missing_element(&self, missing: &OpId) {
+        debug!(
+            missing = %print_hex(missing),
+            "transaction has an op for an element we don't have, queueing transaction"
+        );
+    }
+
+    pub fn debug_broken_chain(&self, author: AuthorId, link: &ChainLink) {
+        warn!(
+            author = %author_to_hex(author),
+            height = link.height,
+            "broken hash chain, message does not extend the author's history"
         );
     }
 
-    pub fn log_actually_apply(&self, _op: &SignedOp) {
+    pub fn debug_forked_chain(&self, author: AuthorId, link: &ChainLink) {
+        warn!(
+            author = %author_to_hex(author),
+            height = link.height,
+            "forked history, author signed two different messages at the same height"
+        );
+    }
+
+    pub fn debug_sequence_jump(
+        &self,
+        author: AuthorId,
+        seq: SequenceNumber,
+        allowed: SequenceNumber,
+    ) {
+        warn!(
+            author = %author_to_hex(author),
+            seq,
+            allowed,
+            "sequence number jumps past what the causal past of the message allows"
+        );
+    }
+
+    pub fn log_actually_apply(&self, op: &SignedOp) {
+        self.log_actually_apply_inner(&op.inner);
+    }
+
+    pub fn log_actually_apply_inner(&self, op: &Op<Value>) {
+        debug!(
+            author = %author_to_hex(op.author),
+            op_id = %short_hex(&op.id),
+            path = %print_path(op.path.clone()),
+            "applying op"
+        );
         #[cfg(feature = "logging-json")]
-        {
-            println!(
-                "  applying op to path: /{}",
-                print_path(_op.inner.path.clone())
-            );
-            println!("{}", _op.inner.debug_view(2));
-        }
+        tracing::trace!(target: "bft_json_crdt::json", "{}", op.debug_view(2));
+    }
+
+    pub fn log_try_apply_transaction(&self, tx: &SignedTransaction) {
+        debug!(ops = tx.ops.len(), "trying to apply transaction");
+    }
+
+    pub fn debug_transaction_digest_failure(&self, tx: SignedTransaction) {
+        warn!(
+            author = %author_to_hex(tx.author()),
+            "digest failure, cannot confirm signed_digest of transaction"
+        );
+    }
+
+    pub fn debug_transaction_rejected(&self, tx: &SignedDigest) {
+        warn!(
+            digest = %short_hex(tx),
+            "transaction rejected, an op would fail"
+        );
     }
 }
 
@@ -134,30 +187,37 @@ where
     T: CrdtNode,
 {
     pub fn debug_hash_failure(&self) {
-        #[cfg(feature = "logging-base")]
-        {
-            println!("  {}", "hash failure!".red());
-            println!("  expected: {}", print_hex(&self.id));
-            println!("  computed: {}", print_hex(&self.hash_to_id()));
-        }
+        warn!(
+            author = %author_to_hex(self.author),
+            expected = %print_hex(&self.id),
+            computed = %print_hex(&self.hash_to_id()),
+            "hash failure"
+        );
     }
 }
 
-impl<T> DebugView for T
-where
-    T: Display,
-{
-    #[cfg(feature = "logging-base")]
-    fn debug_view(&self, _indent: usize) -> String {
-        self.to_string()
-    }
+/// Primitives are shown as they are. This is not a blanket impl over [`std::fmt::Display`] so that
+/// containers like [`Option`] can have their own
+macro_rules! impl_debug_view_for_display {
+    ($($ty:ty),*) => {
+        $(
+            impl DebugView for $ty {
+                #[cfg(feature = "logging-base")]
+                fn debug_view(&self, _indent: usize) -> String {
+                    self.to_string()
+                }
 
-    #[cfg(not(feature = "logging-base"))]
-    fn debug_view(&self, _indent: usize) -> String {
-        "".to_string()
-    }
+                #[cfg(not(feature = "logging-base"))]
+                fn debug_view(&self, _indent: usize) -> String {
+                    "".to_string()
+                }
+            }
+        )*
+    };
 }
 
+impl_debug_view_for_display!(bool, i32, i64, u64, f64, char, String, Value);
+
 impl<T> DebugView for Op<T>
 where
     T: DebugView + CrdtNode,
@@ -191,15 +251,15 @@ impl<T> ListCrdt<T>
 where
     T: CrdtNode,
 {
-    pub fn log_ops(&self, highlight: Option<OpId>) {
+    pub fn log_ops(&self, _highlight: Option<OpId>) {
         #[cfg(feature = "logging-list")]
-        {
+        if tracing::enabled!(target: "bft_json_crdt::list", tracing::Level::TRACE) {
             let mut lines = Vec::<String>::new();
 
             // do in-order traversal
             let res: Vec<&Op<T>> = self.ops.iter().collect();
             if res.is_empty() {
-                println!("[empty]");
+                lines.push("[empty]".to_string());
             }
 
             // figure out parent-child hierarchies from origins
@@ -242,7 +302,7 @@ where
 
                 let cur_char = if is_last(op) { "╰─" } else { "├─" };
                 let prefixes = stack.iter().map(|s| s.1).collect::<Vec<_>>().join("");
-                let highlight_text = if highlight.is_some() && highlight.unwrap() == op.id {
+                let highlight_text = if _highlight == Some(op.id) {
                     if op.is_deleted {
                         "<- deleted".bold().red()
                     } else {
@@ -285,33 +345,35 @@ where
             // full string
             let flat = self.iter().map(|t| t.hash()).collect::<Vec<_>>().join("");
             lines.push(format!("Flattened result: {}", flat));
-            println!("{}", lines.join("\n"));
+            tracing::trace!(target: "bft_json_crdt::list", "\n{}", lines.join("\n"));
         }
     }
 
     pub fn log_apply(&self, op: &Op<T>) {
-        #[cfg(feature = "logging-list")]
-        {
-            if op.is_deleted {
-                println!(
-                    "{} Performing a delete of {}@{}",
-                    display_author(self.our_id),
-                    display_op_id(op),
-                    op.sequence_num(),
-                );
-                return;
-            }
+        let kind = if op.is_deleted { "delete" } else { "insert" };
+        debug!(
+            replica = %author_to_hex(self.our_id),
+            author = %author_to_hex(op.author),
+            op_id = %short_hex(&op.id),
+            seq = op.sequence_num(),
+            origin = %short_hex(&op.origin),
+            kind,
+            "integrating list op"
+        );
+    }
+}
 
-            if let Some(content) = op.content.as_ref() {
-                println!(
-                    "{} Performing an insert of {}@{}: '{}' after {}",
-                    display_author(self.our_id),
-                    display_op_id(op),
-                    op.sequence_num(),
-                    content.hash(),
-                    display_op_id(op)
-                );
-            }
+impl DebugView for Vec<u8> {
+    fn debug_view(&self, indent: usize) -> String {
+        Value::Bytes(self.clone()).debug_view(indent)
+    }
+}
+
+impl<T: DebugView> DebugView for Option<T> {
+    fn debug_view(&self, indent: usize) -> String {
+        match self {
+            Some(inner) => inner.debug_view(indent),
+            None => Value::Null.debug_view(indent),
         }
     }
 }
@@ -0,0 +1,397 @@
+use crate::{
+    json_crdt::{CrdtNode, Edit, Location, Value},
+    json_patch::{edit, read, to_pointer},
+    op::{Op, OpId, ROOT_ID},
+};
+
+/// Compute the ops that turn `current` into `target`. Registers that differ are set, and lists
+/// are diffed with a longest common subsequence so only the elements that actually changed are
+/// inserted or deleted. Elements that changed in place are diffed recursively where possible
+/// so concurrent edits to other parts of them are preserved. Strings are diffed per character
+/// when they are stored in a list of chars.
+///
+/// `current` is left untouched: apply the returned ops to it and send them to other replicas.
+/// Fails if `target` does not fit the shape of the document, e.g. if it is missing a field of a
+/// struct.
+pub fn diff_to_ops<T: CrdtNode>(
+    current: &T,
+    target: serde_json::Value,
+) -> Result<Vec<Op<Value>>, String> {
+    let mut staged = current.clone();
+    let mut ops = vec![];
+    // compared as our own values so numbers are equal when they have the same value
+    diff(&mut staged, &[], target.into(), &mut ops)?;
+    Ok(ops)
+}
+
+fn diff<T: CrdtNode>(
+    doc: &mut T,
+    tokens: &[String],
+    target: Value,
+    ops: &mut Vec<Op<Value>>,
+) -> Result<(), String> {
+    match doc.locate(tokens)? {
+        Location::Register { path, value, .. } => {
+            // bytes come in as the base64 strings they are shown as
+            if value != target && value.clone().into_json() != target.clone().into_json() {
+                edit(doc, &path, Edit::Set { value: target }, ops)?;
+            }
+            Ok(())
+        }
+        Location::Object { .. } => {
+            let Value::Object(mut target) = target else {
+                return Err(format!("expected an object at {}", to_pointer(tokens)));
+            };
+            let Value::Object(current) = read(doc, tokens)? else {
+                return Err(format!("expected an object at {}", to_pointer(tokens)));
+            };
+            let mut fields = current.into_keys().collect::<Vec<_>>();
+            fields.sort();
+            if let Some(extra) = target.keys().find(|k| !fields.contains(k)) {
+                return Err(format!("no field {extra:?} at {}", to_pointer(tokens)));
+            }
+            for field in fields {
+                let value = target
+                    .remove(&field)
+                    .ok_or_else(|| format!("missing field {field:?} at {}", to_pointer(tokens)))?;
+                let mut inner = tokens.to_vec();
+                inner.push(field);
+                diff(doc, &inner, value, ops)?;
+            }
+            Ok(())
+        }
+        Location::List { path, ids, chars } => {
+            let target = match target {
+                Value::Array(arr) => arr,
+                Value::String(s) if chars => {
+                    s.chars().map(|c| Value::String(c.to_string())).collect()
+                }
+                _ => return Err(format!("expected an array at {}", to_pointer(tokens))),
+            };
+            let current = match read(doc, tokens)? {
+                Value::Array(arr) => arr,
+                _ => return Err(format!("expected an array at {}", to_pointer(tokens))),
+            };
+            diff_list(doc, tokens, &path, &ids, current, target, ops)
+        }
+        Location::Primitive => {
+            if read(doc, tokens)? != target {
+                return Err(format!(
+                    "cannot change the primitive at {}",
+                    to_pointer(tokens)
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// What ends up at each position of a list
+enum Slot {
+    /// An existing element that stays
+    Kept(OpId),
+    /// A new element
+    New(Value),
+}
+
+fn diff_list<T: CrdtNode>(
+    doc: &mut T,
+    tokens: &[String],
+    path: &[crate::op::PathSegment],
+    ids: &[OpId],
+    current: Vec<Value>,
+    target: Vec<Value>,
+    ops: &mut Vec<Op<Value>>,
+) -> Result<(), String> {
+    let mut slots = vec![];
+    let mut deleted = vec![];
+    let mut changed = vec![];
+    let (mut i, mut j) = (0, 0);
+    let matches = lcs(&current, &target);
+    let end = (current.len(), target.len());
+    for (ci, tj) in matches.into_iter().chain([end]) {
+        // elements replaced within the same gap are edited in place if they are CRDTs themselves
+        let paired = (ci - i).min(tj - j);
+        for k in 0..paired {
+            let mut element = tokens.to_vec();
+            element.push((i + k).to_string());
+            if matches!(doc.locate(&element), Ok(Location::Primitive) | Err(_)) {
+                deleted.push(ids[i + k]);
+                slots.push(Slot::New(target[j + k].clone()));
+            } else {
+                changed.push((element, target[j + k].clone()));
+                slots.push(Slot::Kept(ids[i + k]));
+            }
+        }
+        deleted.extend(&ids[i + paired..ci]);
+        slots.extend(target[j + paired..tj].iter().cloned().map(Slot::New));
+        if (ci, tj) != end {
+            slots.push(Slot::Kept(ids[ci]));
+        }
+        (i, j) = (ci + 1, tj + 1);
+    }
+
+    // nested edits go first while the indices in `changed` are still valid
+    for (element, value) in changed {
+        diff(doc, &element, value, ops)?;
+    }
+    for id in deleted {
+        edit(doc, path, Edit::Delete { id }, ops)?;
+    }
+    let mut prev = ROOT_ID;
+    for slot in slots {
+        prev = match slot {
+            Slot::Kept(id) => id,
+            Slot::New(value) => {
+                let (op, _) = doc.edit(path, Edit::Insert { after: prev, value })?;
+                let id = op.id;
+                ops.push(op);
+                id
+            }
+        };
+    }
+    Ok(())
+}
+
+/// Pairs of indices of a longest common subsequence of `a` and `b`, in order
+fn lcs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    // common prefix and suffix are matched without searching
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut pairs = (0..prefix).map(|k| (k, k)).collect::<Vec<_>>();
+    hirschberg(a_mid, b_mid, (prefix, prefix), &mut pairs);
+    let a_suffix = a.len() - suffix;
+    let b_suffix = b.len() - suffix;
+    pairs.extend((0..suffix).map(|k| (a_suffix + k, b_suffix + k)));
+    pairs
+}
+
+/// Hirschberg's algorithm: split `a` in half and find where an LCS crosses the split from the
+/// LCS lengths of each half, which only need a single row, then recurse on both sides. Takes
+/// `O(n * m)` time but only `O(n + m)` memory. Pairs are pushed in order, shifted by `offset`
+fn hirschberg<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    offset: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    if a.is_empty() || b.is_empty() {
+        return;
+    }
+    if let [x] = a {
+        if let Some(j) = b.iter().position(|y| y == x) {
+            pairs.push((offset.0, offset.1 + j));
+        }
+        return;
+    }
+
+    let mid = a.len() / 2;
+    let front = lcs_lengths(a[..mid].iter(), b.iter());
+    let back = lcs_lengths(a[mid..].iter().rev(), b.iter().rev());
+    let split = (0..=b.len())
+        .max_by_key(|&j| front[j] + back[b.len() - j])
+        .unwrap_or(0);
+    hirschberg(&a[..mid], &b[..split], offset, pairs);
+    let rest = (offset.0 + mid, offset.1 + split);
+    hirschberg(&a[mid..], &b[split..], rest, pairs);
+}
+
+/// Length of the LCS of all of `a` with each prefix of `b`, by length of the prefix
+fn lcs_lengths<'a, T: PartialEq + 'a>(
+    a: impl Iterator<Item = &'a T>,
+    b: impl Iterator<Item = &'a T>,
+) -> Vec<usize> {
+    let b = b.collect::<Vec<_>>();
+    let mut row = vec![0; b.len() + 1];
+    for x in a {
+        // row[j] from before this element of `a`
+        let mut diagonal = 0;
+        for (j, y) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == *y {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{
+        diff::{diff_to_ops, lcs},
+        json_crdt::{add_crdt_fields, BaseCrdt, CrdtNode, Value},
+        keypair::make_keypair,
+        list_crdt::ListCrdt,
+        lww_crdt::LwwRegisterCrdt,
+    };
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Item {
+        name: LwwRegisterCrdt<String>,
+        count: LwwRegisterCrdt<f64>,
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Form {
+        title: ListCrdt<char>,
+        items: ListCrdt<Item>,
+    }
+
+    /// Lists of chars show up as arrays of single character strings
+    fn chars(s: &str) -> serde_json::Value {
+        s.chars().map(|c| c.to_string()).collect()
+    }
+
+    fn form() -> serde_json::Value {
+        json!({
+            "title": "hello",
+            "items": [
+                { "name": "apple", "count": 1 },
+                { "name": "pear", "count": 2 },
+            ],
+        })
+    }
+
+    #[test]
+    fn test_diff_is_minimal() {
+        let kp1 = make_keypair();
+        let mut base1 = BaseCrdt::<Form>::new(&kp1);
+        for op in diff_to_ops(&base1.doc, form()).unwrap() {
+            base1.doc.apply(op).unwrap();
+        }
+        // the counts are f64 registers, which still compare equal to the integers we gave
+        let mut expected = form();
+        expected["title"] = chars("hello");
+        assert_eq!(base1.doc.view(), Value::from(expected));
+
+        let target = json!({
+            "title": "help!",
+            "items": [
+                { "name": "apple", "count": 1 },
+                { "name": "plum", "count": 2 },
+                { "name": "fig", "count": 3 },
+            ],
+        });
+        let ops = diff_to_ops(&base1.doc, target.clone()).unwrap();
+        // 'l' and 'o' become 'p' and '!', pear is renamed in place and fig is added
+        assert_eq!(ops.len(), 6);
+        for op in ops {
+            base1.doc.apply(op).unwrap();
+        }
+        let mut expected = target;
+        expected["title"] = chars("help!");
+        assert_eq!(base1.doc.view(), Value::from(expected));
+        assert!(diff_to_ops(&base1.doc, base1.doc.view().into_json())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_lcs_is_longest() {
+        // quadratic table to check against
+        fn longest(a: &[u8], b: &[u8]) -> usize {
+            let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for i in 0..a.len() {
+                for j in 0..b.len() {
+                    lengths[i + 1][j + 1] = if a[i] == b[j] {
+                        lengths[i][j] + 1
+                    } else {
+                        lengths[i][j + 1].max(lengths[i + 1][j])
+                    };
+                }
+            }
+            lengths[a.len()][b.len()]
+        }
+
+        let mut seed = 7u32;
+        let mut next = move || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8 % 4
+        };
+        for round in 0..200 {
+            let a = (0..round % 23).map(|_| next()).collect::<Vec<_>>();
+            let b = (0..round % 19).map(|_| next()).collect::<Vec<_>>();
+            let pairs = lcs(&a, &b);
+            assert_eq!(pairs.len(), longest(&a, &b), "{a:?} {b:?}");
+            assert!(pairs.iter().all(|&(i, j)| a[i] == b[j]));
+            assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        }
+    }
+
+    #[test]
+    fn test_diff_preserves_concurrent_edits() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Form>::new(&kp1);
+        let mut base2 = BaseCrdt::<Form>::new(&kp2);
+        for op in diff_to_ops(&base1.doc, form()).unwrap() {
+            base1.doc.apply(op.clone()).unwrap();
+            base2.apply(base1.sign(op, &kp1)).unwrap();
+        }
+
+        // base2 bumps the count of the pear while base1 submits a form that renames it
+        let count = base2.doc.items[1].count.set(5.0);
+        let _count = base2.sign(count, &kp2);
+        let mut target = form();
+        target["items"][1]["name"] = json!("plum");
+        target["title"] = json!("hello world");
+        let ops = diff_to_ops(&base1.doc, target).unwrap();
+        for op in ops {
+            base1.doc.apply(op.clone()).unwrap();
+            base2.apply(base1.sign(op, &kp1)).unwrap();
+        }
+        base1.apply(_count).unwrap();
+
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert_eq!(
+            base1.doc.view().into_json(),
+            json!({
+                "title": chars("hello world"),
+                "items": [
+                    { "name": "apple", "count": 1.0 },
+                    { "name": "plum", "count": 5.0 },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_diff_rejects_wrong_shape() {
+        let kp1 = make_keypair();
+        let base1 = BaseCrdt::<Form>::new(&kp1);
+        assert!(diff_to_ops(&base1.doc, json!({ "title": "hi" })).is_err());
+        assert!(diff_to_ops(&base1.doc, json!({ "title": 1, "items": [] })).is_err());
+        assert!(diff_to_ops(&base1.doc, json!({ "title": "", "items": [], "x": 1 })).is_err());
+        assert!(diff_to_ops(&base1.doc, json!({ "title": "hi", "items": "ab" })).is_err());
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Tags {
+        tags: ListCrdt<String>,
+    }
+
+    #[test]
+    fn test_diff_splits_strings_only_for_chars() {
+        let kp1 = make_keypair();
+        let base1 = BaseCrdt::<Tags>::new(&kp1);
+        assert!(diff_to_ops(&base1.doc, json!({ "tags": "ab" })).is_err());
+        let ops = diff_to_ops(&base1.doc, json!({ "tags": ["ab"] })).unwrap();
+        assert_eq!(ops.len(), 1);
+    }
+}
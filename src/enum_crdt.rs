@@ -0,0 +1,553 @@
+use std::{collections::HashSet, mem};
+
+use crate::{
+    debug::debug_path_mismatch,
+    json_crdt::{
+        ApplyError, ApplyResult, Blame, Change, CrdtNode, Edit, IntoCrdtNode, Location, OpState,
+        Value,
+    },
+    keypair::AuthorId,
+    lww_crdt::{Lamport, LwwOrder, LwwRegisterCrdt},
+    op::{ensure_subpath, join_path, print_path, Op, OpId, PathSegment, SequenceNumber},
+};
+
+/// Path segment of the register that holds the current variant. Variants are named after Rust
+/// identifiers so this can never clash with one of them
+pub const TAG_FIELD: &str = "#tag";
+
+/// Bookkeeping that `#[add_crdt_fields]` adds to every variant of an enum deriving
+/// [`CrdtNode`]. The variant is chosen by a last-writer-wins register. Variants we switched away
+/// from are parked here so that concurrent edits to them are kept and show up again if the
+/// enum switches back.
+#[derive(Clone)]
+pub struct EnumState<T> {
+    pub path: Vec<PathSegment>,
+    pub id: AuthorId,
+    /// Name of the current variant
+    tag: LwwRegisterCrdt<String>,
+    /// Every other variant that has been built, in the order they were first built
+    parked: Vec<T>,
+}
+
+impl<T: CrdtEnum> EnumState<T> {
+    pub fn new(id: AuthorId, path: Vec<PathSegment>) -> Self {
+        let tag = LwwRegisterCrdt::new(id, join_path(path.clone(), tag_segment()));
+        Self {
+            path,
+            id,
+            tag,
+            parked: vec![],
+        }
+    }
+
+    /// Path of the fields of the given variant
+    pub fn variant_path(&self, variant: &str) -> Vec<PathSegment> {
+        join_path(self.path.clone(), PathSegment::Field(variant.to_string()))
+    }
+
+    /// The variant the tag was last set to, if it has been set
+    fn chosen(&self) -> Option<String> {
+        match CrdtNode::view(&self.tag) {
+            Value::String(variant) => Some(variant),
+            _ => None,
+        }
+    }
+
+    /// Find the variant that ops under `variant` should go to, building it if we have not yet
+    fn parked_mut(&mut self, variant: &str) -> &mut T {
+        match self.parked.iter().position(|p| p.variant() == variant) {
+            Some(idx) => &mut self.parked[idx],
+            None => {
+                let state = EnumState::new(self.id, self.path.clone());
+                self.parked.push(build(variant, state));
+                self.parked.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Implemented by `#[derive(CrdtNode)]` for enums. Apart from [`CrdtEnum::variant`] and
+/// [`CrdtEnum::set_variant`] these are plumbing for the generated [`CrdtNode`] impl
+pub trait CrdtEnum: CrdtNode {
+    /// Names of all the variants, the first one is used until a variant is chosen
+    const VARIANTS: &'static [&'static str];
+    /// Key holding the variant for internally tagged enums (`#[crdt(tag = "...")]`), `None` for
+    /// externally tagged ones
+    const TAG: Option<&'static str>;
+
+    /// Name of the current variant
+    fn variant(&self) -> &'static str;
+
+    /// Switch to another variant. Its fields pick up where they were left off if the enum has
+    /// been that variant before, otherwise they start out empty. Returns the op to send to other
+    /// replicas
+    fn set_variant(&mut self, variant: &str) -> Result<Op<Value>, String> {
+        let path = join_path(self.state().path.clone(), tag_segment());
+        let value = Value::String(variant.to_string());
+        self.edit(&path, Edit::Set { value }).map(|(op, _)| op)
+    }
+
+    #[doc(hidden)]
+    fn state(&self) -> &EnumState<Self>;
+    #[doc(hidden)]
+    fn state_mut(&mut self) -> &mut EnumState<Self>;
+    /// Build `variant` around `state`, with new fields or ones created from the given object
+    #[doc(hidden)]
+    fn make_variant(
+        variant: &str,
+        state: EnumState<Self>,
+        fields: Option<Value>,
+    ) -> Result<Self, String>;
+    #[doc(hidden)]
+    fn apply_field(
+        &mut self,
+        field: &str,
+        op: Op<Value>,
+        changes: &mut Vec<Change>,
+    ) -> Option<ApplyResult>;
+    #[doc(hidden)]
+    fn edit_field(
+        &mut self,
+        field: &str,
+        path: &[PathSegment],
+        edit: Edit,
+    ) -> Option<Result<(Op<Value>, Edit), String>>;
+    #[doc(hidden)]
+    fn locate_field(&self, field: &str, tokens: &[String]) -> Option<Result<Location, String>>;
+    #[doc(hidden)]
+    fn fields(&self) -> Vec<(&'static str, Value)>;
+    #[doc(hidden)]
+    fn fields_at(&self, ops: &HashSet<OpId>) -> Vec<(&'static str, Value)>;
+    #[doc(hidden)]
+    fn fields_blame(&self) -> Vec<(&'static str, Blame)>;
+    #[doc(hidden)]
+    fn max_seq_field(
+        &self,
+        field: &str,
+        op: &Op<Value>,
+        justified: SequenceNumber,
+    ) -> Option<Option<SequenceNumber>>;
+}
+
+fn tag_segment() -> PathSegment {
+    PathSegment::Field(TAG_FIELD.to_string())
+}
+
+/// Build a variant with new fields. Only fails for variants that do not exist
+fn build<T: CrdtEnum>(variant: &str, state: EnumState<T>) -> T {
+    T::make_variant(variant, state, None).expect("fields of a new variant can always be created")
+}
+
+pub fn new<T: CrdtEnum>(id: AuthorId, path: Vec<PathSegment>) -> T {
+    build(T::VARIANTS[0], EnumState::new(id, path))
+}
+
+pub fn node_from<T: CrdtEnum>(
+    value: Value,
+    id: AuthorId,
+    path: Vec<PathSegment>,
+) -> Result<T, String> {
+    let (variant, fields) = match (T::TAG, value) {
+        (None, Value::String(variant)) => (variant, Value::Object(Default::default())),
+        (None, Value::Object(obj)) if obj.len() == 1 => obj.into_iter().next().unwrap(),
+        (Some(tag), Value::Object(mut obj)) => match obj.remove(tag) {
+            Some(Value::String(variant)) => (variant, Value::Object(obj)),
+            _ => return Err(format!("missing variant tag {tag:?}")),
+        },
+        (_, value) => return Err(format!("failed to convert {value:?} -> enum")),
+    };
+    if !T::VARIANTS.contains(&&variant[..]) {
+        return Err(format!("unknown variant {variant:?}"));
+    }
+
+    let mut state = EnumState::new(id, path);
+    state.tag = Value::String(variant.clone()).into_node(id, state.tag.path.clone())?;
+    T::make_variant(&variant, state, Some(fields))
+}
+
+pub fn apply<T: CrdtEnum>(node: &mut T, op: Op<Value>, changes: &mut Vec<Change>) -> ApplyResult {
+    let state = node.state();
+    if !ensure_subpath(&state.path, &op.path) {
+        debug_path_mismatch(state.path.to_owned(), op.path.clone());
+        let reason = format!("op is not under /{}", print_path(state.path.clone()));
+        return Err(ApplyError::new(OpState::ErrPathMismatch, &op, reason));
+    }
+    let idx = state.path.len();
+    let segment = match op.path.get(idx) {
+        None => {
+            let reason = "enums cannot be modified directly, only their tag and fields";
+            return Err(ApplyError::new(OpState::ErrApplyOnStruct, &op, reason));
+        }
+        Some(PathSegment::Field(segment)) => segment.clone(),
+        Some(PathSegment::Index(_)) => String::new(),
+    };
+
+    if segment == TAG_FIELD {
+        // only names of variants are accepted so every replica agrees on what the tag means
+        match &op.content {
+            Some(Value::String(variant)) if T::VARIANTS.contains(&&variant[..]) => {}
+            content => {
+                let reason = format!("{content:?} is not a variant");
+                return Err(ApplyError::new(OpState::ErrMismatchedType, &op, reason));
+            }
+        }
+        let outcome = node.state_mut().tag.apply_observed(op, changes)?;
+        switch(node);
+        return Ok(outcome);
+    }
+    if !T::VARIANTS.contains(&&segment[..]) {
+        let reason = format!("no variant at /{}", print_path(op.path[..=idx].to_vec()));
+        return Err(ApplyError::new(OpState::ErrPathMismatch, &op, reason));
+    }
+
+    let Some(PathSegment::Field(field)) = op.path.get(idx + 1).cloned() else {
+        let reason = "variants cannot be modified directly, only their fields";
+        return Err(ApplyError::new(OpState::ErrApplyOnStruct, &op, reason));
+    };
+    let result = if segment == node.variant() {
+        node.apply_field(&field, op.clone(), changes)
+    } else {
+        // edits to other variants are kept in case we switch back to them later
+        node.state_mut()
+            .parked_mut(&segment)
+            .apply_field(&field, op.clone(), &mut vec![])
+    };
+    result.unwrap_or_else(|| {
+        let reason = format!("no field at /{}", print_path(op.path[..=idx + 1].to_vec()));
+        Err(ApplyError::new(OpState::ErrPathMismatch, &op, reason))
+    })
+}
+
+/// Swap in the variant the tag points at if it is not the current one
+fn switch<T: CrdtEnum>(node: &mut T) {
+    let Some(wanted) = node.state().chosen() else {
+        return;
+    };
+    if wanted == node.variant() {
+        return;
+    }
+
+    let (id, path) = (node.state().id, node.state().path.clone());
+    let mut state = mem::replace(node.state_mut(), EnumState::new(id, path.clone()));
+    let next = match state.parked.iter().position(|p| p.variant() == wanted) {
+        Some(idx) => state.parked.remove(idx),
+        None => build(&wanted, EnumState::new(id, path)),
+    };
+    let previous = mem::replace(node, next);
+    state.parked.push(previous);
+    *node.state_mut() = state;
+}
+
+pub fn edit<T: CrdtEnum>(
+    node: &mut T,
+    path: &[PathSegment],
+    edit: Edit,
+) -> Result<(Op<Value>, Edit), String> {
+    let state = node.state();
+    if path == state.tag.path {
+        if let Edit::Set { value } | Edit::Restore { value, .. } = &edit {
+            if !matches!(value, Value::String(variant) if T::VARIANTS.contains(&&variant[..])) {
+                return Err(format!("{value:?} is not a variant"));
+            }
+        }
+        let result = node.state_mut().tag.edit(path, edit)?;
+        switch(node);
+        return Ok(result);
+    }
+
+    let variant_path = state.variant_path(node.variant());
+    if path.len() > variant_path.len() && path.starts_with(&variant_path) {
+        if let PathSegment::Field(field) = &path[variant_path.len()] {
+            if let Some(result) = node.edit_field(field, path, edit) {
+                return result;
+            }
+        }
+    }
+    Err(format!(
+        "{} has no field at /{}",
+        node.variant(),
+        print_path(path.to_vec())
+    ))
+}
+
+pub fn locate<T: CrdtEnum>(node: &T, tokens: &[String]) -> Result<Location, String> {
+    let state = node.state();
+    let variant = node.variant();
+    let tag = |inner: &[String]| Location::Register {
+        path: state.tag.path.clone(),
+        value: Value::String(variant.to_string()),
+        inner: inner.to_vec(),
+    };
+    let field = |token: &String, rest: &[String]| {
+        node.locate_field(token, rest)
+            .unwrap_or_else(|| Err(format!("{variant} has no field {token:?}")))
+    };
+
+    match (T::TAG, tokens.split_first()) {
+        // a unit variant is just its name, so replacing it switches variants
+        (None, None) if node.fields().is_empty() => Ok(tag(&[])),
+        (_, None) => Ok(Location::Object {
+            path: state.path.clone(),
+        }),
+        (Some(key), Some((token, rest))) if token == key => Ok(tag(rest)),
+        (Some(_), Some((token, rest))) => field(token, rest),
+        (None, Some((token, rest))) if token == variant => match rest.split_first() {
+            None => Ok(Location::Object {
+                path: state.variant_path(variant),
+            }),
+            Some((token, rest)) => field(token, rest),
+        },
+        (None, Some((token, _))) => Err(format!("{variant} is the current variant, not {token:?}")),
+    }
+}
+
+/// Lay out the fields of a variant following serde's conventions for tagged enums
+fn tagged<T: CrdtEnum, V>(
+    variant: &str,
+    fields: Vec<(&'static str, V)>,
+    tag: V,
+    object: impl Fn(Vec<(String, V)>) -> V,
+) -> V {
+    let fields = fields.into_iter().map(|(k, v)| (k.to_string(), v));
+    match T::TAG {
+        None if fields.len() == 0 => tag,
+        None => {
+            let inner = object(fields.collect());
+            object(vec![(variant.to_string(), inner)])
+        }
+        Some(key) => object(fields.chain([(key.to_string(), tag)]).collect()),
+    }
+}
+
+pub fn view<T: CrdtEnum>(node: &T) -> Value {
+    let variant = node.variant();
+    let tag = Value::String(variant.to_string());
+    tagged::<T, _>(variant, node.fields(), tag, |f| {
+        Value::Object(f.into_iter().collect())
+    })
+}
+
+pub fn view_at<T: CrdtEnum>(node: &T, ops: &HashSet<OpId>) -> Value {
+    let state = node.state();
+    let variant = match state.tag.view_at(ops) {
+        Value::String(variant) => variant,
+        _ => T::VARIANTS[0].to_string(),
+    };
+    let fields = if variant == node.variant() {
+        node.fields_at(ops)
+    } else {
+        match state.parked.iter().find(|p| p.variant() == variant) {
+            Some(parked) => parked.fields_at(ops),
+            None => {
+                build(&variant, EnumState::<T>::new(state.id, state.path.clone())).fields_at(ops)
+            }
+        }
+    };
+    let tag = Value::String(variant.clone());
+    tagged::<T, _>(&variant, fields, tag, |f| {
+        Value::Object(f.into_iter().collect())
+    })
+}
+
+pub fn blame<T: CrdtEnum>(node: &T) -> Blame {
+    let variant = node.variant();
+    let tag = Blame::Register {
+        writer: node.state().tag.last_writer(),
+        value: Value::String(variant.to_string()),
+    };
+    tagged::<T, _>(variant, node.fields_blame(), tag, |f| {
+        Blame::Object(f.into_iter().collect())
+    })
+}
+
+/// Ops for the tag are bounded by the tag register, ops for a field by the field, whether its
+/// variant is the current one or parked
+pub fn max_seq<T: CrdtEnum>(
+    node: &T,
+    op: &Op<Value>,
+    justified: SequenceNumber,
+) -> Option<SequenceNumber> {
+    let state = node.state();
+    let idx = state.path.len();
+    let bound = match (op.path.get(idx), op.path.get(idx + 1)) {
+        (Some(PathSegment::Field(segment)), _) if segment == TAG_FIELD => {
+            Some(state.tag.max_seq(op, justified))
+        }
+        (Some(PathSegment::Field(segment)), Some(PathSegment::Field(field))) => {
+            if segment == node.variant() {
+                node.max_seq_field(field, op, justified)
+            } else {
+                state
+                    .parked
+                    .iter()
+                    .find(|parked| parked.variant() == segment)
+                    .and_then(|parked| parked.max_seq_field(field, op, justified))
+            }
+        }
+        _ => None,
+    };
+    bound.unwrap_or_else(|| Lamport::max_seq(justified))
+}
+
+/// Signature of the function `#[crdt(default)]` generates for a field of a variant
+pub type DefaultField<T> = fn(AuthorId, Vec<PathSegment>) -> Result<T, String>;
+
+/// Used for the fields of variants when they are created from a JSON value. `default` is used
+/// if the field is missing from it
+pub fn field_from<T: CrdtNode>(
+    fields: &mut Option<Value>,
+    name: &str,
+    id: AuthorId,
+    path: Vec<PathSegment>,
+    default: Option<DefaultField<T>>,
+) -> Result<T, String> {
+    match fields {
+        None => Ok(T::new(id, path)),
+        Some(Value::Object(obj)) => match (obj.remove(name), default) {
+            (Some(value), _) => value.into_node(id, path),
+            (None, Some(default)) => default(id, path),
+            (None, None) => Err(format!("missing field {name:?}")),
+        },
+        Some(value) => Err(format!("failed to convert {value:?} -> variant")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{
+        enum_crdt::CrdtEnum,
+        json_crdt::{add_crdt_fields, BaseCrdt, CrdtNode, OpState},
+        keypair::make_keypair,
+        list_crdt::ListCrdt,
+        lww_crdt::LwwRegisterCrdt,
+        op::ROOT_ID,
+    };
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    enum Item {
+        Weapon { damage: LwwRegisterCrdt<f64> },
+        Potion { heal: LwwRegisterCrdt<f64> },
+        Junk,
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    #[crdt(tag = "kind")]
+    enum Slot {
+        Empty,
+        Held {
+            #[crdt(rename = "label")]
+            name: LwwRegisterCrdt<String>,
+        },
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Inventory {
+        items: ListCrdt<Item>,
+        hand: Slot,
+    }
+
+    #[test]
+    fn test_enum_externally_tagged() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Inventory>::new(&kp1);
+        let mut base2 = BaseCrdt::<Inventory>::new(&kp2);
+
+        let sword = base1
+            .doc
+            .items
+            .insert(ROOT_ID, json!({ "Weapon": { "damage": 5 } }));
+        let _sword = base1.sign(sword, &kp1);
+        base2.apply(_sword).unwrap();
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "items": [{ "Weapon": { "damage": 5.0 } }], "hand": { "kind": "Empty" } })
+        );
+
+        // base1 turns the weapon into a potion while base2 sharpens it
+        let potion = base1.doc.items[0].set_variant("Potion").unwrap();
+        let _potion = base1.sign(potion, &kp1);
+        let Item::Weapon { damage, .. } = &mut base2.doc.items[0] else {
+            panic!("expected a weapon");
+        };
+        let sharpen = damage.set(8.0);
+        let _sharpen = base2.sign(sharpen, &kp2);
+        base1.apply(_sharpen).unwrap();
+        base2.apply(_potion).unwrap();
+        assert_eq!(base1.doc.items[0].variant(), "Potion");
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert_eq!(
+            base1.doc.items.view()[0].view().into_json(),
+            json!({ "Potion": { "heal": null } })
+        );
+
+        // unit variants are just their name
+        let junk = base1.doc.items.insert(ROOT_ID, json!("Junk"));
+        let _junk = base1.sign(junk, &kp1);
+        base2.apply(_junk).unwrap();
+        assert_eq!(base2.doc.items[0].view().into_json(), json!("Junk"));
+
+        // switching back brings back the weapon, including the concurrent edit
+        let back = base2.doc.items[1].set_variant("Weapon").unwrap();
+        let _back = base2.sign(back, &kp2);
+        base1.apply(_back).unwrap();
+        assert_eq!(
+            base1.doc.items.view()[1].view().into_json(),
+            json!({ "Weapon": { "damage": 8.0 } })
+        );
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+    }
+
+    #[test]
+    fn test_enum_internally_tagged() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Inventory>::new(&kp1);
+        let mut base2 = BaseCrdt::<Inventory>::new(&kp2);
+
+        let op = base1.doc.hand.set_variant("Held").unwrap();
+        let _held = base1.sign(op, &kp1);
+        let Slot::Held { name, .. } = &mut base1.doc.hand else {
+            panic!("expected the hand to hold something");
+        };
+        let op = name.set("torch".to_string());
+        let _torch = base1.sign(op, &kp1);
+        base2.apply(_held).unwrap();
+        let held = base2.version();
+        base2.apply(_torch).unwrap();
+        assert_eq!(
+            base2.doc.hand.view().into_json(),
+            json!({ "kind": "Held", "label": "torch" })
+        );
+        assert_eq!(
+            base2.view_at(&held).unwrap().into_json()["hand"],
+            json!({ "kind": "Held", "label": null })
+        );
+
+        let op = base2.doc.hand.set_variant("Empty").unwrap();
+        let _empty = base2.sign(op, &kp2);
+        base1.apply(_empty).unwrap();
+        assert_eq!(
+            base1.doc.hand.view().into_json(),
+            json!({ "kind": "Empty" })
+        );
+        assert_eq!(
+            base1.view_at(&held).unwrap().into_json(),
+            base2.view_at(&held).unwrap().into_json()
+        );
+
+        // the tag only ever holds names of variants
+        assert!(base1.doc.hand.set_variant("Thrown").is_err());
+        let mut bogus = base2.doc.hand.clone();
+        let op = bogus.state_mut().tag.set("Thrown".to_string());
+        assert_eq!(
+            base1.doc.hand.apply(op).unwrap_err().kind,
+            OpState::ErrMismatchedType
+        );
+    }
+}
@@ -1,15 +1,21 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fmt::Display,
+    cmp::max,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    fmt::{Debug, Display},
 };
 
 use crate::{
-    debug::{debug_op_on_primitive, DebugView},
+    debug::{debug_op_on_primitive, debug_path_mismatch, DebugView},
     keypair::{sha256, sign, AuthorId, SignedDigest},
     list_crdt::ListCrdt,
-    lww_crdt::LwwRegisterCrdt,
-    op::{print_hex, print_path, Hashable, Op, OpId, PathSegment},
+    lww_crdt::{Lamport, LwwOrder, LwwRegisterCrdt},
+    op::{
+        ensure_subpath, print_hex, print_path, Hashable, Op, OpId, PathSegment, SequenceNumber,
+        ROOT_ID,
+    },
+    transaction::{SignedTransaction, Transaction},
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
 pub use bft_crdt_derive::*;
 use fastcrypto::{
     ed25519::{Ed25519KeyPair, Ed25519PublicKey, Ed25519Signature},
@@ -18,20 +24,308 @@ use fastcrypto::{
 };
 
 /// Anything that can be nested in a JSON CRDT
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be nested in a CRDT",
+    label = "`{Self}` does not implement `CrdtNode`",
+    note = "wrap values in `LwwRegisterCrdt<_>`, use `ListCrdt<_>` for lists, or add `#[crdt]` to your own types"
+)]
 pub trait CrdtNode: CrdtNodeFromValue + Hashable + Clone {
     /// Create a new CRDT of this type
     fn new(id: AuthorId, path: Vec<PathSegment>) -> Self;
     /// Apply an operation to this CRDT, forwarding if necessary
-    fn apply(&mut self, op: Op<Value>) -> OpState;
+    fn apply(&mut self, op: Op<Value>) -> ApplyResult {
+        self.apply_observed(op, &mut vec![])
+    }
+    /// Same as [`CrdtNode::apply`] but records every visible [`Change`] the op caused
+    fn apply_observed(&mut self, op: Op<Value>, changes: &mut Vec<Change>) -> ApplyResult;
     /// Get a JSON representation of the value in this node
     fn view(&self) -> Value;
+    /// Make a local [`Edit`] to the node at `path`, forwarding if necessary. Returns the op to
+    /// send to other replicas along with the edit that would revert it
+    fn edit(&mut self, path: &[PathSegment], edit: Edit) -> Result<(Op<Value>, Edit), String> {
+        let _ = edit;
+        Err(format!("nothing to edit at /{}", print_path(path.to_vec())))
+    }
+    /// Get a JSON representation of this node as it was when only the given ops had been applied.
+    /// Primitives have no history of their own
+    fn view_at(&self, ops: &HashSet<OpId>) -> Value {
+        let _ = ops;
+        self.view()
+    }
+    /// Find the node that the reference tokens of a JSON pointer lead to, relative to this node
+    fn locate(&self, tokens: &[String]) -> Result<Location, String> {
+        match tokens.first() {
+            None => Ok(Location::Primitive),
+            Some(token) => Err(format!("cannot index into a primitive with {token:?}")),
+        }
+    }
+    /// Get the view of this node along with who wrote each part of it
+    fn blame(&self) -> Blame {
+        Blame::Value(self.view())
+    }
+    /// The highest sequence number `op` may carry when the highest one in the causal past of its
+    /// message is `justified`, or `None` if it isn't bounded by its causal past, see
+    /// [`LwwOrder::max_seq`]. Containers ask the node the op is for, anything else may count one
+    /// past its causal past
+    fn max_seq(&self, op: &Op<Value>, justified: SequenceNumber) -> Option<SequenceNumber> {
+        let _ = op;
+        Lamport::max_seq(justified)
+    }
+    /// Check that `ops` can all be applied to this node in order, without changing it. Returns
+    /// the ID of an element one of them has to wait for, if any. By default the ops are tried on
+    /// a copy of the node, containers only look at the parts of themselves the ops are for
+    fn check_all(&self, ops: &[Op<Value>]) -> Result<Option<OpId>, ApplyError> {
+        let mut staged = self.clone();
+        for op in ops {
+            if staged.apply_observed(op.clone(), &mut vec![])? == ApplyOutcome::Queued {
+                return Ok(Some(op.origin));
+            }
+        }
+        Ok(None)
+    }
 }
 
-/// Enum representing possible outcomes of applying an operation to a CRDT
-#[derive(Debug, PartialEq)]
-pub enum OpState {
+/// Used by the derive to report fields that are not CRDTs at the field itself
+#[doc(hidden)]
+pub fn assert_crdt_node<T: CrdtNode>() {}
+
+/// Used by the derive to find which of the `fields` of the struct `name` at `path` an op is for
+#[doc(hidden)]
+pub fn field_of(
+    path: &Vec<PathSegment>,
+    op: &Op<Value>,
+    name: &str,
+    fields: &[&str],
+) -> Result<usize, ApplyError> {
+    if !ensure_subpath(path, &op.path) {
+        debug_path_mismatch(path.to_owned(), op.path.clone());
+        let reason = format!("op is not under /{}", print_path(path.clone()));
+        return Err(ApplyError::new(OpState::ErrPathMismatch, op, reason));
+    }
+    if path.len() == op.path.len() {
+        let reason = format!("{name} is a struct, only its fields can be modified");
+        return Err(ApplyError::new(OpState::ErrApplyOnStruct, op, reason));
+    }
+
+    let idx = path.len();
+    if let PathSegment::Field(key) = &op.path[idx] {
+        if let Some(field) = fields.iter().position(|field| field == key) {
+            return Ok(field);
+        }
+    }
+    let reason = format!(
+        "{name} has no field at /{}",
+        print_path(op.path[..=idx].to_vec())
+    );
+    Err(ApplyError::new(OpState::ErrPathMismatch, op, reason))
+}
+
+/// Where a struct made with [`crdt`] sits in the document and which author it belongs to
+#[derive(Clone, Debug)]
+pub struct NodeMeta {
+    pub path: Vec<PathSegment>,
+    pub id: AuthorId,
+}
+
+/// The op that wrote a list element or the current value of a register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attribution {
+    pub id: OpId,
+    pub author: AuthorId,
+    pub seq: SequenceNumber,
+}
+
+impl<T: CrdtNode> From<&Op<T>> for Attribution {
+    fn from(op: &Op<T>) -> Self {
+        Self {
+            id: op.id,
+            author: op.author(),
+            seq: op.sequence_num(),
+        }
+    }
+}
+
+/// A view of a node where every list element and register is annotated with the op that
+/// wrote it, see [`CrdtNode::blame`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Blame {
+    Object(BTreeMap<String, Blame>),
+    List(Vec<(Attribution, Blame)>),
+    /// A register is written as a whole so its value has a single writer, or none if it was
+    /// never set
+    Register {
+        writer: Option<Attribution>,
+        value: Value,
+    },
+    /// A primitive that is not wrapped in a CRDT of its own, its writer is the list element
+    /// or register it is in
+    Value(Value),
+}
+
+impl Blame {
+    /// The view this attribution tree was made from
+    pub fn into_value(self) -> Value {
+        match self {
+            Blame::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, v.into_value()))
+                    .collect(),
+            ),
+            Blame::List(elements) => {
+                Value::Array(elements.into_iter().map(|(_, v)| v.into_value()).collect())
+            }
+            Blame::Register { value, .. } | Blame::Value(value) => value,
+        }
+    }
+
+    /// Visit every attribution in the tree
+    fn attributions_mut(&mut self, f: &mut impl FnMut(&mut Attribution)) {
+        match self {
+            Blame::Object(fields) => fields.values_mut().for_each(|v| v.attributions_mut(f)),
+            Blame::List(elements) => {
+                for (attribution, element) in elements {
+                    f(attribution);
+                    element.attributions_mut(f);
+                }
+            }
+            Blame::Register { writer, .. } => writer.iter_mut().for_each(f),
+            Blame::Value(_) => {}
+        }
+    }
+}
+
+/// The node a JSON pointer leads to, see [`CrdtNode::locate`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    /// A struct, its fields can be located further
+    Object { path: Vec<PathSegment> },
+    /// A list along with the ids of its visible elements in order. `chars` is set for lists of
+    /// chars, which a string can stand in for
+    List {
+        path: Vec<PathSegment>,
+        ids: Vec<OpId>,
+        chars: bool,
+    },
+    /// A register along with its current value. `inner` holds the tokens that point into the
+    /// value, which has to be replaced as a whole to modify it
+    Register {
+        path: Vec<PathSegment>,
+        value: Value,
+        inner: Vec<String>,
+    },
+    /// A primitive that is not wrapped in a CRDT of its own, e.g. an element of a list of chars
+    Primitive,
+}
+
+/// A local edit to a list or register that can be routed to it by path with [`CrdtNode::edit`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    /// Insert `value` into a list after the element with id `after`
+    Insert { after: OpId, value: Value },
+    /// Delete the element with id `id` from a list
+    Delete { id: OpId },
+    /// Set the value of a register
+    Set { value: Value },
+    /// Set the value of a register back to `value`, unless someone has written to it since the
+    /// set with id `over`, which is the write being undone
+    Restore { value: Value, over: OpId },
+}
+
+/// The outcome of applying an operation to a CRDT
+pub type ApplyResult = Result<ApplyOutcome, ApplyError>;
+
+/// Enum representing the ways an operation can be accepted by a CRDT
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApplyOutcome {
     /// Operation applied successfully
-    Ok,
+    Applied,
+    /// We have not received all of the causal dependencies of this operation, or enough to
+    /// justify its sequence number. It has been queued up and will be executed once we have
+    Queued,
+}
+
+/// A visible change to the document caused by applying an op. Paths point at the list or
+/// register that changed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A new element is now visible at `index` of the list at `path`
+    ListInsert {
+        path: Vec<PathSegment>,
+        index: usize,
+        value: Value,
+    },
+    /// The element that was visible at `index` of the list at `path` got deleted
+    ListDelete {
+        path: Vec<PathSegment>,
+        index: usize,
+    },
+    /// The register at `path` changed from `old` to `new`
+    RegisterSet {
+        path: Vec<PathSegment>,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl Change {
+    /// Path of the list or register that changed
+    pub fn path(&self) -> &[PathSegment] {
+        match self {
+            Change::ListInsert { path, .. }
+            | Change::ListDelete { path, .. }
+            | Change::RegisterSet { path, .. } => path,
+        }
+    }
+}
+
+/// An operation that was rejected, along with enough context to figure out who sent it and why
+/// it was rejected
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApplyError {
+    /// What kind of failure this was
+    pub kind: OpState,
+    /// ID of the offending op
+    pub op_id: OpId,
+    /// Author of the offending op
+    pub author: AuthorId,
+    /// Path the offending op was targeting
+    pub path: Vec<PathSegment>,
+    /// Human readable explanation of the failure
+    pub reason: String,
+}
+
+impl ApplyError {
+    pub fn new<T: CrdtNode>(kind: OpState, op: &Op<T>, reason: impl Into<String>) -> Self {
+        Self {
+            kind,
+            op_id: op.id,
+            author: op.author,
+            path: op.path.to_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for ApplyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} for op {} from {} at /{}: {}",
+            self.kind,
+            &print_hex(&self.op_id)[..6],
+            &print_hex(&self.author)[..8],
+            print_path(self.path.clone()),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for ApplyError {}
+
+/// Enum representing the reasons an operation can be rejected by a CRDT
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpState {
     /// Tried to apply an operation to a non-CRDT primative (i.e. f64, bool, etc.)
     /// If you would like a mutable primitive, wrap it in a [`LWWRegisterCRDT`]
     ErrApplyOnPrimitive,
@@ -51,11 +345,22 @@ pub enum OpState {
     /// Tried to apply an operation to a non-existent path. The author may have forgotten to attach
     /// a causal dependency
     ErrPathMismatch,
+    /// The [`ChainLink`] of the message does not point at the tip of its author's history or does
+    /// not declare its predecessor as a causal dependency
+    ErrBrokenChain,
+    /// The author already signed a different message at this position in their history.
+    /// This is an equivocation attempt: the author is showing different histories to different peers
+    ErrForkedHistory,
     /// Trying to modify/delete the sentinel (zero-th) node element that is used for book-keeping
     ErrListApplyToEmpty,
-    /// We have not received all of the causal dependencies of this operation. It has been queued
-    /// up and will be executed when its causal dependencies have been delivered
-    MissingCausalDependencies,
+    /// The sequence number of an op jumps further past the causal dependencies of its message
+    /// than the node it is for allows, see [`CrdtNode::max_seq`]. Without this check an op with
+    /// a huge sequence number would win every register and sort ahead of every sibling in lists
+    /// from then on
+    ErrSequenceJump,
+    /// The hybrid logical clock timestamp of a register set is further ahead of our clock than
+    /// the register allows, see [`crate::lww_crdt::Hybrid`]
+    ErrClockDrift,
 }
 
 /// The following types can be used as a 'terminal' type in CRDTs
@@ -63,9 +368,11 @@ pub trait MarkPrimitive: Into<Value> + Default {}
 impl MarkPrimitive for bool {}
 impl MarkPrimitive for i32 {}
 impl MarkPrimitive for i64 {}
+impl MarkPrimitive for u64 {}
 impl MarkPrimitive for f64 {}
 impl MarkPrimitive for char {}
 impl MarkPrimitive for String {}
+impl MarkPrimitive for Vec<u8> {}
 impl MarkPrimitive for Value {}
 
 /// Implement CrdtNode for non-CRDTs
@@ -74,8 +381,12 @@ impl<T> CrdtNode for T
 where
     T: CrdtNodeFromValue + MarkPrimitive + Hashable + Clone,
 {
-    fn apply(&mut self, _op: Op<Value>) -> OpState {
-        OpState::ErrApplyOnPrimitive
+    fn apply_observed(&mut self, op: Op<Value>, _changes: &mut Vec<Change>) -> ApplyResult {
+        Err(ApplyError::new(
+            OpState::ErrApplyOnPrimitive,
+            &op,
+            "primitives cannot be modified, wrap them in a LwwRegisterCrdt",
+        ))
     }
 
     fn view(&self) -> Value {
@@ -88,6 +399,79 @@ where
     }
 }
 
+/// A node that may be null. It is null or not from the moment it is created: to create and clear
+/// a nested CRDT, put it in a [`LwwRegisterCrdt`] and [clear](LwwRegisterCrdt::clear) it, or
+/// put it in a [`LwwRegisterCrdt<Option<T>>`] and set it to null like any other value.
+/// Ops for a node that is null are rejected
+impl<T> CrdtNode for Option<T>
+where
+    T: CrdtNode + Debug,
+{
+    fn apply_observed(&mut self, op: Op<Value>, changes: &mut Vec<Change>) -> ApplyResult {
+        match self {
+            Some(node) => node.apply_observed(op, changes),
+            None => Err(ApplyError::new(
+                OpState::ErrPathMismatch,
+                &op,
+                "the value the op is for is null",
+            )),
+        }
+    }
+
+    fn view(&self) -> Value {
+        self.as_ref().map_or(Value::Null, |node| node.view())
+    }
+
+    fn edit(&mut self, path: &[PathSegment], edit: Edit) -> Result<(Op<Value>, Edit), String> {
+        match self {
+            Some(node) => node.edit(path, edit),
+            None => Err(format!("/{} is null", print_path(path.to_vec()))),
+        }
+    }
+
+    fn view_at(&self, ops: &HashSet<OpId>) -> Value {
+        self.as_ref().map_or(Value::Null, |node| node.view_at(ops))
+    }
+
+    fn locate(&self, tokens: &[String]) -> Result<Location, String> {
+        match (self, tokens.first()) {
+            (Some(node), _) => node.locate(tokens),
+            (None, None) => Ok(Location::Primitive),
+            (None, Some(token)) => Err(format!("cannot index into null with {token:?}")),
+        }
+    }
+
+    fn blame(&self) -> Blame {
+        self.as_ref()
+            .map_or(Blame::Value(Value::Null), |node| node.blame())
+    }
+
+    fn max_seq(&self, op: &Op<Value>, justified: SequenceNumber) -> Option<SequenceNumber> {
+        match self {
+            Some(node) => node.max_seq(op, justified),
+            None => Lamport::max_seq(justified),
+        }
+    }
+
+    fn check_all(&self, ops: &[Op<Value>]) -> Result<Option<OpId>, ApplyError> {
+        match self {
+            Some(node) => node.check_all(ops),
+            None => match ops.first() {
+                Some(op) => Err(ApplyError::new(
+                    OpState::ErrPathMismatch,
+                    op,
+                    "the value the op is for is null",
+                )),
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn new(_id: AuthorId, _path: Vec<PathSegment>) -> Self {
+        None
+    }
+}
+
 /// The base struct for a JSON CRDT. Allows for declaring causal
 /// dependencies across fields. It only accepts messages of [`SignedOp`] for BFT.
 pub struct BaseCrdt<T: CrdtNode> {
@@ -98,10 +482,80 @@ pub struct BaseCrdt<T: CrdtNode> {
     pub doc: T,
 
     /// In a real world scenario, this would be a proper hashgraph that allows for
-    /// efficient reconciliation of missing dependencies. We naively keep a hashset
-    /// of messages we've seen (represented by their [`SignedDigest`]).
-    received: HashSet<SignedDigest>,
-    message_q: HashMap<SignedDigest, Vec<SignedOp>>,
+    /// efficient reconciliation of missing dependencies. We naively keep a hashmap
+    /// of messages we've seen (represented by their [`SignedDigest`]) along with their
+    /// dependencies and the ops they contained so we can rebuild past versions.
+    received: HashMap<SignedDigest, Delivered>,
+    message_q: HashMap<SignedDigest, Vec<QueuedMessage>>,
+    /// Transactions with an op for an element the document does not have yet, by the ID of that
+    /// element. A single op like that waits in the list it is for, a transaction waits here as a
+    /// whole so none of its ops are applied before the others
+    element_q: HashMap<OpId, Vec<SignedTransaction>>,
+    /// Delivered ops the document is holding until the element they are for arrives, by the ID
+    /// of that element, so we know when the transactions waiting for them can go ahead
+    held_by_doc: HashMap<OpId, Vec<OpId>>,
+
+    /// Messages that no other received message depends on yet
+    heads: HashSet<SignedDigest>,
+    /// Hash chain of chained messages per author, indexed by [`ChainLink::height`]
+    chains: HashMap<AuthorId, Vec<SignedDigest>>,
+    /// Authors we have caught signing two different messages at the same height
+    forked: HashSet<AuthorId>,
+
+    /// Callbacks to notify when a delivered message changes the document
+    observers: Vec<Observer>,
+    next_observer: ObserverId,
+}
+
+/// What we remember about a delivered message
+struct Delivered {
+    author: AuthorId,
+    depends_on: Vec<SignedDigest>,
+    ops: Vec<OpId>,
+    /// Highest sequence number in the causal past of the message, including itself, that later
+    /// messages may count past, see [`CrdtNode::max_seq`]
+    max_seq: SequenceNumber,
+}
+
+/// Handle to an observer registered with [`BaseCrdt::observe`]
+pub type ObserverId = usize;
+
+struct Observer {
+    id: ObserverId,
+    prefix: Vec<PathSegment>,
+    callback: Box<dyn FnMut(&Change)>,
+}
+
+/// Position of a signed message in its author's history. Messages signed through
+/// [`BaseCrdt::sign`] form a hash chain per author which lets receivers detect gaps (a message
+/// whose predecessor never arrived) and forks (two messages claiming the same predecessor)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChainLink {
+    /// Digest of the author's previous message, `None` for the first message
+    pub prev: Option<SignedDigest>,
+    /// Number of chained messages the author signed before this one
+    pub height: u64,
+}
+
+impl ChainLink {
+    /// String representation that goes into the signed digest
+    pub(crate) fn digest_string(chain: &Option<ChainLink>) -> String {
+        match chain {
+            Some(link) => format!(
+                ",{},{}",
+                link.height,
+                link.prev.as_ref().map(print_hex).unwrap_or_default()
+            ),
+            None => "".to_string(),
+        }
+    }
+}
+
+/// Anything that can sit in the causal queue of a [`BaseCrdt`] waiting for its dependencies
+#[derive(Clone)]
+enum QueuedMessage {
+    Op(SignedOp),
+    Transaction(SignedTransaction),
 }
 
 /// An [`Op<Value>`] with a few bits of extra metadata
@@ -115,6 +569,8 @@ pub struct SignedOp {
     pub inner: Op<Value>,
     /// List of causal dependencies
     pub depends_on: Vec<SignedDigest>,
+    /// Position in the author's hash chain if this was signed through [`BaseCrdt::sign`]
+    pub chain: Option<ChainLink>,
 }
 
 impl SignedOp {
@@ -134,6 +590,7 @@ impl SignedOp {
     ///    - is_deleted
     ///  - path
     ///  - dependencies
+    ///  - chain link
     fn digest(&self) -> [u8; 32] {
         let path_string = print_path(self.inner.path.clone());
         let dependency_string = self
@@ -142,7 +599,11 @@ impl SignedOp {
             .map(print_hex)
             .collect::<Vec<_>>()
             .join("");
-        let fmt_str = format!("{:?},{path_string},{dependency_string}", self.id());
+        let chain_string = ChainLink::digest_string(&self.chain);
+        let fmt_str = format!(
+            "{:?},{path_string},{dependency_string}{chain_string}",
+            self.id()
+        );
         sha256(fmt_str)
     }
 
@@ -152,6 +613,14 @@ impl SignedOp {
         self.signed_digest = sign(keypair, &self.digest()).sig.to_bytes()
     }
 
+    /// Build an [`ApplyError`] for this op, attributed to the signer
+    fn error(&self, kind: OpState, reason: impl Into<String>) -> ApplyError {
+        ApplyError {
+            author: self.author,
+            ..ApplyError::new(kind, &self.inner, reason)
+        }
+    }
+
     /// Ensure digest was actually signed by the author it claims to be signed by
     pub fn is_valid_digest(&self) -> bool {
         let digest = Ed25519Signature::from_bytes(&self.signed_digest);
@@ -167,6 +636,16 @@ impl SignedOp {
         value: Op<T>,
         keypair: &Ed25519KeyPair,
         depends_on: Vec<SignedDigest>,
+    ) -> Self {
+        Self::from_chained_op(value, keypair, depends_on, None)
+    }
+
+    /// Sign an op at a given position in the author's hash chain
+    pub(crate) fn from_chained_op<T: CrdtNode>(
+        value: Op<T>,
+        keypair: &Ed25519KeyPair,
+        depends_on: Vec<SignedDigest>,
+        chain: Option<ChainLink>,
     ) -> Self {
         let author = keypair.public().0.to_bytes();
         let mut new = Self {
@@ -182,6 +661,7 @@ impl SignedOp {
             author,
             signed_digest: [0u8; 64],
             depends_on,
+            chain,
         };
         new.sign_digest(keypair);
         new
@@ -190,67 +670,525 @@ impl SignedOp {
 
 impl<T: CrdtNode + DebugView> BaseCrdt<T> {
     /// Crease a new BaseCRDT of the given type. Multiple BaseCRDTs
-    /// can be created from a single keypair but you are responsible for 
-    /// routing messages to the right BaseCRDT. Usually you should just make a single 
+    /// can be created from a single keypair but you are responsible for
+    /// routing messages to the right BaseCRDT. Usually you should just make a single
     /// struct that contains all the state you need
     pub fn new(keypair: &Ed25519KeyPair) -> Self {
         let id = keypair.public().0.to_bytes();
         Self {
             id,
             doc: T::new(id, vec![]),
-            received: HashSet::new(),
+            received: HashMap::new(),
             message_q: HashMap::new(),
+            heads: HashSet::new(),
+            chains: HashMap::new(),
+            forked: HashSet::new(),
+            element_q: HashMap::new(),
+            held_by_doc: HashMap::new(),
+            observers: vec![],
+            next_observer: 0,
+        }
+    }
+
+    /// Register a callback that is called with every [`Change`] that a delivered message makes
+    /// under the given path prefix (use an empty prefix to observe the whole document). Messages
+    /// that were queued fire their changes once their dependencies arrive. Local ops made
+    /// directly on the nested CRDTs are not delivered through [`BaseCrdt`] so they are not
+    /// observed.
+    pub fn observe(
+        &mut self,
+        prefix: Vec<PathSegment>,
+        callback: impl FnMut(&Change) + 'static,
+    ) -> ObserverId {
+        let id = self.next_observer;
+        self.next_observer += 1;
+        self.observers.push(Observer {
+            id,
+            prefix,
+            callback: Box::new(callback),
+        });
+        id
+    }
+
+    /// Remove an observer. Returns whether it was registered
+    pub fn unobserve(&mut self, id: ObserverId) -> bool {
+        let before = self.observers.len();
+        self.observers.retain(|o| o.id != id);
+        self.observers.len() != before
+    }
+
+    /// Hand the changes to every observer watching their paths
+    fn notify(&mut self, changes: &[Change]) {
+        for change in changes {
+            for observer in &mut self.observers {
+                if change.path().starts_with(&observer.prefix) {
+                    (observer.callback)(change);
+                }
+            }
+        }
+    }
+
+    /// Sign a local op as the next link in our hash chain. The signed op automatically depends
+    /// on our previous op and on all of the current heads so receivers can verify they have seen
+    /// everything we had seen when we made it.
+    ///
+    /// # Panics
+    ///
+    /// If `keypair` is not the one this [`BaseCrdt`] was created with, as the op would extend
+    /// our chain under another author's name
+    pub fn sign<U: CrdtNode>(&mut self, op: Op<U>, keypair: &Ed25519KeyPair) -> SignedOp {
+        self.check_signer(keypair);
+        let (depends_on, chain) = self.next_link();
+        let signed = SignedOp::from_chained_op(op, keypair, depends_on, Some(chain));
+        let seq = self.local_seq(&signed.depends_on, [&signed.inner]);
+        self.record_delivery(
+            signed.author(),
+            signed.signed_digest,
+            &signed.depends_on,
+            &signed.chain,
+            vec![signed.id()],
+            seq,
+        );
+        signed
+    }
+
+    /// Sign a local [`Transaction`] as the next link in our hash chain. See [`BaseCrdt::sign`]
+    ///
+    /// # Panics
+    ///
+    /// If `keypair` is not the one this [`BaseCrdt`] was created with, like [`BaseCrdt::sign`]
+    pub fn sign_transaction(
+        &mut self,
+        tx: Transaction,
+        keypair: &Ed25519KeyPair,
+    ) -> SignedTransaction {
+        self.check_signer(keypair);
+        let (depends_on, chain) = self.next_link();
+        let signed = tx.sign_chained(keypair, depends_on, Some(chain));
+        let seq = self.local_seq(&signed.depends_on, &signed.ops);
+        self.record_delivery(
+            signed.author(),
+            signed.signed_digest,
+            &signed.depends_on,
+            &signed.chain,
+            signed.ids(),
+            seq,
+        );
+        signed
+    }
+
+    /// Make sure we only sign with our own key, since the chain and heads we record for a
+    /// signed message are the ones of `self.id`
+    fn check_signer(&self, keypair: &Ed25519KeyPair) {
+        assert!(
+            keypair.public().0.to_bytes() == self.id,
+            "signing with a keypair that is not the one of this BaseCrdt"
+        );
+    }
+
+    /// Current heads and the link that our next chained message should carry
+    fn next_link(&self) -> (Vec<SignedDigest>, ChainLink) {
+        let ours = self.chains.get(&self.id);
+        let prev = ours.and_then(|chain| chain.last()).copied();
+        let height = ours.map_or(0, |chain| chain.len() as u64);
+        let mut depends_on = self.heads();
+        if let Some(prev) = prev {
+            if !depends_on.contains(&prev) {
+                depends_on.push(prev);
+            }
+        }
+        (depends_on, ChainLink { prev, height })
+    }
+
+    /// Messages that no other message we have received depends on. Sorted so that they
+    /// are stable across calls
+    pub fn heads(&self) -> Vec<SignedDigest> {
+        let mut heads = self.heads.iter().copied().collect::<Vec<_>>();
+        heads.sort();
+        heads
+    }
+
+    /// The chained messages we have received from the given author, in the order they were signed
+    pub fn history_of(&self, author: &AuthorId) -> &[SignedDigest] {
+        self.chains.get(author).map_or(&[], |chain| &chain[..])
+    }
+
+    /// IDs of all the ops in the causal past of the given messages (including themselves).
+    /// Fails if we have not received one of them
+    pub(crate) fn ops_before(&self, heads: &[SignedDigest]) -> Result<HashSet<OpId>, String> {
+        let mut ops = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut stack = heads.to_vec();
+        while let Some(digest) = stack.pop() {
+            if !seen.insert(digest) {
+                continue;
+            }
+            let delivered = self
+                .received
+                .get(&digest)
+                .ok_or_else(|| format!("unknown message {}", &print_hex(&digest)[..6]))?;
+            ops.extend(&delivered.ops);
+            stack.extend(&delivered.depends_on);
         }
+        Ok(ops)
+    }
+
+    /// The document annotated with who wrote each list element and register, see
+    /// [`CrdtNode::blame`]. Ops made on nested nodes are stamped with the ID of the node rather
+    /// than the key of the replica that made them, so authors are taken from the signed
+    /// messages the ops arrived in where we have them
+    pub fn blame(&self) -> Blame {
+        let signers = self
+            .received
+            .values()
+            .flat_map(|delivered| delivered.ops.iter().map(|id| (*id, delivered.author)))
+            .collect::<HashMap<_, _>>();
+        let mut blame = self.doc.blame();
+        blame.attributions_mut(&mut |attribution| {
+            if let Some(signer) = signers.get(&attribution.id) {
+                attribution.author = *signer;
+            }
+        });
+        blame
+    }
+
+    /// Digests of messages that something in our queue is waiting on. A non-empty result for a
+    /// long period of time suggests a peer is withholding messages from us
+    pub fn missing_dependencies(&self) -> Vec<SignedDigest> {
+        let mut missing = self.message_q.keys().copied().collect::<Vec<_>>();
+        missing.sort();
+        missing
+    }
+
+    /// Whether we have seen the given author fork their own history
+    pub fn has_forked(&self, author: &AuthorId) -> bool {
+        self.forked.contains(author)
     }
 
     /// Apply a signed operation to this BaseCRDT, verifying integrity and routing to the right
     /// nested CRDT
-    pub fn apply(&mut self, op: SignedOp) -> OpState {
+    pub fn apply(&mut self, op: SignedOp) -> ApplyResult {
+        let _span = self.apply_span(&op.signed_digest, op.author()).entered();
         self.log_try_apply(&op);
 
         #[cfg(feature = "bft")]
         if !op.is_valid_digest() {
-            self.debug_digest_failure(op);
-            return OpState::ErrDigestMismatch;
+            self.debug_digest_failure(op.clone());
+            return Err(op.error(
+                OpState::ErrDigestMismatch,
+                "signed digest does not match the claimed author",
+            ));
+        }
+
+        // the signature only covers the op ID, so check it matches the content before the op can
+        // wait in the queue
+        if let Err(reason) = op.inner.check_hash() {
+            return Err(op.error(OpState::ErrHashMismatch, reason));
         }
 
         let op_id = op.signed_digest;
-        if !op.depends_on.is_empty() {
-            for origin in &op.depends_on {
-                if !self.received.contains(origin) {
-                    self.log_missing_causal_dep(origin);
-                    self.message_q.entry(*origin).or_default().push(op);
-                    return OpState::MissingCausalDependencies;
-                }
-            }
+        if let Some(missing) = self.missing_dependency(&op.depends_on) {
+            self.log_missing_causal_dep(&missing);
+            self.message_q
+                .entry(missing)
+                .or_default()
+                .push(QueuedMessage::Op(op));
+            return Ok(ApplyOutcome::Queued);
+        }
+
+        if let Err((kind, reason)) = self.check_chain(op.author(), op_id, &op.depends_on, &op.chain)
+        {
+            return Err(op.error(kind, reason));
         }
+        let seq = match self.check_seqs(op.author(), &op.depends_on, [&op.inner]) {
+            Ok(seq) => seq,
+            Err((kind, reason)) => return Err(op.error(kind, reason)),
+        };
 
         // apply
         self.log_actually_apply(&op);
-        let status = self.doc.apply(op.inner);
+        let (id, origin) = (op.id(), op.inner.origin);
+        self.record_delivery(op.author(), op_id, &op.depends_on, &op.chain, vec![id], seq);
+        let mut changes = vec![];
+        let status = self.doc.apply_observed(op.inner, &mut changes);
         self.debug_view();
-        self.received.insert(op_id);
-        
-        // apply all of its causal dependents if there are any
-        let dependent_queue = self.message_q.remove(&op_id);
+        self.notify(&changes);
+        match status {
+            Ok(ApplyOutcome::Applied) => self.apply_waiting_for(vec![id]),
+            // the document only holds ops back for the element they come after
+            Ok(ApplyOutcome::Queued) => self.held_by_doc.entry(origin).or_default().push(id),
+            Err(_) => {}
+        }
+        self.apply_dependents(op_id);
+        status
+    }
+
+    /// Apply a signed transaction to this BaseCRDT. Either every op in the transaction is applied
+    /// or none of them are: the ops are checked against the document first, see
+    /// [`CrdtNode::check_all`], and only applied if all of them fit. A transaction with an op for
+    /// an element we don't have yet is queued until the element arrives, like a single op would be
+    pub fn apply_transaction(&mut self, mut tx: SignedTransaction) -> ApplyResult {
+        let _span = self.apply_span(&tx.signed_digest, tx.author()).entered();
+        self.log_try_apply_transaction(&tx);
+
+        #[cfg(feature = "bft")]
+        if !tx.is_valid_digest() {
+            self.debug_transaction_digest_failure(tx.clone());
+            return Err(tx.error(
+                OpState::ErrDigestMismatch,
+                "signed digest does not match the claimed author",
+            ));
+        }
+
+        if let Some(reason) = tx.ops.iter().find_map(|op| op.check_hash().err()) {
+            return Err(tx.error(OpState::ErrHashMismatch, reason));
+        }
+
+        let tx_id = tx.signed_digest;
+        if let Some(missing) = self.missing_dependency(&tx.depends_on) {
+            self.log_missing_causal_dep(&missing);
+            self.message_q
+                .entry(missing)
+                .or_default()
+                .push(QueuedMessage::Transaction(tx));
+            return Ok(ApplyOutcome::Queued);
+        }
+
+        if let Err((kind, reason)) = self.check_chain(tx.author(), tx_id, &tx.depends_on, &tx.chain)
+        {
+            return Err(tx.error(kind, reason));
+        }
+        let seq = match self.check_seqs(tx.author(), &tx.depends_on, &tx.ops) {
+            Ok(seq) => seq,
+            Err((kind, reason)) => return Err(tx.error(kind, reason)),
+        };
+
+        // check every op first so a failure halfway through leaves the document untouched
+        match self.doc.check_all(&tx.ops) {
+            Ok(None) => {}
+            Ok(Some(missing)) => {
+                self.log_missing_element(&missing);
+                self.element_q.entry(missing).or_default().push(tx);
+                return Ok(ApplyOutcome::Queued);
+            }
+            Err(e) => {
+                self.debug_transaction_rejected(&tx_id);
+                return Err(e);
+            }
+        }
+
+        let ids = tx.ids();
+        let mut changes = vec![];
+        for op in tx.ops.drain(..) {
+            self.log_actually_apply_inner(&op);
+            // check_all tries every op on a copy of the node it is for, in the same order, so it
+            // can't fail here. If it ever does the transaction is half applied
+            let result = self.doc.apply_observed(op, &mut changes);
+            debug_assert!(result.is_ok(), "op passed check_all but failed: {result:?}");
+        }
+
+        self.debug_view();
+        self.notify(&changes);
+        self.record_delivery(
+            tx.author(),
+            tx_id,
+            &tx.depends_on,
+            &tx.chain,
+            ids.clone(),
+            seq,
+        );
+        self.apply_waiting_for(ids);
+        self.apply_dependents(tx_id);
+        Ok(ApplyOutcome::Applied)
+    }
+
+    /// Make sure a chained message extends the author's history instead of skipping or forking it.
+    /// All causal dependencies have been delivered by the time this is called, so an honest
+    /// author's previous message is always the tip of their chain.
+    fn check_chain(
+        &mut self,
+        author: AuthorId,
+        id: SignedDigest,
+        depends_on: &[SignedDigest],
+        chain: &Option<ChainLink>,
+    ) -> Result<(), (OpState, String)> {
+        let Some(link) = chain else {
+            return Ok(());
+        };
+
+        let history = self.history_of(&author);
+        let expected_prev = match link.height {
+            0 => None,
+            h => history.get(h as usize - 1).copied(),
+        };
+        let prev_declared = link.prev.is_none_or(|prev| depends_on.contains(&prev));
+        if link.prev != expected_prev || !prev_declared {
+            self.debug_broken_chain(author, link);
+            return Err((
+                OpState::ErrBrokenChain,
+                format!(
+                    "link at height {} does not extend the {} messages we have from this author",
+                    link.height,
+                    history.len()
+                ),
+            ));
+        }
+
+        match history.get(link.height as usize).copied() {
+            Some(existing) if existing != id => {
+                self.debug_forked_chain(author, link);
+                self.forked.insert(author);
+                Err((
+                    OpState::ErrForkedHistory,
+                    format!(
+                        "author already signed {} at height {}",
+                        &print_hex(&existing)[..6],
+                        link.height
+                    ),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Make sure no op in a message jumps further past the causal dependencies of the message
+    /// than the node it is for allows, see [`CrdtNode::max_seq`]. An honest replica only counts
+    /// past the sequence numbers it has seen, and everything it has seen is declared as a
+    /// dependency when it signs through [`BaseCrdt::sign`]. The bound only depends on the message
+    /// itself, so every replica comes to the same verdict. Ops within the message may each count
+    /// one past the previous ones. Returns the highest sequence number later messages may count
+    /// past, see [`Delivered::max_seq`]
+    fn check_seqs<'a>(
+        &self,
+        author: AuthorId,
+        depends_on: &[SignedDigest],
+        ops: impl IntoIterator<Item = &'a Op<Value>>,
+    ) -> Result<SequenceNumber, (OpState, String)> {
+        let mut justified = self.seq_before(depends_on);
+        for op in ops {
+            let Some(allowed) = self.doc.max_seq(op, justified) else {
+                continue;
+            };
+            if op.seq > allowed {
+                self.debug_sequence_jump(author, op.seq, allowed);
+                return Err((
+                    OpState::ErrSequenceJump,
+                    format!(
+                        "sequence number {} is past the {allowed} its causal past allows",
+                        op.seq
+                    ),
+                ));
+            }
+            justified = max(justified, op.seq);
+        }
+        Ok(justified)
+    }
+
+    /// [`BaseCrdt::check_seqs`] for a message we signed ourselves. An op that jumps doesn't
+    /// justify anything, receivers reject it
+    fn local_seq<'a>(
+        &self,
+        depends_on: &[SignedDigest],
+        ops: impl IntoIterator<Item = &'a Op<Value>>,
+    ) -> SequenceNumber {
+        self.check_seqs(self.id, depends_on, ops)
+            .unwrap_or_else(|_| self.seq_before(depends_on))
+    }
+
+    /// Highest sequence number in the causal past of the given messages
+    fn seq_before(&self, depends_on: &[SignedDigest]) -> SequenceNumber {
+        depends_on
+            .iter()
+            .filter_map(|dep| self.received.get(dep))
+            .map(|delivered| delivered.max_seq)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Bookkeeping for a message that has been delivered
+    fn record_delivery(
+        &mut self,
+        author: AuthorId,
+        id: SignedDigest,
+        depends_on: &[SignedDigest],
+        chain: &Option<ChainLink>,
+        ops: Vec<OpId>,
+        max_seq: SequenceNumber,
+    ) {
+        for dep in depends_on {
+            self.heads.remove(dep);
+        }
+        if let Entry::Vacant(entry) = self.received.entry(id) {
+            let depends_on = depends_on.to_vec();
+            entry.insert(Delivered {
+                author,
+                depends_on,
+                ops,
+                max_seq,
+            });
+            self.heads.insert(id);
+        }
+        if let Some(link) = chain {
+            let history = self.chains.entry(author).or_default();
+            if history.len() as u64 == link.height {
+                history.push(id);
+            }
+        }
+    }
+
+    /// Returns the first causal dependency we have not received yet, if any
+    fn missing_dependency(&self, depends_on: &[SignedDigest]) -> Option<SignedDigest> {
+        depends_on
+            .iter()
+            .find(|origin| !self.received.contains_key(*origin))
+            .copied()
+    }
+
+    /// Apply all of the causal dependents of a message if there are any
+    fn apply_dependents(&mut self, id: SignedDigest) {
+        let dependent_queue = self.message_q.remove(&id);
         if let Some(mut q) = dependent_queue {
             for dependent in q.drain(..) {
-                self.apply(dependent);
+                // failures are logged, the original sender of the message is long gone
+                let _ = match dependent {
+                    QueuedMessage::Op(op) => self.apply(op),
+                    QueuedMessage::Transaction(tx) => self.apply_transaction(tx),
+                };
             }
         }
-        status
+    }
+
+    /// Apply the transactions waiting for the elements the document just integrated, which
+    /// includes the ops it was holding back for them
+    fn apply_waiting_for(&mut self, mut ids: Vec<OpId>) {
+        while let Some(id) = ids.pop() {
+            ids.extend(self.held_by_doc.remove(&id).into_iter().flatten());
+            for tx in self.element_q.remove(&id).into_iter().flatten() {
+                // a transaction that is still missing something is queued again
+                let _ = self.apply_transaction(tx);
+            }
+        }
+    }
+
+    /// Start building a [`Transaction`] of ops that should be delivered atomically
+    pub fn transaction(&self) -> Transaction {
+        Transaction::new()
     }
 }
 
-/// An enum representing a JSON value
-#[derive(Clone, Debug, PartialEq)]
+/// An enum representing a JSON value. Object keys are kept sorted so that views print the same
+/// way on every replica and equal content always hashes to the same op ID
+#[derive(Clone, Debug, Default, PartialEq)]
 pub enum Value {
+    #[default]
     Null,
     Bool(bool),
-    Number(f64),
+    Number(Number),
     String(String),
+    /// Binary data. JSON has no such type so it shows up there as a base64 string
+    Bytes(Vec<u8>),
     Array(Vec<Value>),
-    Object(HashMap<String, Value>),
+    Object(BTreeMap<String, Value>),
 }
 
 impl Display for Value {
@@ -263,6 +1201,7 @@ impl Display for Value {
                 Value::Bool(b) => b.to_string(),
                 Value::Number(n) => n.to_string(),
                 Value::String(s) => format!("\"{s}\""),
+                Value::Bytes(b) => format!("\"{}\"", STANDARD.encode(b)),
                 Value::Array(arr) => {
                     if arr.len() > 1 {
                         format!(
@@ -294,30 +1233,144 @@ impl Display for Value {
     }
 }
 
-impl Default for Value {
-    fn default() -> Self {
-        Self::Null
+/// A JSON number. Integers are kept exact instead of going through `f64`, so IDs, timestamps
+/// and amounts above 2^53 survive the round trip
+#[derive(Clone, Copy)]
+pub enum Number {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+}
+
+impl Number {
+    /// The number as an `i64`, if it is an integer that fits. Floats count if they have no
+    /// fractional part
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Number::Int(x) => Some(x),
+            Number::UInt(x) => i64::try_from(x).ok(),
+            Number::Float(x) if x.fract() == 0.0 && x >= i64::MIN as f64 && x < i64::MAX as f64 => {
+                Some(x as i64)
+            }
+            Number::Float(_) => None,
+        }
+    }
+
+    /// The number as a `u64`, if it is an integer that fits. Floats count if they have no
+    /// fractional part
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Number::Int(x) => u64::try_from(x).ok(),
+            Number::UInt(x) => Some(x),
+            Number::Float(x) if x.fract() == 0.0 && x >= 0.0 && x < u64::MAX as f64 => {
+                Some(x as u64)
+            }
+            Number::Float(_) => None,
+        }
+    }
+
+    /// The number as an `f64`, which may round integers above 2^53
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Number::Int(x) => x as f64,
+            Number::UInt(x) => x as f64,
+            Number::Float(x) => x,
+        }
+    }
+
+    /// The number as an `f64`, if that does not lose precision
+    pub fn as_exact_f64(&self) -> Option<f64> {
+        let x = self.as_f64();
+        match *self {
+            Number::Int(i) if x as i64 != i || x == i64::MAX as f64 => None,
+            Number::UInt(u) if x as u64 != u || x == u64::MAX as f64 => None,
+            _ => Some(x),
+        }
+    }
+}
+
+/// Numbers are equal if they have the same value, no matter which variant holds them. A float
+/// is only equal to an integer if it is exactly that integer
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Number::Float(a), Number::Float(b)) => a == b,
+            (Number::Float(f), x) | (x, Number::Float(f)) => x.as_exact_f64() == Some(*f),
+            (a, b) => a.as_i64() == b.as_i64() && a.as_u64() == b.as_u64(),
+        }
+    }
+}
+
+/// Only shows the number itself, this ends up in the content that op IDs are hashed from
+impl Debug for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Int(x) => write!(f, "{x}"),
+            Number::UInt(x) => write!(f, "{x}"),
+            Number::Float(x) => write!(f, "{x:?}"),
+        }
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Int(x) => write!(f, "{x}"),
+            Number::UInt(x) => write!(f, "{x}"),
+            Number::Float(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+impl From<serde_json::Number> for Number {
+    fn from(x: serde_json::Number) -> Self {
+        if let Some(x) = x.as_u64() {
+            Number::UInt(x)
+        } else if let Some(x) = x.as_i64() {
+            Number::Int(x)
+        } else {
+            // every other number serde_json parses is a finite float
+            Number::Float(x.as_f64().unwrap_or(f64::NAN))
+        }
+    }
+}
+
+impl TryFrom<Number> for serde_json::Number {
+    type Error = String;
+
+    fn try_from(x: Number) -> Result<Self, Self::Error> {
+        match x {
+            Number::Int(x) => Ok(x.into()),
+            Number::UInt(x) => Ok(x.into()),
+            Number::Float(x) => serde_json::Number::from_f64(x)
+                .ok_or_else(|| format!("{x} cannot be represented in JSON")),
+        }
     }
 }
 
 /// Allow easy conversion to and from serde's JSON format. This allows us to use the [`json!`]
-/// macro
-impl From<Value> for serde_json::Value {
-    fn from(value: Value) -> Self {
-        match value {
+/// macro. Fails on floats that are not finite as JSON has no way to write them
+impl TryFrom<Value> for serde_json::Value {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Ok(match value {
             Value::Null => serde_json::Value::Null,
             Value::Bool(x) => serde_json::Value::Bool(x),
-            Value::Number(x) => serde_json::Value::Number(serde_json::Number::from_f64(x).unwrap()),
+            Value::Number(x) => serde_json::Value::Number(x.try_into()?),
             Value::String(x) => serde_json::Value::String(x),
-            Value::Array(x) => {
-                serde_json::Value::Array(x.iter().map(|a| a.clone().into()).collect())
-            }
+            Value::Bytes(x) => serde_json::Value::String(STANDARD.encode(x)),
+            Value::Array(x) => serde_json::Value::Array(
+                x.into_iter()
+                    .map(serde_json::Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
             Value::Object(x) => serde_json::Value::Object(
-                x.iter()
-                    .map(|(k, v)| (k.clone(), v.clone().into()))
-                    .collect(),
+                x.into_iter()
+                    .map(|(k, v)| Ok((k, serde_json::Value::try_from(v)?)))
+                    .collect::<Result<_, String>>()?,
             ),
-        }
+        })
     }
 }
 
@@ -326,7 +1379,7 @@ impl From<serde_json::Value> for Value {
         match value {
             serde_json::Value::Null => Value::Null,
             serde_json::Value::Bool(x) => Value::Bool(x),
-            serde_json::Value::Number(x) => Value::Number(x.as_f64().unwrap()),
+            serde_json::Value::Number(x) => Value::Number(x.into()),
             serde_json::Value::String(x) => Value::String(x),
             serde_json::Value::Array(x) => {
                 Value::Array(x.iter().map(|a| a.clone().into()).collect())
@@ -341,8 +1394,24 @@ impl From<serde_json::Value> for Value {
 }
 
 impl Value {
+    /// Convert to a [`serde_json::Value`]. Like `JSON.stringify`, floats that are not finite
+    /// become null, use [`TryFrom`] to get an error for them instead
     pub fn into_json(self) -> serde_json::Value {
-        self.into()
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(x) => serde_json::Value::Bool(x),
+            Value::Number(x) => x
+                .try_into()
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+            Value::String(x) => serde_json::Value::String(x),
+            Value::Bytes(x) => serde_json::Value::String(STANDARD.encode(x)),
+            Value::Array(x) => {
+                serde_json::Value::Array(x.into_iter().map(Value::into_json).collect())
+            }
+            Value::Object(x) => {
+                serde_json::Value::Object(x.into_iter().map(|(k, v)| (k, v.into_json())).collect())
+            }
+        }
     }
 }
 
@@ -355,19 +1424,25 @@ impl From<bool> for Value {
 
 impl From<i64> for Value {
     fn from(val: i64) -> Self {
-        Value::Number(val as f64)
+        Value::Number(Number::Int(val))
     }
 }
 
 impl From<i32> for Value {
     fn from(val: i32) -> Self {
-        Value::Number(val as f64)
+        Value::Number(Number::Int(val.into()))
+    }
+}
+
+impl From<u64> for Value {
+    fn from(val: u64) -> Self {
+        Value::Number(Number::UInt(val))
     }
 }
 
 impl From<f64> for Value {
     fn from(val: f64) -> Self {
-        Value::Number(val)
+        Value::Number(Number::Float(val))
     }
 }
 
@@ -377,6 +1452,12 @@ impl From<String> for Value {
     }
 }
 
+impl From<Vec<u8>> for Value {
+    fn from(val: Vec<u8>) -> Self {
+        Value::Bytes(val)
+    }
+}
+
 impl From<char> for Value {
     fn from(val: char) -> Self {
         Value::String(val.into())
@@ -404,12 +1485,19 @@ where
     }
 }
 
-/// Fallibly create a CRDT Node from a JSON Value 
+/// Fallibly create a CRDT Node from a JSON Value
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be created from a JSON value",
+    note = "wrap values in `LwwRegisterCrdt<_>`, use `ListCrdt<_>` for lists, or add `#[crdt]` to your own types"
+)]
 pub trait CrdtNodeFromValue: Sized {
+    /// Whether the node is a single character, see [`Location::List`]
+    const IS_CHAR: bool = false;
+
     fn node_from(value: Value, id: AuthorId, path: Vec<PathSegment>) -> Result<Self, String>;
 }
 
-/// Fallibly cast a JSON Value into a CRDT Node 
+/// Fallibly cast a JSON Value into a CRDT Node
 pub trait IntoCrdtNode<T>: Sized {
     fn into_node(self, id: AuthorId, path: Vec<PathSegment>) -> Result<T, String>;
 }
@@ -444,20 +1532,45 @@ impl CrdtNodeFromValue for bool {
 
 impl CrdtNodeFromValue for f64 {
     fn node_from(value: Value, _id: AuthorId, _path: Vec<PathSegment>) -> Result<Self, String> {
-        if let Value::Number(x) = value {
-            Ok(x)
-        } else {
-            Err(format!("failed to convert {value:?} -> f64"))
+        match value {
+            Value::Number(x) => x
+                .as_exact_f64()
+                .ok_or_else(|| format!("{x} does not fit in an f64 without rounding")),
+            value => Err(format!("failed to convert {value:?} -> f64")),
         }
     }
 }
 
 impl CrdtNodeFromValue for i64 {
     fn node_from(value: Value, _id: AuthorId, _path: Vec<PathSegment>) -> Result<Self, String> {
-        if let Value::Number(x) = value {
-            Ok(x as i64)
-        } else {
-            Err(format!("failed to convert {value:?} -> f64"))
+        match value {
+            Value::Number(x) => x
+                .as_i64()
+                .ok_or_else(|| format!("{x} is not an integer that fits in an i64")),
+            value => Err(format!("failed to convert {value:?} -> i64")),
+        }
+    }
+}
+
+impl CrdtNodeFromValue for i32 {
+    fn node_from(value: Value, _id: AuthorId, _path: Vec<PathSegment>) -> Result<Self, String> {
+        match value {
+            Value::Number(x) => x
+                .as_i64()
+                .and_then(|x| i32::try_from(x).ok())
+                .ok_or_else(|| format!("{x} is not an integer that fits in an i32")),
+            value => Err(format!("failed to convert {value:?} -> i32")),
+        }
+    }
+}
+
+impl CrdtNodeFromValue for u64 {
+    fn node_from(value: Value, _id: AuthorId, _path: Vec<PathSegment>) -> Result<Self, String> {
+        match value {
+            Value::Number(x) => x
+                .as_u64()
+                .ok_or_else(|| format!("{x} is not an integer that fits in a u64")),
+            value => Err(format!("failed to convert {value:?} -> u64")),
         }
     }
 }
@@ -472,7 +1585,22 @@ impl CrdtNodeFromValue for String {
     }
 }
 
+/// Bytes that went through JSON come back as base64 strings
+impl CrdtNodeFromValue for Vec<u8> {
+    fn node_from(value: Value, _id: AuthorId, _path: Vec<PathSegment>) -> Result<Self, String> {
+        match value {
+            Value::Bytes(x) => Ok(x),
+            Value::String(x) => STANDARD
+                .decode(&x)
+                .map_err(|e| format!("{x:?} is not valid base64: {e}")),
+            value => Err(format!("failed to convert {value:?} -> Vec<u8>")),
+        }
+    }
+}
+
 impl CrdtNodeFromValue for char {
+    const IS_CHAR: bool = true;
+
     fn node_from(value: Value, _id: AuthorId, _path: Vec<PathSegment>) -> Result<Self, String> {
         if let Value::String(x) = value.clone() {
             x.chars().next().ok_or(format!(
@@ -484,13 +1612,21 @@ impl CrdtNodeFromValue for char {
     }
 }
 
-impl<T> CrdtNodeFromValue for LwwRegisterCrdt<T>
+impl<T, O> CrdtNodeFromValue for LwwRegisterCrdt<T, O>
 where
     T: CrdtNode,
+    O: LwwOrder,
 {
     fn node_from(value: Value, id: AuthorId, path: Vec<PathSegment>) -> Result<Self, String> {
-        let mut crdt = LwwRegisterCrdt::new(id, path);
+        let mut crdt = LwwRegisterCrdt::with_order(id, path.clone());
+        // null is how an empty register shows up in the view
+        if value == Value::Null {
+            return Ok(crdt);
+        }
+        // check the type up front, `set` would quietly leave the register empty
+        IntoCrdtNode::<T>::into_node(value.clone(), id, path)?;
         crdt.set(value);
+        crdt.mark_initial();
         Ok(crdt)
     }
 }
@@ -502,12 +1638,15 @@ where
     fn node_from(value: Value, id: AuthorId, path: Vec<PathSegment>) -> Result<Self, String> {
         if let Value::Array(arr) = value {
             let mut crdt = ListCrdt::new(id, path);
-            let result: Result<(), String> =
-                arr.into_iter().enumerate().try_for_each(|(i, val)| {
-                    crdt.insert_idx(i, val);
-                    Ok(())
-                });
-            result?;
+            let mut prev = ROOT_ID;
+            for val in arr {
+                // elements of the wrong type are rejected by the list so we skip over them
+                let op = crdt.insert(prev, val);
+                if crdt.find_idx(op.id).is_some() {
+                    prev = op.id;
+                }
+            }
+            crdt.mark_initial();
             Ok(crdt)
         } else {
             Err(format!("failed to convert {value:?} -> ListCRDT<T>"))
@@ -515,16 +1654,32 @@ where
     }
 }
 
+impl<T> CrdtNodeFromValue for Option<T>
+where
+    T: CrdtNode,
+{
+    fn node_from(value: Value, id: AuthorId, path: Vec<PathSegment>) -> Result<Self, String> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::node_from(value, id, path).map(Some),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use serde_json::json;
+    use std::{cell::RefCell, rc::Rc};
 
     use crate::{
-        json_crdt::{add_crdt_fields, BaseCrdt, CrdtNode, OpState, Value, IntoCrdtNode},
-        keypair::make_keypair,
+        json_crdt::{
+            add_crdt_fields, crdt, ApplyOutcome, BaseCrdt, Blame, Change, CrdtNode, IntoCrdtNode,
+            OpState, Value,
+        },
+        keypair::{make_author, make_keypair},
         list_crdt::ListCrdt,
         lww_crdt::LwwRegisterCrdt,
-        op::{print_path, ROOT_ID},
+        op::{print_path, Op, PathSegment, ROOT_ID},
     };
 
     #[test]
@@ -582,11 +1737,16 @@ mod test {
         let mut base1 = BaseCrdt::<Test>::new(&kp1);
         let mut base2 = BaseCrdt::<Test>::new(&kp2);
 
-        let _1_a_1 = base1.doc.a.set(3.0).sign(&kp1);
-        let _1_b_1 = base1.doc.b.set(true).sign(&kp1);
-        let _2_a_1 = base2.doc.a.set(1.5).sign(&kp2);
-        let _2_a_2 = base2.doc.a.set(2.13).sign(&kp2);
-        let _2_c_1 = base2.doc.c.set("abc".to_string()).sign(&kp2);
+        let _1_a_1 = base1.doc.a.set(3.0);
+        let _1_a_1 = base1.sign(_1_a_1, &kp1);
+        let _1_b_1 = base1.doc.b.set(true);
+        let _1_b_1 = base1.sign(_1_b_1, &kp1);
+        let _2_a_1 = base2.doc.a.set(1.5);
+        let _2_a_1 = base2.sign(_2_a_1, &kp2);
+        let _2_a_2 = base2.doc.a.set(2.13);
+        let _2_a_2 = base2.sign(_2_a_2, &kp2);
+        let _2_c_1 = base2.doc.c.set("abc".to_string());
+        let _2_c_1 = base2.sign(_2_c_1, &kp2);
 
         assert_eq!(base1.doc.a.view(), json!(3.0).into());
         assert_eq!(base2.doc.a.view(), json!(2.13).into());
@@ -610,11 +1770,11 @@ mod test {
             })
         );
 
-        assert_eq!(base2.apply(_1_a_1), OpState::Ok);
-        assert_eq!(base2.apply(_1_b_1), OpState::Ok);
-        assert_eq!(base1.apply(_2_a_1), OpState::Ok);
-        assert_eq!(base1.apply(_2_a_2), OpState::Ok);
-        assert_eq!(base1.apply(_2_c_1), OpState::Ok);
+        assert_eq!(base2.apply(_1_a_1), Ok(ApplyOutcome::Applied));
+        assert_eq!(base2.apply(_1_b_1), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.apply(_2_a_1), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.apply(_2_a_2), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.apply(_2_c_1), Ok(ApplyOutcome::Applied));
 
         assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
         assert_eq!(
@@ -640,10 +1800,14 @@ mod test {
         let mut base1 = BaseCrdt::<Test>::new(&kp1);
         let mut base2 = BaseCrdt::<Test>::new(&kp2);
 
-        let _1a = base1.doc.a.insert(ROOT_ID, "a".to_string()).sign(&kp1);
-        let _1b = base1.doc.a.insert(_1a.id(), "b".to_string()).sign(&kp1);
-        let _2c = base2.doc.a.insert(ROOT_ID, "c".to_string()).sign(&kp2);
-        let _2d = base2.doc.a.insert(_1b.id(), "d".to_string()).sign(&kp2);
+        let _1a = base1.doc.a.insert(ROOT_ID, "a".to_string());
+        let _1a = base1.sign(_1a, &kp1);
+        let _1b = base1.doc.a.insert(_1a.id(), "b".to_string());
+        let _1b = base1.sign(_1b, &kp1);
+        let _2c = base2.doc.a.insert(ROOT_ID, "c".to_string());
+        let _2c = base2.sign(_2c, &kp2);
+        let _2d = base2.doc.a.insert(_1b.id(), "d".to_string());
+        let _2d = base2.sign(_2d, &kp2);
 
         assert_eq!(
             base1.doc.view().into_json(),
@@ -660,10 +1824,10 @@ mod test {
             })
         );
 
-        assert_eq!(base2.apply(_1b), OpState::MissingCausalDependencies);
-        assert_eq!(base2.apply(_1a), OpState::Ok);
-        assert_eq!(base1.apply(_2d), OpState::Ok);
-        assert_eq!(base1.apply(_2c), OpState::Ok);
+        assert_eq!(base2.apply(_1b), Ok(ApplyOutcome::Queued));
+        assert_eq!(base2.apply(_1a), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.apply(_2c), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.apply(_2d), Ok(ApplyOutcome::Applied));
         assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
     }
 
@@ -721,16 +1885,541 @@ mod test {
         );
 
         // do it completely out of order
+        assert_eq!(base2.apply(_new_inventory_item), Ok(ApplyOutcome::Queued));
+        assert_eq!(base2.apply(_spend_money), Ok(ApplyOutcome::Queued));
+        assert_eq!(base2.apply(_add_money), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+    }
+
+    #[test]
+    fn test_hash_chain() {
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Test {
+            a: ListCrdt<char>,
+        }
+
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Test>::new(&kp1);
+        let mut base2 = BaseCrdt::<Test>::new(&kp2);
+
+        let _a = base1.doc.a.insert(ROOT_ID, 'a');
+        let _a = base1.sign(_a, &kp1);
+        let _b = base1.doc.a.insert(_a.id(), 'b');
+        let _b = base1.sign(_b, &kp1);
+        assert_eq!(_b.depends_on, vec![_a.signed_digest]);
+        assert_eq!(_b.chain.unwrap().prev, Some(_a.signed_digest));
+        assert_eq!(_b.chain.unwrap().height, 1);
+        assert_eq!(base1.heads(), vec![_b.signed_digest]);
+
+        // the gap is visible to the receiver until the missing op arrives
+        assert_eq!(base2.apply(_b.clone()), Ok(ApplyOutcome::Queued));
+        assert_eq!(base2.missing_dependencies(), vec![_a.signed_digest]);
+        assert_eq!(base2.apply(_a.clone()), Ok(ApplyOutcome::Applied));
+        assert!(base2.missing_dependencies().is_empty());
         assert_eq!(
-            base2.apply(_new_inventory_item),
-            OpState::MissingCausalDependencies
+            base2.history_of(&base1.id),
+            &[_a.signed_digest, _b.signed_digest]
         );
+
+        // new ops from base2 build on everything it has seen
+        let _c = base2.doc.a.insert(_b.id(), 'c');
+        let _c = base2.sign(_c, &kp2);
+        assert_eq!(_c.depends_on, vec![_b.signed_digest]);
+        assert_eq!(_c.chain.unwrap().height, 0);
+        assert_eq!(base1.apply(_c), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.heads(), base2.heads());
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+    }
+
+    #[test]
+    #[should_panic(expected = "not the one of this BaseCrdt")]
+    fn test_sign_with_other_keypair() {
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Test {
+            a: ListCrdt<char>,
+        }
+
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Test>::new(&kp1);
+        let _a = base1.doc.a.insert(ROOT_ID, 'a');
+        base1.sign(_a, &kp2);
+    }
+
+    #[test]
+    fn test_observers() {
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Test {
+            title: LwwRegisterCrdt<String>,
+            chars: ListCrdt<char>,
+        }
+
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Test>::new(&kp1);
+        let mut base2 = BaseCrdt::<Test>::new(&kp2);
+
+        let all = Rc::new(RefCell::new(vec![]));
+        let chars = Rc::new(RefCell::new(vec![]));
+        let all_handle = all.clone();
+        let chars_handle = chars.clone();
+        base2.observe(vec![], move |c| all_handle.borrow_mut().push(c.clone()));
+        let chars_id = base2.observe(vec![PathSegment::Field("chars".to_string())], move |c| {
+            chars_handle.borrow_mut().push(c.clone())
+        });
+
+        let _title = base1.doc.title.set("draft".to_string()).sign(&kp1);
+        let _a = base1.doc.chars.insert(ROOT_ID, 'a');
+        let _a = base1.sign(_a, &kp1);
+        let _b = base1.doc.chars.insert(_a.id(), 'b');
+        let _b = base1.sign(_b, &kp1);
+        let _del_a = base1.doc.chars.delete(_a.id());
+        let _del_a = base1.sign(_del_a, &kp1);
+
+        // queued ops fire their changes once the insert they depend on arrives
+        assert_eq!(base2.apply(_del_a), Ok(ApplyOutcome::Queued));
+        assert_eq!(base2.apply(_b), Ok(ApplyOutcome::Queued));
+        assert_eq!(base2.apply(_a), Ok(ApplyOutcome::Applied));
+        assert_eq!(base2.apply(_title), Ok(ApplyOutcome::Applied));
+
+        let path = vec![PathSegment::Field("chars".to_string())];
+        let expected = vec![
+            Change::ListInsert {
+                path: path.clone(),
+                index: 0,
+                value: Value::String("a".to_string()),
+            },
+            Change::ListInsert {
+                path: path.clone(),
+                index: 1,
+                value: Value::String("b".to_string()),
+            },
+            Change::ListDelete {
+                path: path.clone(),
+                index: 0,
+            },
+        ];
+        assert_eq!(*chars.borrow(), expected);
+        assert_eq!(all.borrow().len(), 4);
         assert_eq!(
-            base2.apply(_spend_money),
-            OpState::MissingCausalDependencies
+            all.borrow()[3],
+            Change::RegisterSet {
+                path: vec![PathSegment::Field("title".to_string())],
+                old: Value::Null,
+                new: Value::String("draft".to_string()),
+            }
         );
-        assert_eq!(base2.apply(_add_money), OpState::Ok);
+
+        assert!(base2.unobserve(chars_id));
+        assert!(!base2.unobserve(chars_id));
+        let _c = base1.doc.chars.insert_idx(0, 'c');
+        base2.apply(base1.sign(_c, &kp1)).unwrap();
+        assert_eq!(chars.borrow().len(), 3);
+        assert_eq!(all.borrow().len(), 5);
+    }
+
+    #[test]
+    fn test_blame() {
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Line {
+            text: ListCrdt<char>,
+        }
+
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Doc {
+            title: LwwRegisterCrdt<String>,
+            lines: ListCrdt<Line>,
+        }
+
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Doc>::new(&kp1);
+        let mut base2 = BaseCrdt::<Doc>::new(&kp2);
+        let (author1, author2) = (base1.id, base2.id);
+
+        let _title = base1.doc.title.set("notes".to_string());
+        let _title = base1.sign(_title, &kp1);
+        let _line = base1.doc.lines.insert(ROOT_ID, json!({ "text": ['h'] }));
+        let _line = base1.sign(_line, &kp1);
+        base2.apply(_title).unwrap();
+        base2.apply(_line).unwrap();
+        // base2 types into the line base1 created
+        let h = base2.doc.lines[0].text.id_at(1).unwrap();
+        let _i = base2.doc.lines[0].text.insert(h, 'i');
+        let _i = base2.sign(_i, &kp2);
+        base1.apply(_i).unwrap();
+
+        let blame = base1.blame();
+        assert_eq!(blame.clone().into_value(), base1.doc.view());
+        let Blame::Object(fields) = blame else {
+            panic!("expected an object");
+        };
+        let Blame::Register { writer, .. } = &fields["title"] else {
+            panic!("expected a register");
+        };
+        assert_eq!(writer.unwrap().author, author1);
+        let Blame::List(lines) = &fields["lines"] else {
+            panic!("expected a list");
+        };
+        assert_eq!(lines[0].0.author, author1);
+        let Blame::Object(line) = &lines[0].1 else {
+            panic!("expected an object");
+        };
+        let Blame::List(chars) = &line["text"] else {
+            panic!("expected a list");
+        };
+        // the op for 'i' is stamped with the id of the line but was signed by base2
+        assert_ne!(base1.doc.lines[0].text.blame()[1].0.author, author2);
+        assert_eq!(chars[1].0.author, author2);
+        assert_eq!(chars[1].1, Blame::Value(Value::String("i".to_string())));
+    }
+
+    #[test]
+    fn test_field_attributes() {
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Profile {
+            #[crdt(rename = "displayName")]
+            display_name: LwwRegisterCrdt<String>,
+            #[crdt(default = "default_level")]
+            level: LwwRegisterCrdt<f64>,
+            #[crdt(default)]
+            tags: ListCrdt<String>,
+            #[crdt(skip)]
+            draft: String,
+        }
+
+        fn default_level() -> f64 {
+            1.0
+        }
+
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Team {
+            members: ListCrdt<Profile>,
+        }
+
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Team>::new(&kp1);
+        let mut base2 = BaseCrdt::<Team>::new(&kp2);
+
+        // missing fields fall back to their defaults
+        let _ann = base1
+            .doc
+            .members
+            .insert(ROOT_ID, json!({ "displayName": "ann" }));
+        let _ann = base1.sign(_ann, &kp1);
+        base2.apply(_ann).unwrap();
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "members": [{ "displayName": "ann", "level": 1.0, "tags": [] }] })
+        );
+
+        // skipped fields are local only
+        base2.doc.members[0].draft = "unsent".to_string();
+        let _rename = base2.doc.members[0].display_name.set("anna".to_string());
+        let _rename = base2.sign(_rename, &kp2);
+        assert_eq!(
+            _rename.inner.path[2],
+            PathSegment::Field("displayName".to_string())
+        );
+        base1.apply(_rename).unwrap();
         assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert!(base1.doc.members[0].draft.is_empty());
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Strict {
+            a: LwwRegisterCrdt<f64>,
+            b: ListCrdt<char>,
+        }
+
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        #[crdt(default)]
+        struct Lenient {
+            a: LwwRegisterCrdt<f64>,
+            b: ListCrdt<char>,
+        }
+
+        let id = make_author(1);
+        assert!(
+            IntoCrdtNode::<Strict>::into_node(Value::from(json!({ "a": 1 })), id, vec![]).is_err()
+        );
+        assert!(IntoCrdtNode::<Strict>::into_node(
+            Value::from(json!({ "a": "1", "b": [] })),
+            id,
+            vec![]
+        )
+        .is_err());
+        let lenient: Lenient = Value::from(json!({ "a": 1 }))
+            .into_node(id, vec![])
+            .unwrap();
+        assert_eq!(lenient.view().into_json(), json!({ "a": 1.0, "b": [] }));
+        // fields that are there still have to have the right type
+        assert!(
+            IntoCrdtNode::<Lenient>::into_node(Value::from(json!({ "b": 1 })), id, vec![]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_optional_fields() {
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Profile {
+            name: LwwRegisterCrdt<String>,
+            tags: ListCrdt<String>,
+        }
+
+        #[add_crdt_fields]
+        #[derive(Clone, CrdtNode)]
+        struct Account {
+            handle: LwwRegisterCrdt<String>,
+            nickname: Option<LwwRegisterCrdt<String>>,
+            profile: LwwRegisterCrdt<Option<Profile>>,
+        }
+
+        // missing optional fields are null, ops for them are rejected
+        let id = make_author(1);
+        let mut with: Account =
+            Value::from(json!({ "handle": "ann", "nickname": "annie", "profile": null }))
+                .into_node(id, vec![])
+                .unwrap();
+        let mut without: Account = Value::from(json!({ "handle": "ann", "profile": null }))
+            .into_node(id, vec![])
+            .unwrap();
+        assert_eq!(
+            without.view().into_json(),
+            json!({ "handle": "ann", "nickname": null, "profile": null })
+        );
+        let rename = with.nickname.as_mut().unwrap().set("ann-marie".to_string());
+        assert_eq!(with.nickname.view().into_json(), json!("ann-marie"));
+        assert_eq!(
+            without.apply(rename).map_err(|e| e.kind),
+            Err(OpState::ErrPathMismatch)
+        );
+
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Account>::new(&kp1);
+        let mut base2 = BaseCrdt::<Account>::new(&kp2);
+        let _create = base1.doc.profile.set(json!({ "name": "ann", "tags": [] }));
+        base2.apply(base1.sign(_create, &kp1)).unwrap();
+
+        // ops for the nested profile reach it through the register
+        let profile = base2.doc.profile.get_mut().unwrap().as_mut().unwrap();
+        let _tag = profile.tags.insert(ROOT_ID, "admin".to_string());
+        base1.apply(base2.sign(_tag, &kp2)).unwrap();
+        assert_eq!(
+            base1.doc.profile.view().into_json(),
+            json!({ "name": "ann", "tags": ["admin"] })
+        );
+
+        // clearing it wins over a concurrent edit of the old profile, whichever comes first
+        let _clear = base1.doc.profile.set(Value::Null);
+        let _clear = base1.sign(_clear, &kp1);
+        let profile = base2.doc.profile.get_mut().unwrap().as_mut().unwrap();
+        let _rename = profile.name.set("anna".to_string());
+        let _rename = base2.sign(_rename, &kp2);
+        assert_eq!(base1.apply(_rename), Ok(ApplyOutcome::Applied));
+        assert_eq!(base2.apply(_clear), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert_eq!(base1.doc.profile.view().into_json(), json!(null));
+        assert!(base1.doc.profile.get_mut().unwrap().is_none());
+
+        // concurrent create and clear are resolved like any other concurrent sets
+        let _recreate = base1
+            .doc
+            .profile
+            .set(json!({ "name": "ann", "tags": ["new"] }));
+        let _recreate = base1.sign(_recreate, &kp1);
+        let _clear = base2.doc.profile.set(Value::Null);
+        let _clear = base2.sign(_clear, &kp2);
+        base1.apply(_clear).unwrap();
+        base2.apply(_recreate).unwrap();
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        let created = base1.id < base2.id;
+        assert_eq!(base1.doc.profile.get().unwrap().is_some(), created);
+    }
+
+    #[test]
+    fn test_register_clear() {
+        #[crdt]
+        struct Note {
+            title: LwwRegisterCrdt<String>,
+            body: LwwRegisterCrdt<ListCrdt<char>>,
+        }
+
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Note>::new(&kp1);
+        let mut base2 = BaseCrdt::<Note>::new(&kp2);
+        let _title = base1.doc.title.set("draft".to_string());
+        let _title = base1.sign(_title, &kp1);
+        let _body = base1.doc.body.set(json!(["h", "i"]));
+        let _body = base1.sign(_body, &kp1);
+        base2.apply(_title).unwrap();
+        base2.apply(_body).unwrap();
+
+        // clears are routed through the struct like sets
+        let _clear = base1.doc.title.clear();
+        assert_eq!(_clear.path[..1], [PathSegment::Field("title".to_string())]);
+        let _clear = base1.sign(_clear, &kp1);
+        assert_eq!(base2.apply(_clear), Ok(ApplyOutcome::Applied));
+        assert_eq!(base2.doc.title.get(), None);
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "title": null, "body": ["h", "i"] })
+        );
+
+        // a clear of a nested CRDT wins over concurrent edits to it
+        let _clear = base1.doc.body.clear();
+        let _clear = base1.sign(_clear, &kp1);
+        let body = base2.doc.body.get_mut().unwrap();
+        let _edit = body.insert_idx(2, '!');
+        let _edit = base2.sign(_edit, &kp2);
+        let _rewrite = base2.doc.body.set(json!(["y", "o"]));
+        let _rewrite = base2.sign(_rewrite, &kp2);
+        base1.apply(_edit).unwrap();
+        base1.apply(_rewrite).unwrap();
+        base2.apply(_clear).unwrap();
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert_eq!(base1.doc.body.view(), Value::Null);
+
+        // once everyone has seen the clear the register can be filled again
+        let _refill = base2.doc.body.set(json!(["o", "k"]));
+        let _refill = base2.sign(_refill, &kp2);
+        base1.apply(_refill).unwrap();
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert_eq!(base1.doc.body.view().into_json(), json!(["o", "k"]));
+    }
+
+    #[test]
+    fn test_number_precision() {
+        #[crdt]
+        struct Ledger {
+            id: LwwRegisterCrdt<u64>,
+            balance: LwwRegisterCrdt<i64>,
+            rate: LwwRegisterCrdt<f64>,
+        }
+
+        // integers above 2^53 survive the round trip through ops and views
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Ledger>::new(&kp1);
+        let mut base2 = BaseCrdt::<Ledger>::new(&kp2);
+        let _id = base1.doc.id.set(u64::MAX).sign(&kp1);
+        let _balance = base1.doc.balance.set(-(1i64 << 53) - 1).sign(&kp1);
+        let _rate = base1.doc.rate.set(0.1).sign(&kp1);
+        for op in [_id, _balance, _rate] {
+            base2.apply(op).unwrap();
+        }
+        let expected = json!({ "id": u64::MAX, "balance": -(1i64 << 53) - 1, "rate": 0.1 });
+        assert_eq!(base2.doc.view().into_json(), expected);
+        assert_eq!(Value::from(expected.clone()).into_json(), expected);
+
+        // numbers that do not fit the register are rejected instead of rounded
+        let id = make_author(1);
+        let ledger = |value: serde_json::Value| {
+            IntoCrdtNode::<Ledger>::into_node(Value::from(value), id, vec![])
+        };
+        assert!(ledger(json!({ "id": 1, "balance": 1.5, "rate": 1 })).is_err());
+        assert!(ledger(json!({ "id": -1, "balance": 1, "rate": 1 })).is_err());
+        assert!(ledger(json!({ "id": 1, "balance": u64::MAX, "rate": 1 })).is_err());
+        assert!(ledger(json!({ "id": 1, "balance": 1, "rate": (1u64 << 53) + 1 })).is_err());
+        let ok = ledger(json!({ "id": 1, "balance": 2.0, "rate": 3 })).unwrap();
+        assert_eq!(
+            ok.view().into_json(),
+            json!({ "id": 1, "balance": 2, "rate": 3.0 })
+        );
+        // which is also why integers and floats with the same value are equal
+        assert_eq!(Value::from(3), Value::from(3.0));
+        assert_ne!(
+            Value::from((1u64 << 53) + 1),
+            Value::from((1u64 << 53) as f64)
+        );
+
+        // floats that JSON cannot hold are an error rather than a panic
+        let nan = Value::Array(vec![Value::from(f64::NAN)]);
+        assert!(serde_json::Value::try_from(nan.clone()).is_err());
+        assert_eq!(nan.into_json(), json!([null]));
+    }
+
+    #[test]
+    fn test_bytes() {
+        #[crdt]
+        struct Attachment {
+            thumbnail: LwwRegisterCrdt<Vec<u8>>,
+            chunks: ListCrdt<Vec<u8>>,
+        }
+
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Attachment>::new(&kp1);
+        let mut base2 = BaseCrdt::<Attachment>::new(&kp2);
+        let _thumbnail = base1.doc.thumbnail.set(vec![0u8, 159, 146, 150]).sign(&kp1);
+        let _chunk = base1.doc.chunks.insert(ROOT_ID, vec![255u8]).sign(&kp1);
+        base2.apply(_thumbnail).unwrap();
+        base2.apply(_chunk).unwrap();
+        assert_eq!(base2.doc.view(), base1.doc.view());
+        assert_eq!(
+            base2.doc.thumbnail.view(),
+            Value::Bytes(vec![0, 159, 146, 150])
+        );
+
+        // JSON only has strings for them, which turn back into bytes
+        let json = base2.doc.view().into_json();
+        assert_eq!(json, json!({ "thumbnail": "AJ+Slg==", "chunks": ["/w=="] }));
+        let copy: Attachment = Value::from(json.clone())
+            .into_node(make_author(1), vec![])
+            .unwrap();
+        assert_eq!(copy.view(), base2.doc.view());
+        assert!(crate::diff::diff_to_ops(&base2.doc, json)
+            .unwrap()
+            .is_empty());
+        assert!(IntoCrdtNode::<Vec<u8>>::into_node(
+            Value::from(json!("not base64!")),
+            make_author(1),
+            vec![]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_objects_are_ordered() {
+        let fields = [
+            ("b", Value::from(1)),
+            ("a", Value::from(true)),
+            ("c", Value::Null),
+        ];
+        let forward = Value::Object(fields.iter().cloned().map(|(k, v)| (k.into(), v)).collect());
+        let backward = Value::Object(
+            fields
+                .iter()
+                .rev()
+                .cloned()
+                .map(|(k, v)| (k.into(), v))
+                .collect(),
+        );
+        assert_eq!(
+            forward.to_string(),
+            "{   \"a\": true,\n  \"b\": 1,\n  \"c\": null }"
+        );
+        assert_eq!(forward.to_string(), backward.to_string());
+
+        // the same content makes the same op no matter how it was put together
+        let op = |content: Value| Op::new(ROOT_ID, make_author(1), 1, false, Some(content), vec![]);
+        assert_eq!(op(forward).id, op(backward).id);
     }
 
     #[test]
@@ -749,11 +2438,13 @@ mod test {
         // init a 2d grid
         let row0: Value = json!([true, false]).into();
         let row1: Value = json!([false, true]).into();
-        let construct1 = base1.doc.grid.insert_idx(0, row0).sign(&kp1);
-        let construct2 = base1.doc.grid.insert_idx(1, row1).sign(&kp1);
+        let construct1 = base1.doc.grid.insert_idx(0, row0);
+        let construct1 = base1.sign(construct1, &kp1);
+        let construct2 = base1.doc.grid.insert_idx(1, row1);
+        let construct2 = base1.sign(construct2, &kp1);
 
-        assert_eq!(base2.apply(construct1), OpState::Ok);
-        assert_eq!(base2.apply(construct2.clone()), OpState::Ok);
+        assert_eq!(base2.apply(construct1), Ok(ApplyOutcome::Applied));
+        assert_eq!(base2.apply(construct2.clone()), Ok(ApplyOutcome::Applied));
 
         assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
         assert_eq!(
@@ -763,10 +2454,12 @@ mod test {
             })
         );
 
-        let set1 = base1.doc.grid[0][0].set(false).sign(&kp1);
-        let set2 = base2.doc.grid[1][1].set(false).sign(&kp2);
-        assert_eq!(base1.apply(set2), OpState::Ok);
-        assert_eq!(base2.apply(set1), OpState::Ok);
+        let set1 = base1.doc.grid[0][0].set(false);
+        let set1 = base1.sign(set1, &kp1);
+        let set2 = base2.doc.grid[1][1].set(false);
+        let set2 = base2.sign(set2, &kp2);
+        assert_eq!(base1.apply(set2), Ok(ApplyOutcome::Applied));
+        assert_eq!(base2.apply(set1), Ok(ApplyOutcome::Applied));
 
         assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
         assert_eq!(
@@ -849,8 +2542,14 @@ mod test {
         let mut crdt = BaseCrdt::<Test>::new(&key);
 
         // wrong type should not go through
-        crdt.doc.reg.set(32);
+        let wrong_type = crdt.doc.reg.set(32);
         assert_eq!(crdt.doc.reg.view(), json!(null).into());
+        let err = crdt.doc.apply(wrong_type.clone()).unwrap_err();
+        assert_eq!(err.kind, OpState::ErrMismatchedType);
+        assert_eq!(err.op_id, wrong_type.id);
+        assert_eq!(err.author, crdt.id);
+        assert_eq!(err.path, wrong_type.path);
+        assert_eq!(err.reason, "failed to convert Number(32) -> bool");
         crdt.doc.reg.set(true);
         assert_eq!(crdt.doc.reg.view(), json!(true).into());
 
@@ -0,0 +1,603 @@
+use std::fmt::Display;
+
+use crate::{
+    debug::DebugView,
+    json_crdt::{BaseCrdt, CrdtNode, Edit, Location, Value},
+    op::{Op, ROOT_ID},
+};
+
+/// Reasons a JSON Patch can fail. No ops are generated if any operation in the patch fails
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchError {
+    /// The patch is not a valid RFC 6902 document
+    Malformed(String),
+    /// The `test` operation at `index` in the patch did not match the document
+    TestFailed { index: usize, path: String },
+    /// The operation at `index` in the patch cannot be applied to the document
+    Invalid { index: usize, reason: String },
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::Malformed(reason) => write!(f, "malformed patch: {reason}"),
+            PatchError::TestFailed { index, path } => {
+                write!(f, "test of {path} failed (operation {index})")
+            }
+            PatchError::Invalid { index, reason } => write!(f, "operation {index}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// A single RFC 6902 operation. Paths are JSON pointers
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+impl TryFrom<&serde_json::Value> for PatchOperation {
+    type Error = String;
+
+    fn try_from(op: &serde_json::Value) -> Result<Self, Self::Error> {
+        let field = |name: &str| -> Result<String, String> {
+            op.get(name)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .ok_or_else(|| format!("missing string field {name:?} in {op}"))
+        };
+        let value = || -> Result<Value, String> {
+            op.get("value")
+                .map(|v| v.clone().into())
+                .ok_or_else(|| format!("missing field \"value\" in {op}"))
+        };
+        Ok(match &field("op")?[..] {
+            "add" => PatchOperation::Add {
+                path: field("path")?,
+                value: value()?,
+            },
+            "remove" => PatchOperation::Remove {
+                path: field("path")?,
+            },
+            "replace" => PatchOperation::Replace {
+                path: field("path")?,
+                value: value()?,
+            },
+            "move" => PatchOperation::Move {
+                from: field("from")?,
+                path: field("path")?,
+            },
+            "copy" => PatchOperation::Copy {
+                from: field("from")?,
+                path: field("path")?,
+            },
+            "test" => PatchOperation::Test {
+                path: field("path")?,
+                value: value()?,
+            },
+            other => return Err(format!("unknown op {other:?}")),
+        })
+    }
+}
+
+/// Parse an RFC 6902 JSON Patch document, which is an array of operations
+pub fn parse_patch(patch: &serde_json::Value) -> Result<Vec<PatchOperation>, PatchError> {
+    patch
+        .as_array()
+        .ok_or_else(|| PatchError::Malformed("a patch has to be an array".to_string()))?
+        .iter()
+        .map(|op| PatchOperation::try_from(op).map_err(PatchError::Malformed))
+        .collect()
+}
+
+/// Apply a JSON Patch to a document by turning it into list inserts/deletes and register sets
+/// on the right paths. The generated ops are applied locally and returned so they can be signed
+/// and sent to other replicas. The patch is atomic: if any operation fails (including a `test`)
+/// the document is left untouched and no ops are returned.
+///
+/// Lists map to JSON arrays and structs to JSON objects whose fields cannot be removed, so
+/// replacing a struct needs a value for every field. Anything inside a register is a plain value
+/// so modifying it replaces the value of the register.
+pub fn apply_patch<T: CrdtNode>(
+    doc: &mut T,
+    patch: &[PatchOperation],
+) -> Result<Vec<Op<Value>>, PatchError> {
+    let mut staged = doc.clone();
+    let mut ops = vec![];
+    for (index, operation) in patch.iter().enumerate() {
+        apply_operation(&mut staged, operation, &mut ops).map_err(|err| match err {
+            OperationError::TestFailed => PatchError::TestFailed {
+                index,
+                path: pointer_of(operation).to_string(),
+            },
+            OperationError::Invalid(reason) => PatchError::Invalid { index, reason },
+        })?;
+    }
+    *doc = staged;
+    Ok(ops)
+}
+
+impl<T: CrdtNode + DebugView> BaseCrdt<T> {
+    /// Parse and apply a JSON Patch to our document, see [`apply_patch`]
+    pub fn apply_patch(&mut self, patch: &serde_json::Value) -> Result<Vec<Op<Value>>, PatchError> {
+        apply_patch(&mut self.doc, &parse_patch(patch)?)
+    }
+}
+
+enum OperationError {
+    TestFailed,
+    Invalid(String),
+}
+
+impl From<String> for OperationError {
+    fn from(reason: String) -> Self {
+        OperationError::Invalid(reason)
+    }
+}
+
+fn pointer_of(operation: &PatchOperation) -> &str {
+    match operation {
+        PatchOperation::Add { path, .. }
+        | PatchOperation::Remove { path }
+        | PatchOperation::Replace { path, .. }
+        | PatchOperation::Move { path, .. }
+        | PatchOperation::Copy { path, .. }
+        | PatchOperation::Test { path, .. } => path,
+    }
+}
+
+fn apply_operation<T: CrdtNode>(
+    doc: &mut T,
+    operation: &PatchOperation,
+    ops: &mut Vec<Op<Value>>,
+) -> Result<(), OperationError> {
+    match operation {
+        PatchOperation::Add { path, value } => add(doc, &tokens(path)?, value.clone(), ops)?,
+        PatchOperation::Remove { path } => remove(doc, &tokens(path)?, ops)?,
+        PatchOperation::Replace { path, value } => {
+            let tokens = tokens(path)?;
+            read(doc, &tokens)?;
+            match tokens.split_last() {
+                Some((last, parent)) => {
+                    if let Location::List { path, ids, .. } = doc.locate(parent)? {
+                        // swap the element for a new one in the same spot
+                        let id = ids[list_index(last, ids.len())?];
+                        edit(doc, &path, Edit::Delete { id }, ops)?;
+                        let value = value.clone();
+                        edit(doc, &path, Edit::Insert { after: id, value }, ops)?;
+                        return Ok(());
+                    }
+                    replace(doc, &tokens, value.clone(), ops)?
+                }
+                None => replace(doc, &tokens, value.clone(), ops)?,
+            }
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{from}/")) {
+                return Err(format!("cannot move {from} into itself").into());
+            }
+            let from = tokens(from)?;
+            let value = read(doc, &from)?;
+            remove(doc, &from, ops)?;
+            add(doc, &tokens(path)?, value, ops)?
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = read(doc, &tokens(from)?)?;
+            add(doc, &tokens(path)?, value, ops)?
+        }
+        PatchOperation::Test { path, value } => {
+            // numbers are compared by value, as RFC 6902 asks
+            if read(doc, &tokens(path)?).as_ref() != Ok(value) {
+                return Err(OperationError::TestFailed);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Split a JSON pointer into its unescaped reference tokens
+fn tokens(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(vec![]);
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(format!("{pointer:?} is not a JSON pointer"));
+    };
+    Ok(rest
+        .split('/')
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+pub(crate) fn to_pointer(tokens: &[String]) -> String {
+    tokens
+        .iter()
+        .map(|t| format!("/{}", t.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Index of an existing element of a list
+fn list_index(token: &str, len: usize) -> Result<usize, String> {
+    token
+        .parse::<usize>()
+        .ok()
+        .filter(|idx| *idx < len)
+        .ok_or_else(|| format!("no element {token:?} in a list of length {len}"))
+}
+
+/// Make a local edit and keep the op it generated
+pub(crate) fn edit<T: CrdtNode>(
+    doc: &mut T,
+    path: &[crate::op::PathSegment],
+    edit: Edit,
+    ops: &mut Vec<Op<Value>>,
+) -> Result<(), String> {
+    let (op, _) = doc.edit(path, edit)?;
+    ops.push(op);
+    Ok(())
+}
+
+/// Current value at a pointer
+pub(crate) fn read<T: CrdtNode>(doc: &T, tokens: &[String]) -> Result<Value, String> {
+    doc.view()
+        .into_json()
+        .pointer(&to_pointer(tokens))
+        .map(|v| v.clone().into())
+        .ok_or_else(|| format!("nothing at {}", to_pointer(tokens)))
+}
+
+fn add<T: CrdtNode>(
+    doc: &mut T,
+    tokens: &[String],
+    value: Value,
+    ops: &mut Vec<Op<Value>>,
+) -> Result<(), String> {
+    let Some((last, parent)) = tokens.split_last() else {
+        return replace(doc, tokens, value, ops);
+    };
+    match doc.locate(parent)? {
+        Location::List { path, ids, .. } => {
+            let idx = match &last[..] {
+                "-" => ids.len(),
+                token => token
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|idx| *idx <= ids.len())
+                    .ok_or_else(|| {
+                        format!(
+                            "cannot insert at {token:?} in a list of length {}",
+                            ids.len()
+                        )
+                    })?,
+            };
+            let after = if idx == 0 { ROOT_ID } else { ids[idx - 1] };
+            edit(doc, &path, Edit::Insert { after, value }, ops)
+        }
+        // every field of a struct always exists, so adding one replaces it
+        Location::Object { .. } => replace(doc, tokens, value, ops),
+        Location::Register {
+            path,
+            value: current,
+            mut inner,
+        } => {
+            inner.push(last.to_owned());
+            let mut json = current.into_json();
+            add_plain(&mut json, &inner, value.into_json())?;
+            edit(doc, &path, Edit::Set { value: json.into() }, ops)
+        }
+        Location::Primitive => Err(format!(
+            "cannot add to the primitive at {}",
+            to_pointer(parent)
+        )),
+    }
+}
+
+fn remove<T: CrdtNode>(
+    doc: &mut T,
+    tokens: &[String],
+    ops: &mut Vec<Op<Value>>,
+) -> Result<(), String> {
+    let Some((last, parent)) = tokens.split_last() else {
+        return Err("cannot remove the whole document".to_string());
+    };
+    match doc.locate(parent)? {
+        Location::List { path, ids, .. } => {
+            let id = ids[list_index(last, ids.len())?];
+            edit(doc, &path, Edit::Delete { id }, ops)
+        }
+        Location::Object { .. } => Err(format!(
+            "cannot remove {}, fields of a struct always exist",
+            to_pointer(tokens)
+        )),
+        Location::Register {
+            path,
+            value,
+            mut inner,
+        } => {
+            inner.push(last.to_owned());
+            let mut json = value.into_json();
+            remove_plain(&mut json, &inner)?;
+            edit(doc, &path, Edit::Set { value: json.into() }, ops)
+        }
+        Location::Primitive => Err(format!(
+            "cannot remove from the primitive at {}",
+            to_pointer(parent)
+        )),
+    }
+}
+
+/// Replace whatever is at the pointer with the given value
+fn replace<T: CrdtNode>(
+    doc: &mut T,
+    tokens: &[String],
+    value: Value,
+    ops: &mut Vec<Op<Value>>,
+) -> Result<(), String> {
+    match doc.locate(tokens)? {
+        Location::Register {
+            path,
+            value: current,
+            inner,
+        } => {
+            let value = if inner.is_empty() {
+                value
+            } else {
+                let mut json = current.into_json();
+                *json
+                    .pointer_mut(&to_pointer(&inner))
+                    .ok_or_else(|| format!("nothing at {}", to_pointer(tokens)))? =
+                    value.into_json();
+                json.into()
+            };
+            edit(doc, &path, Edit::Set { value }, ops)
+        }
+        Location::List { path, ids, .. } => {
+            let Value::Array(items) = value else {
+                return Err(format!(
+                    "{} is a list, expected an array",
+                    to_pointer(tokens)
+                ));
+            };
+            for id in ids {
+                edit(doc, &path, Edit::Delete { id }, ops)?;
+            }
+            let mut after = ROOT_ID;
+            for value in items {
+                let (op, _) = doc.edit(&path, Edit::Insert { after, value })?;
+                after = op.id;
+                ops.push(op);
+            }
+            Ok(())
+        }
+        Location::Object { .. } => {
+            let Value::Object(fields) = value else {
+                return Err(format!(
+                    "{} is a struct, expected an object",
+                    to_pointer(tokens)
+                ));
+            };
+            // fields of a struct always exist, so the new value has to say what each of them becomes
+            if let Value::Object(current) = read(doc, tokens)? {
+                if let Some(missing) = current.keys().find(|key| !fields.contains_key(*key)) {
+                    return Err(format!(
+                        "{} is a struct, the new value is missing its field {missing:?}",
+                        to_pointer(tokens)
+                    ));
+                }
+            }
+            for (key, value) in fields {
+                let mut field = tokens.to_vec();
+                field.push(key);
+                replace(doc, &field, value, ops)?;
+            }
+            Ok(())
+        }
+        Location::Primitive => Err(format!(
+            "cannot replace the primitive at {}",
+            to_pointer(tokens)
+        )),
+    }
+}
+
+/// RFC 6902 `add` on a plain JSON value
+fn add_plain(
+    json: &mut serde_json::Value,
+    tokens: &[String],
+    value: serde_json::Value,
+) -> Result<(), String> {
+    let (last, parent) = tokens
+        .split_last()
+        .expect("add_plain needs a pointer to a child");
+    match json.pointer_mut(&to_pointer(parent)) {
+        Some(serde_json::Value::Array(arr)) => {
+            let idx = match &last[..] {
+                "-" => arr.len(),
+                token => token
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|idx| *idx <= arr.len())
+                    .ok_or_else(|| {
+                        format!(
+                            "cannot insert at {token:?} in an array of length {}",
+                            arr.len()
+                        )
+                    })?,
+            };
+            arr.insert(idx, value);
+            Ok(())
+        }
+        Some(serde_json::Value::Object(obj)) => {
+            obj.insert(last.to_owned(), value);
+            Ok(())
+        }
+        _ => Err(format!("cannot add to {}", to_pointer(parent))),
+    }
+}
+
+/// RFC 6902 `remove` on a plain JSON value
+fn remove_plain(json: &mut serde_json::Value, tokens: &[String]) -> Result<(), String> {
+    let (last, parent) = tokens
+        .split_last()
+        .expect("remove_plain needs a pointer to a child");
+    let removed = match json.pointer_mut(&to_pointer(parent)) {
+        Some(serde_json::Value::Array(arr)) => {
+            list_index(last, arr.len()).ok().map(|idx| arr.remove(idx))
+        }
+        Some(serde_json::Value::Object(obj)) => obj.remove(last),
+        _ => None,
+    };
+    removed
+        .map(|_| ())
+        .ok_or_else(|| format!("nothing at {}", to_pointer(tokens)))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{
+        json_crdt::{add_crdt_fields, BaseCrdt, CrdtNode, Value},
+        json_patch::PatchError,
+        keypair::make_keypair,
+        list_crdt::ListCrdt,
+        lww_crdt::LwwRegisterCrdt,
+    };
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Item {
+        name: LwwRegisterCrdt<String>,
+        tags: ListCrdt<char>,
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Player {
+        inventory: ListCrdt<Item>,
+        balance: LwwRegisterCrdt<f64>,
+        meta: LwwRegisterCrdt<Value>,
+    }
+
+    #[test]
+    fn test_patch_to_ops() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Player>::new(&kp1);
+        let mut base2 = BaseCrdt::<Player>::new(&kp2);
+
+        let ops = base1
+            .apply_patch(&json!([
+                { "op": "replace", "path": "/balance", "value": 100 },
+                { "op": "add", "path": "/inventory/-", "value": { "name": "Sword", "tags": ["a"] } },
+                { "op": "add", "path": "/inventory/0", "value": { "name": "Shield", "tags": [] } },
+                { "op": "copy", "from": "/inventory/1/tags/0", "path": "/inventory/0/tags/0" },
+                { "op": "replace", "path": "/inventory/1/name", "value": "Axe" },
+                { "op": "add", "path": "/meta", "value": { "level": 1 } },
+                { "op": "add", "path": "/meta/guild", "value": "red" },
+                { "op": "move", "from": "/meta/level", "path": "/meta/rank" },
+                { "op": "test", "path": "/inventory/0/tags", "value": ["a"] },
+            ]))
+            .unwrap();
+        assert_eq!(
+            base1.doc.view().into_json(),
+            json!({
+                "balance": 100.0,
+                "inventory": [
+                    { "name": "Shield", "tags": ["a"] },
+                    { "name": "Axe", "tags": ["a"] },
+                ],
+                "meta": { "guild": "red", "rank": 1 },
+            })
+        );
+
+        for op in ops {
+            base2.apply(base1.sign(op, &kp1)).unwrap();
+        }
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+
+        let ops = base2
+            .apply_patch(&json!([
+                { "op": "remove", "path": "/inventory/0" },
+                { "op": "replace", "path": "/inventory/0/tags", "value": ["b", "c"] },
+                { "op": "remove", "path": "/meta/guild" },
+            ]))
+            .unwrap();
+        for op in ops {
+            base1.apply(base2.sign(op, &kp2)).unwrap();
+        }
+        assert_eq!(
+            base1.doc.view().into_json(),
+            json!({
+                "balance": 100.0,
+                "inventory": [{ "name": "Axe", "tags": ["b", "c"] }],
+                "meta": { "rank": 1 },
+            })
+        );
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+
+        // replacing a struct replaces every field
+        let ops = base1
+            .apply_patch(&json!([
+                { "op": "replace", "path": "", "value": { "balance": 5, "inventory": [], "meta": null } },
+            ]))
+            .unwrap();
+        for op in ops {
+            base2.apply(base1.sign(op, &kp1)).unwrap();
+        }
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "balance": 5.0, "inventory": [], "meta": null })
+        );
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+    }
+
+    #[test]
+    fn test_patch_is_atomic() {
+        let kp1 = make_keypair();
+        let mut base1 = BaseCrdt::<Player>::new(&kp1);
+        let before = base1.doc.view().into_json();
+
+        let failed_test = base1.apply_patch(&json!([
+            { "op": "replace", "path": "/balance", "value": 100 },
+            { "op": "test", "path": "/balance", "value": 5 },
+        ]));
+        assert_eq!(
+            failed_test.err().unwrap(),
+            PatchError::TestFailed {
+                index: 1,
+                path: "/balance".to_string()
+            }
+        );
+        assert_eq!(base1.doc.view().into_json(), before);
+
+        let wrong_type = base1.apply_patch(&json!([
+            { "op": "add", "path": "/inventory/0", "value": { "name": "Sword", "tags": [] } },
+            { "op": "replace", "path": "/balance", "value": "lots" },
+        ]));
+        assert!(matches!(
+            wrong_type,
+            Err(PatchError::Invalid { index: 1, .. })
+        ));
+        let struct_field = base1.apply_patch(&json!([{ "op": "remove", "path": "/balance" }]));
+        assert!(matches!(
+            struct_field,
+            Err(PatchError::Invalid { index: 0, .. })
+        ));
+        let missing_field = base1.apply_patch(&json!([
+            { "op": "replace", "path": "", "value": { "balance": 5, "meta": null } },
+        ]));
+        assert!(matches!(
+            missing_field,
+            Err(PatchError::Invalid { index: 0, .. })
+        ));
+        let malformed = base1.apply_patch(&json!([{ "op": "frobnicate", "path": "/balance" }]));
+        assert!(matches!(malformed, Err(PatchError::Malformed(_))));
+        assert_eq!(base1.doc.view().into_json(), before);
+    }
+}
@@ -1,8 +1,16 @@
 pub mod debug;
+pub mod diff;
+pub mod enum_crdt;
 pub mod json_crdt;
+pub mod json_patch;
 pub mod keypair;
 pub mod list_crdt;
 pub mod lww_crdt;
 pub mod op;
+#[cfg(feature = "simulator")]
+pub mod simulator;
+pub mod transaction;
+pub mod undo;
+pub mod version;
 
 extern crate self as bft_json_crdt;
@@ -1,12 +1,17 @@
 use crate::{
     debug::debug_path_mismatch,
-    json_crdt::{CrdtNode, OpState, Value},
+    debug::debug_type_mismatch,
+    json_crdt::{
+        ApplyError, ApplyOutcome, ApplyResult, Attribution, Blame, Change, CrdtNode, Edit,
+        Location, OpState, Value,
+    },
     keypair::AuthorId,
+    lww_crdt::{Lamport, LwwOrder},
     op::*,
 };
 use std::{
     cmp::{max, Ordering},
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Debug,
     ops::{Index, IndexMut},
 };
@@ -28,6 +33,12 @@ where
     message_q: HashMap<OpId, Vec<Op<T>>>,
     /// The sequence number of this node
     our_seq: SequenceNumber,
+    /// Lets us find ops and their visible index without scanning the whole list
+    index: PositionIndex,
+    /// IDs of the delete ops that targeted each element, so we know when it got deleted
+    deleted_by: HashMap<OpId, Vec<OpId>>,
+    /// Elements that came with the op that created this list rather than ops of their own
+    initial: HashSet<OpId>,
 }
 
 impl<T> ListCrdt<T>
@@ -43,6 +54,9 @@ where
             ops,
             message_q: HashMap::new(),
             our_seq: 0,
+            index: PositionIndex::new(ROOT_ID),
+            deleted_by: HashMap::new(),
+            initial: HashSet::new(),
         }
     }
 
@@ -61,7 +75,7 @@ where
         // [`OpID`]
         let new_path = join_path(self.path.to_owned(), PathSegment::Index(op.id));
         op.path = new_path;
-        self.apply(op.clone());
+        let _ = self.apply(op.clone());
         op
     }
 
@@ -105,51 +119,171 @@ where
             None,
             join_path(self.path.to_owned(), PathSegment::Index(id)),
         );
-        self.apply(op.clone());
+        let _ = self.apply(op.clone());
         op
     }
 
     /// Find the idx of an operation with the given [`OpID`]
     pub fn find_idx(&self, id: OpId) -> Option<usize> {
-        self.ops.iter().position(|op| op.id == id)
+        self.index.locate(&self.ops, id).map(|(idx, _)| idx)
+    }
+
+    /// Number of visible elements before the op at `idx` in our log
+    fn visible_idx(&self, idx: usize) -> usize {
+        self.index.visible_before(&self.ops, idx)
+    }
+
+    /// Current visible index of the element with the given [`OpId`], if it is visible.
+    /// Inverse of [`ListCrdt::id_at`]
+    pub fn index_of(&self, id: OpId) -> Option<usize> {
+        let (idx, visible) = self.index.locate(&self.ops, id)?;
+        is_visible(&self.ops[idx]).then_some(visible)
+    }
+
+    /// Make a [`Cursor`] for the gap before the visible element at `idx` (`idx == len` is the
+    /// end of the list) that keeps its place as the list is edited
+    pub fn cursor_at(&self, idx: usize, stickiness: Stickiness) -> Option<Cursor> {
+        // id_at counts the root, so it returns the element before the gap
+        let anchor = match stickiness {
+            Stickiness::Left => self.id_at(idx)?,
+            Stickiness::Right if idx == self.index.visible_len() => ROOT_ID,
+            Stickiness::Right => self.id_at(idx + 1)?,
+        };
+        Some(Cursor { anchor, stickiness })
+    }
+
+    /// Current visible index of a [`Cursor`]. Returns `None` if we have not received the
+    /// element it is anchored to yet
+    pub fn resolve(&self, cursor: &Cursor) -> Option<usize> {
+        if cursor.anchor == ROOT_ID {
+            return Some(match cursor.stickiness {
+                Stickiness::Left => 0,
+                Stickiness::Right => self.index.visible_len(),
+            });
+        }
+        let (idx, visible) = self.index.locate(&self.ops, cursor.anchor)?;
+        // a deleted anchor still marks the spot where it was
+        Some(match cursor.stickiness {
+            Stickiness::Left if is_visible(&self.ops[idx]) => visible + 1,
+            _ => visible,
+        })
     }
 
     /// Apply an operation (both local and remote) to this local list CRDT.
     /// Forwards it to a nested CRDT if necessary.
-    pub fn apply(&mut self, op: Op<Value>) -> OpState {
-        if !op.is_valid_hash() {
-            return OpState::ErrHashMismatch;
+    pub fn apply(&mut self, op: Op<Value>) -> ApplyResult {
+        self.apply_observed(op, &mut vec![])
+    }
+
+    /// Same as [`ListCrdt::apply`] but records the visible changes, including those of any queued
+    /// ops that could be integrated as a result
+    pub fn apply_observed(&mut self, op: Op<Value>, changes: &mut Vec<Change>) -> ApplyResult {
+        // haven't reached end yet, navigate to inner CRDT
+        if let Some(op_id) = self.element_of(&op)? {
+            let Some(idx) = self.find_idx(op_id) else {
+                return Err(self.no_element(&op, op_id));
+            };
+            return match self.ops[idx].content.as_mut() {
+                Some(content) => content.apply_observed(op, changes),
+                None => Err(no_content(&op, op_id)),
+            };
         }
 
-        if !ensure_subpath(&self.path, &op.path) {
-            return OpState::ErrPathMismatch;
+        // otherwise, this is just a direct replacement
+        if op.is_deleted && op.origin == ROOT_ID {
+            return Err(root_delete(&op));
         }
+        Ok(self.integrate(into_element(op)?, changes))
+    }
 
-        // haven't reached end yet, navigate to inner CRDT
-        if op.path.len() - 1 > self.path.len() {
-            if let Some(PathSegment::Index(op_id)) = op.path.get(self.path.len()) {
-                let op_id = op_id.to_owned();
-                if let Some(idx) = self.find_idx(op_id) {
-                    if self.ops[idx].content.is_none() {
-                        return OpState::ErrListApplyToEmpty;
-                    } else {
-                        return self.ops[idx].content.as_mut().unwrap().apply(op);
+    /// Check that `ops` can all be applied in order, see [`CrdtNode::check_all`]. Ops for the
+    /// list itself are checked against the elements we have and the ones inserted before them,
+    /// ops for nested CRDTs are checked by the content of the element they are for
+    pub fn check_all(&self, ops: &[Op<Value>]) -> Result<Option<OpId>, ApplyError> {
+        let mut inserted: Vec<Op<T>> = vec![];
+        // ops for each element in the order the elements were first addressed
+        let mut nested: Vec<(OpId, Vec<Op<Value>>)> = vec![];
+        for op in ops {
+            match self.element_of(op)? {
+                Some(id) => {
+                    let known =
+                        self.find_idx(id).is_some() || inserted.iter().any(|new| new.id == id);
+                    if !known {
+                        return Err(self.no_element(op, id));
                     }
-                } else {
-                    debug_path_mismatch(
-                        join_path(self.path.to_owned(), PathSegment::Index(op_id)),
-                        op.path,
-                    );
-                    return OpState::ErrPathMismatch;
-                };
-            } else {
-                debug_path_mismatch(self.path.to_owned(), op.path);
-                return OpState::ErrPathMismatch;
+                    match nested.iter_mut().find(|(element, _)| *element == id) {
+                        Some((_, element_ops)) => element_ops.push(op.clone()),
+                        None => nested.push((id, vec![op.clone()])),
+                    }
+                }
+                None => {
+                    if op.is_deleted && op.origin == ROOT_ID {
+                        return Err(root_delete(op));
+                    }
+                    let new = into_element(op.clone())?;
+                    let origin = new.origin;
+                    if self.find_idx(origin).is_none()
+                        && !inserted.iter().any(|new| new.id == origin)
+                    {
+                        return Ok(Some(origin));
+                    }
+                    if !new.is_deleted {
+                        inserted.push(new);
+                    }
+                }
             }
         }
 
-        // otherwise, this is just a direct replacement
-        self.integrate(op.into())
+        for (id, element_ops) in nested {
+            // elements we have win over inserts of the same element, which are ignored
+            let content = match self.find_idx(id) {
+                Some(idx) => self.ops[idx].content.as_ref(),
+                None => inserted
+                    .iter()
+                    .find(|new| new.id == id)
+                    .and_then(|new| new.content.as_ref()),
+            };
+            let Some(content) = content else {
+                return Err(no_content(&element_ops[0], id));
+            };
+            if let Some(missing) = content.check_all(&element_ops)? {
+                return Ok(Some(missing));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The element whose content an op is for, or `None` if it is for the list itself
+    fn element_of(&self, op: &Op<Value>) -> Result<Option<OpId>, ApplyError> {
+        if let Err(reason) = op.check_hash() {
+            return Err(ApplyError::new(OpState::ErrHashMismatch, op, reason));
+        }
+
+        if !ensure_subpath(&self.path, &op.path) {
+            let reason = format!("op is not under /{}", print_path(self.path.clone()));
+            return Err(ApplyError::new(OpState::ErrPathMismatch, op, reason));
+        }
+
+        if op.path.len() <= self.path.len() + 1 {
+            return Ok(None);
+        }
+        match op.path.get(self.path.len()) {
+            Some(PathSegment::Index(op_id)) => Ok(Some(*op_id)),
+            _ => {
+                debug_path_mismatch(self.path.to_owned(), op.path.clone());
+                let reason = "expected an element id after the path of the list";
+                Err(ApplyError::new(OpState::ErrPathMismatch, op, reason))
+            }
+        }
+    }
+
+    fn no_element(&self, op: &Op<Value>, id: OpId) -> ApplyError {
+        debug_path_mismatch(
+            join_path(self.path.to_owned(), PathSegment::Index(id)),
+            op.path.clone(),
+        );
+        let reason = format!("no element with id {}", &print_hex(&id)[..6]);
+        ApplyError::new(OpState::ErrPathMismatch, op, reason)
     }
 
     /// Main CRDT logic of integrating an op properly into our local log
@@ -159,7 +293,7 @@ where
     /// Effectively, we
     /// 1) find the parent item
     /// 2) find the right spot to insert before the next node
-    fn integrate(&mut self, new_op: Op<T>) -> OpState {
+    fn integrate(&mut self, new_op: Op<T>, changes: &mut Vec<Change>) -> ApplyOutcome {
         let op_id = new_op.id;
         let seq = new_op.sequence_num();
         let origin_id = self.find_idx(new_op.origin);
@@ -169,7 +303,7 @@ where
                 .entry(new_op.origin)
                 .or_default()
                 .push(new_op);
-            return OpState::MissingCausalDependencies;
+            return ApplyOutcome::Queued;
         }
 
         let new_op_parent_idx = origin_id.unwrap();
@@ -177,12 +311,28 @@ where
         // if its a delete operation, we don't need to do much
         self.log_apply(&new_op);
         if new_op.is_deleted {
+            let deletes = self.deleted_by.entry(new_op.origin).or_default();
+            if !deletes.contains(&op_id) {
+                deletes.push(op_id);
+            }
+            let index = self.visible_idx(new_op_parent_idx);
             let op = &mut self.ops[new_op_parent_idx];
+            if is_visible(op) {
+                changes.push(Change::ListDelete {
+                    path: self.path.to_owned(),
+                    index,
+                });
+                self.index.hide(op.id);
+            }
             op.is_deleted = true;
-            return OpState::Ok;
+            return ApplyOutcome::Applied;
         }
 
         // otherwise, we are in an insert case
+        if self.find_idx(op_id).is_some() {
+            return ApplyOutcome::Applied;
+        }
+
         // start looking from right after parent
         // stop when we reach end of document
         let mut i = new_op_parent_idx + 1;
@@ -192,7 +342,7 @@ where
 
             // idempotency
             if op.id == new_op.id {
-                return OpState::Ok;
+                return ApplyOutcome::Applied;
             }
 
             // first, lets compare causal origins
@@ -205,8 +355,9 @@ where
                         Ordering::Greater => break,
                         Ordering::Equal => {
                             // conflict, resolve arbitrarily but deterministically
-                            // tie-break on author id as that is unique
-                            if new_op.author() > op.author() {
+                            // tie-break on author id, then on op id in case a Byzantine author
+                            // reused a sequence number
+                            if (new_op.author(), new_op.id) > (op.author(), op.id) {
                                 break;
                             }
                         }
@@ -219,7 +370,15 @@ where
         }
 
         // insert at i
+        if let Some(content) = new_op.content.as_ref() {
+            changes.push(Change::ListInsert {
+                path: self.path.to_owned(),
+                index: self.visible_idx(i),
+                value: content.view(),
+            });
+        }
         self.ops.insert(i, new_op);
+        self.index.insert(&self.ops, i);
         self.our_seq = max(self.our_seq, seq);
         self.log_ops(Some(op_id));
 
@@ -227,17 +386,121 @@ where
         let dependent_queue = self.message_q.remove(&op_id);
         if let Some(mut q) = dependent_queue {
             for dependent in q.drain(..) {
-                self.integrate(dependent);
+                self.integrate(dependent, changes);
+            }
+        }
+        ApplyOutcome::Applied
+    }
+
+    /// Make a local [`Edit`] to this list or forward it to the element it targets. Returns the op
+    /// along with the edit that reverts it: deleting an insert, or re-inserting deleted content
+    /// right after its tombstone so it ends up in the same spot
+    pub fn edit(&mut self, path: &[PathSegment], edit: Edit) -> Result<(Op<Value>, Edit), String> {
+        if !path.starts_with(&self.path) {
+            return Err(format!(
+                "/{} is not in this list",
+                print_path(path.to_vec())
+            ));
+        }
+
+        if path.len() > self.path.len() {
+            let Some(PathSegment::Index(id)) = path.get(self.path.len()) else {
+                return Err("expected an element id after the path of the list".to_string());
+            };
+            let idx = self
+                .find_idx(*id)
+                .ok_or_else(|| format!("no element with id {}", &print_hex(id)[..6]))?;
+            return match self.ops[idx].content.as_mut() {
+                Some(content) => content.edit(path, edit),
+                None => Err(format!("element {} has no content", &print_hex(id)[..6])),
+            };
+        }
+
+        match edit {
+            Edit::Insert { after, value } => {
+                if self.find_idx(after).is_none() {
+                    return Err(format!("no element with id {}", &print_hex(&after)[..6]));
+                }
+                let op = self.insert(after, value);
+                if self.find_idx(op.id).is_none() {
+                    return Err(format!(
+                        "value does not fit in the list at /{}",
+                        print_path(self.path.clone())
+                    ));
+                }
+                let id = op.id;
+                Ok((op, Edit::Delete { id }))
+            }
+            Edit::Delete { id } => {
+                let value = self
+                    .find_idx(id)
+                    .map(|idx| &self.ops[idx])
+                    .filter(|op| !op.is_deleted)
+                    .and_then(|op| op.content.as_ref())
+                    .map(|content| content.view())
+                    .ok_or_else(|| {
+                        format!("no visible element with id {}", &print_hex(&id)[..6])
+                    })?;
+                Ok((self.delete(id), Edit::Insert { after: id, value }))
             }
+            Edit::Set { .. } | Edit::Restore { .. } => Err(format!(
+                "/{} is a list, it cannot be set",
+                print_path(self.path.clone())
+            )),
         }
-        OpState::Ok
+    }
+
+    /// Find the node that a JSON pointer leads to, starting with the index of an element
+    pub fn locate(&self, tokens: &[String]) -> Result<Location, String> {
+        let visible = self.ops.iter().filter(|op| is_visible(op));
+        let Some((token, rest)) = tokens.split_first() else {
+            return Ok(Location::List {
+                path: self.path.to_owned(),
+                ids: visible.map(|op| op.id).collect(),
+                chars: T::IS_CHAR,
+            });
+        };
+        let element = token
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| visible.clone().nth(idx))
+            .ok_or_else(|| {
+                format!(
+                    "no element {token:?} in the list at /{}",
+                    print_path(self.path.clone())
+                )
+            })?;
+        element.content.as_ref().unwrap().locate(rest)
+    }
+
+    /// Visible elements as they were when only the given ops had been applied. Tombstones are
+    /// kept forever so we only have to check which inserts and deletes are included
+    pub fn view_at(&self, ops: &HashSet<OpId>) -> Vec<Value> {
+        let deleted = |id: &OpId| {
+            self.deleted_by
+                .get(id)
+                .is_some_and(|deletes| deletes.iter().any(|d| ops.contains(d)))
+        };
+        self.ops
+            .iter()
+            .filter(|op| ops.contains(&op.id) || self.initial.contains(&op.id))
+            .filter(|op| !deleted(&op.id))
+            .filter_map(|op| op.content.as_ref())
+            .map(|content| content.view_at(ops))
+            .collect()
+    }
+
+    /// Treat everything currently in the list as part of the op that created it, see
+    /// [`ListCrdt::view_at`]
+    pub(crate) fn mark_initial(&mut self) {
+        self.initial = self.ops.iter().map(|op| op.id).collect();
     }
 
     /// Make an iterator out of list CRDT contents, ignoring deleted items and empty content
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.ops
             .iter()
-            .filter(|op| !op.is_deleted && op.content.is_some())
+            .filter(|op| is_visible(op))
             .map(|op| op.content.as_ref().unwrap())
     }
 
@@ -245,6 +508,15 @@ where
     pub fn view(&self) -> Vec<T> {
         self.iter().map(|i| i.to_owned()).collect()
     }
+
+    /// Visible list elements along with the op that inserted each of them
+    pub fn blame(&self) -> Vec<(Attribution, T)> {
+        self.ops
+            .iter()
+            .filter(|op| is_visible(op))
+            .map(|op| (op.into(), op.content.to_owned().unwrap()))
+            .collect()
+    }
 }
 
 impl<T> Debug for ListCrdt<T>
@@ -273,9 +545,9 @@ where
     fn index(&self, idx: usize) -> &Self::Output {
         let mut i = 0;
         for op in &self.ops {
-            if !op.is_deleted && op.content.is_some() {
+            if let (false, Some(content)) = (op.is_deleted, op.content.as_ref()) {
                 if idx == i {
-                    return op.content.as_ref().unwrap();
+                    return content;
                 }
                 i += 1;
             }
@@ -292,9 +564,9 @@ where
     fn index_mut(&mut self, idx: usize) -> &mut Self::Output {
         let mut i = 0;
         for op in &mut self.ops {
-            if !op.is_deleted && op.content.is_some() {
+            if let (false, Some(content)) = (op.is_deleted, op.content.as_mut()) {
                 if idx == i {
-                    return op.content.as_mut().unwrap();
+                    return content;
                 }
                 i += 1;
             }
@@ -303,12 +575,80 @@ where
     }
 }
 
+fn no_content(op: &Op<Value>, id: OpId) -> ApplyError {
+    ApplyError::new(
+        OpState::ErrListApplyToEmpty,
+        op,
+        format!("element {} has no content", &print_hex(&id)[..6]),
+    )
+}
+
+/// The root element is what the first element is inserted after, every index counts from it
+fn root_delete(op: &Op<Value>) -> ApplyError {
+    ApplyError::new(
+        OpState::ErrListApplyToEmpty,
+        op,
+        "the root element can't be deleted",
+    )
+}
+
+/// Turn an op for the list itself into an element
+fn into_element<T: CrdtNode>(op: Op<Value>) -> Result<Op<T>, ApplyError> {
+    match op.clone().try_into_node() {
+        Ok(op) => Ok(op),
+        Err(reason) => {
+            debug_type_mismatch(reason.clone());
+            Err(ApplyError::new(OpState::ErrMismatchedType, &op, reason))
+        }
+    }
+}
+
 impl<T> CrdtNode for ListCrdt<T>
 where
     T: CrdtNode,
 {
-    fn apply(&mut self, op: Op<Value>) -> OpState {
-        self.apply(op.into())
+    fn apply_observed(&mut self, op: Op<Value>, changes: &mut Vec<Change>) -> ApplyResult {
+        self.apply_observed(op.into(), changes)
+    }
+
+    fn edit(&mut self, path: &[PathSegment], edit: Edit) -> Result<(Op<Value>, Edit), String> {
+        self.edit(path, edit)
+    }
+
+    fn locate(&self, tokens: &[String]) -> Result<Location, String> {
+        self.locate(tokens)
+    }
+
+    fn view_at(&self, ops: &HashSet<OpId>) -> Value {
+        Value::Array(self.view_at(ops))
+    }
+
+    fn blame(&self) -> Blame {
+        Blame::List(
+            self.ops
+                .iter()
+                .filter(|op| is_visible(op))
+                .map(|op| (op.into(), op.content.as_ref().unwrap().blame()))
+                .collect(),
+        )
+    }
+
+    fn check_all(&self, ops: &[Op<Value>]) -> Result<Option<OpId>, ApplyError> {
+        self.check_all(ops)
+    }
+
+    /// Ops for the content of an element are bounded by that content
+    fn max_seq(&self, op: &Op<Value>, justified: SequenceNumber) -> Option<SequenceNumber> {
+        let content = match op.path.get(self.path.len()) {
+            Some(PathSegment::Index(id)) if op.path.len() > self.path.len() + 1 => self
+                .find_idx(*id)
+                .and_then(|idx| self.ops[idx].content.as_ref()),
+            _ => None,
+        };
+        match content {
+            Some(content) => content.max_seq(op, justified),
+            None => Lamport::max_seq(justified),
+        }
     }
 
     fn view(&self) -> Value {
@@ -320,6 +660,178 @@ where
     }
 }
 
+/// Which side of the gap a [`Cursor`] sticks to when content is inserted right at it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stickiness {
+    /// Stay right after the element on the left, content inserted at the cursor ends up after it
+    Left,
+    /// Stay right before the element on the right, content inserted at the cursor ends up
+    /// before it
+    Right,
+}
+
+/// A position in a [`ListCrdt`] that stays put as the list is edited concurrently, e.g. a caret
+/// or one end of a selection. It is anchored to the element on its left or right depending on its
+/// [`Stickiness`]. If that element gets deleted the cursor stays where the element used to be.
+/// The start (for [`Stickiness::Left`]) and end (for [`Stickiness::Right`]) of the list are
+/// represented with an anchor of [`ROOT_ID`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    pub anchor: OpId,
+    pub stickiness: Stickiness,
+}
+
+fn is_visible<T: CrdtNode>(op: &Op<T>) -> bool {
+    !op.is_deleted && op.content.is_some()
+}
+
+/// Ops are only ever inserted into a list so we can cut it into blocks of consecutive ops and
+/// remember which block each op is in. Looking up an op means walking the blocks and scanning a
+/// single one. Blocks are kept at about `sqrt(n)` ops each, and so are the number of blocks, which
+/// makes that `O(sqrt(n))`.
+#[derive(Clone)]
+struct PositionIndex {
+    /// Blocks in list order
+    blocks: Vec<Block>,
+    /// Key of the block each op is in
+    block_of: HashMap<OpId, usize>,
+    next_key: usize,
+}
+
+#[derive(Clone)]
+struct Block {
+    key: usize,
+    len: usize,
+    visible: usize,
+}
+
+/// Blocks are never cut shorter than this, walking many tiny blocks costs more than scanning a few
+const MIN_BLOCK_LEN: usize = 16;
+
+/// Length blocks are cut to in a list of `n` ops. They are split in half once they grow past
+/// twice that, and all of them are cut again once there are more than twice as many as this
+fn block_len(n: usize) -> usize {
+    n.isqrt().max(MIN_BLOCK_LEN)
+}
+
+impl PositionIndex {
+    fn new(root: OpId) -> Self {
+        Self {
+            blocks: vec![Block {
+                key: 0,
+                len: 1,
+                visible: 0,
+            }],
+            block_of: HashMap::from([(root, 0)]),
+            next_key: 1,
+        }
+    }
+
+    fn visible_len(&self) -> usize {
+        self.blocks.iter().map(|b| b.visible).sum()
+    }
+
+    /// Position of the op with the given id in the log along with the number of visible ops
+    /// before it
+    fn locate<T: CrdtNode>(&self, ops: &[Op<T>], id: OpId) -> Option<(usize, usize)> {
+        let key = *self.block_of.get(&id)?;
+        let mut start = 0;
+        let mut visible = 0;
+        for block in &self.blocks {
+            if block.key == key {
+                for (i, op) in ops[start..start + block.len].iter().enumerate() {
+                    if op.id == id {
+                        return Some((start + i, visible));
+                    }
+                    visible += is_visible(op) as usize;
+                }
+                return None;
+            }
+            start += block.len;
+            visible += block.visible;
+        }
+        None
+    }
+
+    /// Number of visible ops before the given position in the log
+    fn visible_before<T: CrdtNode>(&self, ops: &[Op<T>], idx: usize) -> usize {
+        let mut start = 0;
+        let mut visible = 0;
+        for block in &self.blocks {
+            if idx < start + block.len {
+                break;
+            }
+            start += block.len;
+            visible += block.visible;
+        }
+        visible + ops[start..idx].iter().filter(|op| is_visible(op)).count()
+    }
+
+    /// Bookkeeping for an op that was just inserted at `idx`
+    fn insert<T: CrdtNode>(&mut self, ops: &[Op<T>], idx: usize) {
+        // the op joins the block that contains the op before it
+        let mut start = 0;
+        let mut b = 0;
+        while idx > start + self.blocks[b].len {
+            start += self.blocks[b].len;
+            b += 1;
+        }
+        let block = &mut self.blocks[b];
+        block.len += 1;
+        block.visible += is_visible(&ops[idx]) as usize;
+        self.block_of.insert(ops[idx].id, block.key);
+
+        if block.len > 2 * block_len(ops.len()) {
+            let half = block.len / 2;
+            let moved = &ops[start + half..start + block.len];
+            let new = Block {
+                key: self.next_key,
+                len: moved.len(),
+                visible: moved.iter().filter(|op| is_visible(op)).count(),
+            };
+            block.len = half;
+            block.visible -= new.visible;
+            for op in moved {
+                self.block_of.insert(op.id, new.key);
+            }
+            self.next_key += 1;
+            self.blocks.insert(b + 1, new);
+        }
+
+        // blocks cut while the list was short are too many to walk once it has grown
+        if self.blocks.len() > 2 * ops.len().div_ceil(block_len(ops.len())) {
+            self.rebuild(ops);
+        }
+    }
+
+    /// Cut the whole list into blocks of [`block_len`] ops again. There are about `sqrt(n)`
+    /// inserts between rebuilds for every block, so this is `O(1)` per insert on average
+    fn rebuild<T: CrdtNode>(&mut self, ops: &[Op<T>]) {
+        self.blocks.clear();
+        for chunk in ops.chunks(block_len(ops.len())) {
+            let key = self.next_key;
+            self.next_key += 1;
+            for op in chunk {
+                self.block_of.insert(op.id, key);
+            }
+            self.blocks.push(Block {
+                key,
+                len: chunk.len(),
+                visible: chunk.iter().filter(|op| is_visible(op)).count(),
+            });
+        }
+    }
+
+    /// Bookkeeping for a visible op that is about to be deleted
+    fn hide(&mut self, id: OpId) {
+        if let Some(key) = self.block_of.get(&id) {
+            if let Some(block) = self.blocks.iter_mut().find(|b| b.key == *key) {
+                block.visible -= 1;
+            }
+        }
+    }
+}
+
 #[cfg(feature = "logging-base")]
 use crate::debug::DebugView;
 #[cfg(feature = "logging-base")]
@@ -348,7 +860,13 @@ where
 
 #[cfg(test)]
 mod test {
-    use crate::{json_crdt::OpState, keypair::make_author, list_crdt::ListCrdt, op::ROOT_ID};
+    use crate::{
+        json_crdt::{ApplyOutcome, OpState},
+        keypair::make_author,
+        list_crdt::{ListCrdt, Stickiness},
+        op::ROOT_ID,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn test_list_simple() {
@@ -365,11 +883,22 @@ mod test {
         let mut list = ListCrdt::<i64>::new(make_author(1), vec![]);
         let op = list.insert(ROOT_ID, 1);
         for _ in 1..10 {
-            assert_eq!(list.apply(op.clone()), OpState::Ok);
+            assert_eq!(list.apply(op.clone()), Ok(ApplyOutcome::Applied));
         }
         assert_eq!(list.view(), vec![1]);
     }
 
+    #[test]
+    fn test_list_empty_path() {
+        let mut list1 = ListCrdt::<char>::new(make_author(1), vec![]);
+        let mut list2 = ListCrdt::<char>::new(make_author(2), vec![]);
+        let mut op = list1.insert(ROOT_ID, 'a');
+        // the path is not covered by the op ID, so anyone can send this
+        op.path = vec![];
+        assert_eq!(list2.apply(op), Ok(ApplyOutcome::Applied));
+        assert_eq!(list2.view(), vec!['a']);
+    }
+
     #[test]
     fn test_list_delete() {
         let mut list = ListCrdt::<char>::new(make_author(1), vec![]);
@@ -395,18 +924,18 @@ mod test {
         let mut list1 = ListCrdt::<char>::new(make_author(1), vec![]);
         let mut list2 = ListCrdt::new(make_author(2), vec![]);
         let _1_a = list1.insert(ROOT_ID, 'a');
-        assert_eq!(list2.apply(_1_a.clone()), OpState::Ok);
+        assert_eq!(list2.apply(_1_a.clone()), Ok(ApplyOutcome::Applied));
         let _2_b = list2.insert(_1_a.id, 'b');
-        assert_eq!(list1.apply(_2_b.clone()), OpState::Ok);
+        assert_eq!(list1.apply(_2_b.clone()), Ok(ApplyOutcome::Applied));
 
         let _2_d = list2.insert(ROOT_ID, 'd');
         let _2_y = list2.insert(_2_b.id, 'y');
         let _1_x = list1.insert(_2_b.id, 'x');
 
         // create artificial delay, then apply out of order
-        assert_eq!(list2.apply(_1_x), OpState::Ok);
-        assert_eq!(list1.apply(_2_y), OpState::Ok);
-        assert_eq!(list1.apply(_2_d), OpState::Ok);
+        assert_eq!(list2.apply(_1_x), Ok(ApplyOutcome::Applied));
+        assert_eq!(list1.apply(_2_y), Ok(ApplyOutcome::Applied));
+        assert_eq!(list1.apply(_2_d), Ok(ApplyOutcome::Applied));
 
         assert_eq!(list1.view(), vec!['d', 'a', 'b', 'y', 'x']);
         assert_eq!(list1.view(), list2.view());
@@ -417,11 +946,11 @@ mod test {
         let mut list1 = ListCrdt::<char>::new(make_author(1), vec![]);
         let mut list2 = ListCrdt::new(make_author(2), vec![]);
         let _1_a = list1.insert(ROOT_ID, 'a');
-        assert_eq!(list2.apply(_1_a.clone()), OpState::Ok);
+        assert_eq!(list2.apply(_1_a.clone()), Ok(ApplyOutcome::Applied));
         let _2_b = list2.insert(_1_a.id, 'b');
         let del_1_a = list1.delete(_1_a.id);
-        assert_eq!(list1.apply(_2_b), OpState::Ok);
-        assert_eq!(list2.apply(del_1_a), OpState::Ok);
+        assert_eq!(list1.apply(_2_b), Ok(ApplyOutcome::Applied));
+        assert_eq!(list2.apply(del_1_a), Ok(ApplyOutcome::Applied));
 
         assert_eq!(list1.view(), vec!['b']);
         assert_eq!(list1.view(), list2.view());
@@ -437,4 +966,131 @@ mod test {
 
         assert_eq!(list1.view(), vec!['a', 'b', 'c', 'd']);
     }
+
+    #[test]
+    fn test_list_blame() {
+        let mut list1 = ListCrdt::<char>::new(make_author(1), vec![]);
+        let mut list2 = ListCrdt::new(make_author(2), vec![]);
+        let _a = list1.insert(ROOT_ID, 'a');
+        let _b = list1.insert(_a.id, 'b');
+        assert_eq!(list2.apply(_a.clone()), Ok(ApplyOutcome::Applied));
+        assert_eq!(list2.apply(_b.clone()), Ok(ApplyOutcome::Applied));
+        let _c = list2.insert(_a.id, 'c');
+        list2.delete(_b.id);
+
+        let blame = list2
+            .blame()
+            .into_iter()
+            .map(|(attribution, c)| (c, attribution.author, attribution.seq))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blame,
+            vec![('a', make_author(1), 1), ('c', make_author(2), 3)]
+        );
+    }
+
+    #[test]
+    fn test_list_cursors() {
+        let mut list1 = ListCrdt::<char>::new(make_author(1), vec![]);
+        let mut list2 = ListCrdt::<char>::new(make_author(2), vec![]);
+        let _a = list1.insert(ROOT_ID, 'a');
+        let _b = list1.insert(_a.id, 'b');
+        let _c = list1.insert(_b.id, 'c');
+        for op in [&_a, &_b, &_c] {
+            list2.apply(op.clone()).unwrap();
+        }
+
+        // caret between 'a' and 'b' on list1
+        let left = list1.cursor_at(1, Stickiness::Left).unwrap();
+        let right = list1.cursor_at(1, Stickiness::Right).unwrap();
+        let end = list1.cursor_at(3, Stickiness::Right).unwrap();
+        assert_eq!(list1.resolve(&left), Some(1));
+        assert_eq!(list1.resolve(&right), Some(1));
+
+        // someone else types at the caret and at the start
+        let _x = list2.insert(_a.id, 'x');
+        let _y = list2.insert(ROOT_ID, 'y');
+        list1.apply(_x).unwrap();
+        list1.apply(_y).unwrap();
+        assert_eq!(list1.view(), vec!['y', 'a', 'x', 'b', 'c']);
+        assert_eq!(list1.resolve(&left), Some(2));
+        assert_eq!(list1.resolve(&right), Some(3));
+        assert_eq!(list1.resolve(&end), Some(5));
+        assert_eq!(list1.index_of(_b.id), Some(3));
+
+        // anchors survive being deleted
+        list1.delete(_a.id);
+        list1.delete(_b.id);
+        assert_eq!(list1.view(), vec!['y', 'x', 'c']);
+        assert_eq!(list1.index_of(_b.id), None);
+        assert_eq!(list1.resolve(&left), Some(1));
+        assert_eq!(list1.resolve(&right), Some(2));
+
+        // cursors from other replicas can't be resolved before their anchor arrives
+        let _z = list2.insert(_c.id, 'z');
+        let remote = list2.cursor_at(6, Stickiness::Left).unwrap();
+        assert_eq!(list1.resolve(&remote), None);
+    }
+
+    #[test]
+    fn test_list_delete_root() {
+        let mut list1 = ListCrdt::<char>::new(make_author(1), vec![]);
+        let mut list2 = ListCrdt::<char>::new(make_author(2), vec![]);
+        let _a = list1.insert(ROOT_ID, 'a');
+        let _b = list1.insert(_a.id, 'b');
+
+        // deleting the root would shift every index by one
+        let _root = list2.delete(ROOT_ID);
+        assert_eq!(
+            list1.apply(_root.clone()).map_err(|e| e.kind),
+            Err(OpState::ErrListApplyToEmpty)
+        );
+        assert_eq!(
+            list1.check_all(&[_root]).map_err(|e| e.kind),
+            Err(OpState::ErrListApplyToEmpty)
+        );
+
+        let cursor = list1.cursor_at(1, Stickiness::Left).unwrap();
+        assert_eq!(list1.resolve(&cursor), Some(1));
+        assert_eq!(list1.id_at(2), Some(_b.id));
+        list1.insert_idx(2, 'c');
+        assert_eq!(list1.view(), vec!['a', 'b', 'c']);
+    }
+
+    #[test]
+    fn test_list_index_matches_scan() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut list = ListCrdt::<i64>::new(make_author(1), vec![]);
+        let mut ids = vec![ROOT_ID];
+        for i in 0..3000 {
+            let after = ids[rng.gen_range(0..ids.len())];
+            ids.push(list.insert(after, i).id);
+            if rng.gen_bool(0.3) {
+                list.delete(ids[rng.gen_range(1..ids.len())]);
+            }
+        }
+
+        let mut visible = 0;
+        for (idx, op) in list.ops.iter().enumerate() {
+            assert_eq!(list.find_idx(op.id), Some(idx));
+            if !op.is_deleted && op.content.is_some() {
+                assert_eq!(list.index_of(op.id), Some(visible));
+                assert_eq!(list.id_at(visible + 1), Some(op.id));
+                visible += 1;
+            } else {
+                assert_eq!(list.index_of(op.id), None);
+            }
+        }
+        let end = list.cursor_at(visible, Stickiness::Right).unwrap();
+        assert_eq!(list.resolve(&end), Some(visible));
+
+        // about sqrt(n) blocks of about sqrt(n) ops
+        let n = list.ops.len();
+        assert!(list.index.blocks.len() <= 2 * n.div_ceil(n.isqrt()));
+        assert!(list
+            .index
+            .blocks
+            .iter()
+            .all(|block| block.len <= 2 * n.isqrt()));
+    }
 }
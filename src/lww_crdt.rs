@@ -1,26 +1,179 @@
-use crate::debug::DebugView;
-use crate::json_crdt::{CrdtNode, OpState, Value};
-use crate::op::{join_path, print_path, Op, PathSegment, SequenceNumber};
+use crate::debug::{debug_path_mismatch, debug_type_mismatch, DebugView};
+use crate::json_crdt::{
+    ApplyError, ApplyOutcome, ApplyResult, Attribution, Blame, Change, CrdtNode, Edit,
+    IntoCrdtNode, Location, OpState, Value,
+};
+use crate::op::{join_path, print_hex, print_path, Op, OpId, PathSegment, SequenceNumber, ROOT_ID};
 use std::cmp::{max, Ordering};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::keypair::AuthorId;
 
+/// Picks the sequence numbers of the sets of a [`LwwRegisterCrdt`], which decide the winner out
+/// of concurrent sets: the higher one wins and ties go to the lower author
+pub trait LwwOrder {
+    /// Sequence number for our next set, given the highest one the register has seen
+    fn next_seq(max_seen: SequenceNumber) -> SequenceNumber;
+
+    /// The highest sequence number a set may carry when the highest one in its causal past is
+    /// `justified`, see [`CrdtNode::max_seq`]. Without a bound a single set could claim a huge
+    /// sequence number and win the register from then on. `None` if sequence numbers don't
+    /// count past the causal past, in which case [`LwwOrder::check_seq`] has to bound them
+    fn max_seq(justified: SequenceNumber) -> Option<SequenceNumber> {
+        Some(justified.saturating_add(1))
+    }
+
+    /// Check the sequence number of a set against what the receiver knows before it is applied
+    fn check_seq(seq: SequenceNumber) -> Result<(), String> {
+        let _ = seq;
+        Ok(())
+    }
+}
+
+/// Order sets by a Lamport clock. The replica that set the register the most often wins, even
+/// if another replica's set happened later in real time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lamport;
+
+impl LwwOrder for Lamport {
+    fn next_seq(max_seen: SequenceNumber) -> SequenceNumber {
+        max_seen + 1
+    }
+}
+
+/// Where [`Hybrid`] gets the time from and how far ahead of it a set may be
+pub trait WallClock {
+    /// How many milliseconds ahead of our clock a set may be stamped
+    const MAX_DRIFT_MS: u64;
+
+    /// Milliseconds since the Unix epoch
+    fn now_ms() -> u64;
+}
+
+/// The system clock, allowing a minute of drift between replicas
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl WallClock for SystemClock {
+    const MAX_DRIFT_MS: u64 = 60_000;
+
+    fn now_ms() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
+}
+
+/// Order sets by a hybrid logical clock, so the set made last in real time wins as long as the
+/// clocks of the replicas roughly agree. Sequence numbers hold the milliseconds of the physical
+/// clock in their upper 48 bits and a logical counter in the lower 16, which keeps them above
+/// every sequence number the register has seen, just like a Lamport clock.
+///
+/// Stamps are bounded by the clock rather than by their causal past: a set stamped more than
+/// [`WallClock::MAX_DRIFT_MS`] ahead of our clock is rejected with [`OpState::ErrClockDrift`]
+/// instead of winning the register for that long. Replicas that receive it before and after
+/// their clocks catch up would not agree on it, so the bound should be well above the drift
+/// between honest replicas
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Hybrid<C: WallClock = SystemClock>(PhantomData<C>);
+
+impl<C: WallClock> Hybrid<C> {
+    /// Bits of the sequence number that hold the logical counter
+    pub const LOGICAL_BITS: u32 = 16;
+
+    /// The physical time in milliseconds a sequence number was stamped at
+    pub fn physical_ms(seq: SequenceNumber) -> u64 {
+        seq >> Self::LOGICAL_BITS
+    }
+}
+
+impl<C: WallClock> LwwOrder for Hybrid<C> {
+    fn next_seq(max_seen: SequenceNumber) -> SequenceNumber {
+        max(C::now_ms() << Self::LOGICAL_BITS, max_seen + 1)
+    }
+
+    fn max_seq(_justified: SequenceNumber) -> Option<SequenceNumber> {
+        None
+    }
+
+    fn check_seq(seq: SequenceNumber) -> Result<(), String> {
+        let ahead = Self::physical_ms(seq).saturating_sub(C::now_ms());
+        if ahead > C::MAX_DRIFT_MS {
+            return Err(format!(
+                "stamped {ahead}ms ahead of our clock, at most {}ms is allowed",
+                C::MAX_DRIFT_MS
+            ));
+        }
+        Ok(())
+    }
+}
+
 /// A simple delete-wins, last-writer-wins (LWW) register CRDT.
-/// Basically only for adding support for primitives within a more complex CRDT
-#[derive(Clone)]
-pub struct LwwRegisterCrdt<T>
+/// Basically only for adding support for primitives within a more complex CRDT.
+/// Concurrent sets are ordered by a [`Lamport`] clock unless another [`LwwOrder`] is given.
+///
+/// Every set and [clear](LwwRegisterCrdt::clear) names the op that decided the register when it
+/// was made as its origin. A set only shows once it has seen the latest clear through that
+/// chain, so a clear beats every set made concurrently with it no matter their sequence number
+pub struct LwwRegisterCrdt<T, O = Lamport>
 where
     T: CrdtNode,
+    O: LwwOrder,
 {
     /// Public key for this node
     pub our_id: AuthorId,
     /// Path to this CRDT
     pub path: Vec<PathSegment>,
-    /// Internal value of this CRDT. We wrap it in an Op to retain the author/sequence metadata
-    value: Op<T>,
     /// The sequence number of this node
     our_seq: SequenceNumber,
+    /// Every set and clear that has been applied, including the ones that lost. They keep their
+    /// own IDs so we can tell which of them are part of a past version and route nested ops to
+    /// their value
+    history: Vec<Op<T>>,
+    /// Index in `history` of every op by its ID
+    index: HashMap<OpId, usize>,
+    /// Indices in `history` of the ops made on top of each op, by the ID of their origin
+    children: HashMap<OpId, Vec<usize>>,
+    /// Index in `history` of the clear that beats every other clear, if there is one
+    latest_clear: Option<usize>,
+    /// Indices in `history` of the ops made on top of `latest_clear`, following the chain of
+    /// origins. Only these sets can show
+    seen_clear: HashSet<usize>,
+    /// Index in `history` of the latest set that has seen `latest_clear`
+    latest_set: Option<usize>,
+    /// Index of the op in `history` that decides the value, if the register has been set or
+    /// cleared at all
+    current: Option<usize>,
+    /// The set this register was created with, if it came with the op that created it
+    initial: Option<OpId>,
+    order: PhantomData<O>,
+}
+
+// derived, this would require the order to be Clone as well
+impl<T, O> Clone for LwwRegisterCrdt<T, O>
+where
+    T: CrdtNode,
+    O: LwwOrder,
+{
+    fn clone(&self) -> Self {
+        LwwRegisterCrdt {
+            our_id: self.our_id,
+            path: self.path.clone(),
+            our_seq: self.our_seq,
+            history: self.history.clone(),
+            index: self.index.clone(),
+            children: self.children.clone(),
+            latest_clear: self.latest_clear,
+            seen_clear: self.seen_clear.clone(),
+            latest_set: self.latest_set,
+            current: self.current,
+            initial: self.initial,
+            order: PhantomData,
+        }
+    }
 }
 
 impl<T> LwwRegisterCrdt<T>
@@ -28,23 +181,54 @@ where
     T: CrdtNode,
 {
     /// Create a new register CRDT with the given [`AuthorID`] (it should be unique)
-    pub fn new(id: AuthorId, path: Vec<PathSegment>) -> LwwRegisterCrdt<T> {
+    pub fn new(id: AuthorId, path: Vec<PathSegment>) -> Self {
+        Self::with_order(id, path)
+    }
+}
+
+impl<T, O> LwwRegisterCrdt<T, O>
+where
+    T: CrdtNode,
+    O: LwwOrder,
+{
+    /// Create a new register CRDT that orders its sets by `O`, e.g. [`Hybrid`]
+    pub fn with_order(id: AuthorId, path: Vec<PathSegment>) -> Self {
         LwwRegisterCrdt {
             our_id: id,
             path,
-            value: Op::make_root(),
             our_seq: 0,
+            history: vec![],
+            index: HashMap::new(),
+            children: HashMap::new(),
+            latest_clear: None,
+            seen_clear: HashSet::new(),
+            latest_set: None,
+            current: None,
+            initial: None,
+            order: PhantomData,
         }
     }
 
     /// Sets the current value of the register
     pub fn set<U: Into<Value>>(&mut self, content: U) -> Op<Value> {
+        self.write(Some(content.into()))
+    }
+
+    /// Clears the register so it shows as null. Sets made concurrently with the clear lose to
+    /// it, only sets that have seen it can fill the register again
+    pub fn clear(&mut self) -> Op<Value> {
+        self.write(None)
+    }
+
+    /// Make a set, or a clear if there is no content, on top of the op that currently decides
+    /// the register
+    fn write(&mut self, content: Option<Value>) -> Op<Value> {
         let mut op = Op::new(
-            self.value.id,
+            self.current().map_or(ROOT_ID, |op| op.id),
             self.our_id,
-            self.our_seq + 1,
-            false,
-            Some(content.into()),
+            O::next_seq(self.our_seq),
+            content.is_none(),
+            content,
             self.path.to_owned(),
         );
 
@@ -52,92 +236,463 @@ where
         // [`OpID`]
         let new_path = join_path(self.path.to_owned(), PathSegment::Index(op.id));
         op.path = new_path;
-        self.apply(op.clone());
+        let _ = self.apply(op.clone());
         op
     }
 
+    /// Make a local [`Edit::Set`] or [`Edit::Restore`] to this register, or forward the edit to
+    /// the current value if it is a CRDT itself. Returns the op along with the edit that restores
+    /// the value it replaced
+    pub fn edit(&mut self, path: &[PathSegment], edit: Edit) -> Result<(Op<Value>, Edit), String> {
+        if path.len() > self.path.len() && path.starts_with(&self.path) {
+            return match self.get_mut() {
+                Some(value) => value.edit(path, edit),
+                None => Err(format!(
+                    "/{} has not been set",
+                    print_path(self.path.clone())
+                )),
+            };
+        }
+        if path != self.path {
+            return Err(format!(
+                "/{} is not in this register",
+                print_path(path.to_vec())
+            ));
+        }
+
+        let value = match edit {
+            Edit::Set { value } => value,
+            Edit::Restore { value, over } => {
+                // compare op IDs, every replica shares the `our_id` of a nested register
+                if self.current().is_none_or(|op| op.id != over) {
+                    return Err(format!(
+                        "/{} was changed by someone else",
+                        print_path(self.path.clone())
+                    ));
+                }
+                value
+            }
+            Edit::Insert { .. } | Edit::Delete { .. } => {
+                return Err(format!(
+                    "/{} is a register, it cannot be inserted into or deleted from",
+                    print_path(self.path.clone())
+                ))
+            }
+        };
+
+        // check the type up front, a local op that does not fit would be silently dropped
+        IntoCrdtNode::<T>::into_node(value.clone(), self.our_id, self.path.clone())?;
+        let old = self.view().into();
+        let op = self.set(value);
+        let over = op.id;
+        Ok((op, Edit::Restore { value: old, over }))
+    }
+
     /// Apply an operation (both local and remote) to this local register CRDT.
-    pub fn apply(&mut self, op: Op<Value>) -> OpState {
-        if !op.is_valid_hash() {
-            return OpState::ErrHashMismatch;
+    /// Forwards it to the value of one of our sets if it goes deeper than the set itself
+    pub fn apply(&mut self, op: Op<Value>) -> ApplyResult {
+        self.apply_observed(op, &mut vec![])
+    }
+
+    /// Same as [`LwwRegisterCrdt::apply`] but records a [`Change`] if the op wins
+    pub fn apply_observed(&mut self, op: Op<Value>, changes: &mut Vec<Change>) -> ApplyResult {
+        if let Err(reason) = op.check_hash() {
+            return Err(ApplyError::new(OpState::ErrHashMismatch, &op, reason));
         }
 
-        let op: Op<T> = op.into();
+        // sets are at our path plus their own id, anything longer is for the value of a set
+        if op.path.len() > self.path.len() + 1 {
+            return self.apply_nested(op, changes);
+        }
+
+        let op: Op<T> = match op.clone().try_into_node() {
+            Ok(op) => op,
+            Err(reason) => {
+                debug_type_mismatch(reason.clone());
+                return Err(ApplyError::new(OpState::ErrMismatchedType, &op, reason));
+            }
+        };
         let seq = op.sequence_num();
+        if self.index.contains_key(&op.id) {
+            return Ok(ApplyOutcome::Applied);
+        }
+        if let Err(reason) = O::check_seq(seq) {
+            return Err(ApplyError::new(OpState::ErrClockDrift, &op, reason));
+        }
+
+        // take most recent update by sequence number, unless a clear hides it
+        let old = self.view().into();
+        self.history.push(op);
+        self.integrate(self.history.len() - 1);
+        let current = self.latest_set.or(self.latest_clear);
+        if current != self.current {
+            self.current = current;
+            changes.push(Change::RegisterSet {
+                path: self.path.to_owned(),
+                old,
+                new: self.view().into(),
+            });
+        }
+
+        // update bookkeeping
+        self.our_seq = max(self.our_seq, seq);
+        Ok(ApplyOutcome::Applied)
+    }
+
+    /// Forward an op to the value of the set it targets. Values that lost to a later set still
+    /// take their ops so that every replica ends up with the same history for them no matter
+    /// when it learned they lost, but only changes to the current value are visible
+    fn apply_nested(&mut self, op: Op<Value>, changes: &mut Vec<Change>) -> ApplyResult {
+        let target = self.set_of(&op)?;
+        let Some(&idx) = self.index.get(&target) else {
+            return Err(no_set(&op, target));
+        };
+
+        let mut hidden = vec![];
+        let changes = if self.current == Some(idx) {
+            changes
+        } else {
+            &mut hidden
+        };
+        match self.history[idx].content.as_mut() {
+            Some(value) => value.apply_observed(op, changes),
+            None => Err(no_value(&op, target)),
+        }
+    }
+
+    /// The set whose value an op longer than the path of a set is for
+    fn set_of(&self, op: &Op<Value>) -> Result<OpId, ApplyError> {
+        match op.path.get(self.path.len()) {
+            Some(PathSegment::Index(id)) if op.path.starts_with(&self.path) => Ok(*id),
+            _ => {
+                debug_path_mismatch(self.path.to_owned(), op.path.clone());
+                let reason = format!(
+                    "op is not under a set of /{}",
+                    print_path(self.path.clone())
+                );
+                Err(ApplyError::new(OpState::ErrPathMismatch, op, reason))
+            }
+        }
+    }
+
+    /// Check that `ops` can all be applied in order, see [`CrdtNode::check_all`]. Ops for the
+    /// value of a set are checked by that value, whether we have the set or it comes before them
+    pub fn check_all(&self, ops: &[Op<Value>]) -> Result<Option<OpId>, ApplyError> {
+        let mut sets: Vec<Op<T>> = vec![];
+        // ops for the value of each set in the order the sets were first addressed
+        let mut nested: Vec<(OpId, Vec<Op<Value>>)> = vec![];
+        for op in ops {
+            if let Err(reason) = op.check_hash() {
+                return Err(ApplyError::new(OpState::ErrHashMismatch, op, reason));
+            }
+            if op.path.len() > self.path.len() + 1 {
+                let target = self.set_of(op)?;
+                if !self.index.contains_key(&target) && !sets.iter().any(|set| set.id == target) {
+                    return Err(no_set(op, target));
+                }
+                match nested.iter_mut().find(|(set, _)| *set == target) {
+                    Some((_, set_ops)) => set_ops.push(op.clone()),
+                    None => nested.push((target, vec![op.clone()])),
+                }
+                continue;
+            }
 
-        // take most recent update by sequence number
-        match seq.cmp(&self.our_seq) {
-            Ordering::Greater => {
-                self.value = Op {
-                    id: self.value.id,
-                    ..op
-                };
+            let set: Op<T> = match op.clone().try_into_node() {
+                Ok(set) => set,
+                Err(reason) => {
+                    debug_type_mismatch(reason.clone());
+                    return Err(ApplyError::new(OpState::ErrMismatchedType, op, reason));
+                }
+            };
+            if self.index.contains_key(&set.id) || sets.iter().any(|old| old.id == set.id) {
+                continue;
+            }
+            if let Err(reason) = O::check_seq(set.sequence_num()) {
+                return Err(ApplyError::new(OpState::ErrClockDrift, op, reason));
             }
-            Ordering::Equal => {
-                // if we are equal, tie break on author
-                if op.author() < self.value.author() {
-                    // we want to keep id constant so replace everything but id
-                    self.value = Op {
-                        id: self.value.id,
-                        ..op
-                    };
+            sets.push(set);
+        }
+
+        for (target, set_ops) in nested {
+            let set = match self.index.get(&target) {
+                Some(&idx) => &self.history[idx],
+                None => sets.iter().find(|set| set.id == target).unwrap(),
+            };
+            let Some(value) = set.content.as_ref() else {
+                return Err(no_value(&set_ops[0], target));
+            };
+            if let Some(missing) = value.check_all(&set_ops)? {
+                return Ok(Some(missing));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Update the latest clear and the latest set that has seen it for the op at `idx`, which
+    /// was just added to our history
+    fn integrate(&mut self, idx: usize) {
+        let op = &self.history[idx];
+        let (id, origin, is_clear) = (op.id, op.origin, op.is_deleted);
+        self.index.insert(id, idx);
+        self.children.entry(origin).or_default().push(idx);
+
+        match self.latest_clear {
+            Some(clear) if !is_clear || !Self::wins(&self.history[idx], &self.history[clear]) => {
+                let seen = self.history[clear].id == origin
+                    || self
+                        .index
+                        .get(&origin)
+                        .is_some_and(|origin| self.seen_clear.contains(origin));
+                if seen {
+                    self.seen_clear.insert(idx);
+                    self.consider(idx);
+                    // ops that arrived before this one have seen the clear through it
+                    self.see(id);
+                }
+            }
+            None if !is_clear => self.consider(idx),
+            _ => {
+                // a new latest clear hides every set that has not seen it
+                self.latest_clear = Some(idx);
+                self.seen_clear = HashSet::new();
+                self.latest_set = None;
+                self.see(id);
+            }
+        }
+    }
+
+    /// Mark everything in our history made on top of `id` as having seen the latest clear
+    fn see(&mut self, id: OpId) {
+        for idx in self.descendants(id) {
+            if self.seen_clear.insert(idx) {
+                self.consider(idx);
+            }
+        }
+    }
+
+    /// Make the op at `idx` the latest set if it is a set that beats the current one
+    fn consider(&mut self, idx: usize) {
+        let op = &self.history[idx];
+        if !op.is_deleted
+            && self
+                .latest_set
+                .is_none_or(|set| Self::wins(op, &self.history[set]))
+        {
+            self.latest_set = Some(idx);
+        }
+    }
+
+    /// Indices in our history of the ops made on top of `id`, following the chain of origins
+    fn descendants(&self, id: OpId) -> HashSet<usize> {
+        let mut found = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            for &idx in self.children.get(&id).into_iter().flatten() {
+                // origins are part of the hashed ID so they can't loop, this is just for safety
+                if found.insert(idx) {
+                    stack.push(self.history[idx].id);
                 }
             }
-            Ordering::Less => {} // LWW, ignore if its outdate
+        }
+        found
+    }
+
+    /// Whether `op` replaces `old`
+    fn wins(op: &Op<T>, old: &Op<T>) -> bool {
+        match op.sequence_num().cmp(&old.sequence_num()) {
+            Ordering::Greater => true,
+            // if we are equal, tie break on author, and on the op id if a Byzantine author
+            // reused a sequence number
+            Ordering::Equal => (op.author(), op.id) < (old.author(), old.id),
+            Ordering::Less => false, // LWW, ignore if its outdate
+        }
+    }
+
+    /// The value of the register when only the given ops had been applied
+    pub fn view_at(&self, ops: &HashSet<OpId>) -> Value {
+        let winner = self.winner(|id| ops.contains(id));
+        match winner.and_then(|idx| self.history[idx].content.as_ref()) {
+            Some(value) => value.view_at(ops),
+            None => Value::Null,
+        }
+    }
+
+    /// The op that set the current value or cleared the register, if there is one
+    pub fn last_writer(&self) -> Option<Attribution> {
+        self.current().map(Attribution::from)
+    }
+
+    /// Index of the op that decides the value out of the initial value and the included ops
+    /// from our history: the latest set that has seen the latest clear, or else that clear
+    fn winner(&self, included: impl Fn(&OpId) -> bool) -> Option<usize> {
+        let ops = (0..self.history.len()).filter(|idx| {
+            let id = self.history[*idx].id;
+            included(&id) || self.initial == Some(id)
+        });
+        let latest = |a: Option<usize>, b: usize| match a {
+            Some(a) if !Self::wins(&self.history[b], &self.history[a]) => Some(a),
+            _ => Some(b),
         };
+        let clear = ops
+            .clone()
+            .filter(|idx| self.history[*idx].is_deleted)
+            .fold(None, latest);
+        let seen = clear.map(|clear| self.descendants(self.history[clear].id));
+        let set = ops
+            .filter(|idx| !self.history[*idx].is_deleted)
+            .filter(|idx| seen.as_ref().is_none_or(|seen| seen.contains(idx)))
+            .fold(None, latest);
+        set.or(clear)
+    }
 
-        // update bookkeeping
-        self.our_seq = max(self.our_seq, seq);
-        OpState::Ok
+    /// Treat the current value as part of the op that created this register, see
+    /// [`LwwRegisterCrdt::view_at`]
+    pub(crate) fn mark_initial(&mut self) {
+        self.initial = self.history.last().map(|op| op.id);
+    }
+
+    /// The set that currently wins
+    fn current(&self) -> Option<&Op<T>> {
+        self.current.map(|idx| &self.history[idx])
+    }
+
+    /// The current value of the register
+    pub fn get(&self) -> Option<&T> {
+        self.current().and_then(|op| op.content.as_ref())
+    }
+
+    /// The current value of the register, for making local ops on it when it is a CRDT itself.
+    /// Those ops are routed to this value on other replicas too, even if it has been replaced
+    /// by the time they arrive
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        let idx = self.current?;
+        self.history[idx].content.as_mut()
     }
 
     fn view(&self) -> Option<T> {
-        self.value.content.to_owned()
+        self.get().cloned()
     }
 }
 
-impl<T> CrdtNode for LwwRegisterCrdt<T>
+fn no_set(op: &Op<Value>, id: OpId) -> ApplyError {
+    let reason = format!("no value was set by {}", &print_hex(&id)[..6]);
+    ApplyError::new(OpState::ErrPathMismatch, op, reason)
+}
+
+fn no_value(op: &Op<Value>, id: OpId) -> ApplyError {
+    let reason = format!("{} did not set a value", &print_hex(&id)[..6]);
+    ApplyError::new(OpState::ErrPathMismatch, op, reason)
+}
+
+impl<T, O> CrdtNode for LwwRegisterCrdt<T, O>
 where
+    O: LwwOrder,
     T: CrdtNode,
 {
-    fn apply(&mut self, op: Op<Value>) -> OpState {
-        self.apply(op.into())
+    fn apply_observed(&mut self, op: Op<Value>, changes: &mut Vec<Change>) -> ApplyResult {
+        self.apply_observed(op.into(), changes)
+    }
+
+    fn edit(&mut self, path: &[PathSegment], edit: Edit) -> Result<(Op<Value>, Edit), String> {
+        self.edit(path, edit)
+    }
+
+    /// Tokens lead into the current value if it is a CRDT itself. Otherwise it is a plain value
+    /// and any remaining tokens point into it
+    fn locate(&self, tokens: &[String]) -> Result<Location, String> {
+        if let Some(value) = self.get().filter(|_| !tokens.is_empty()) {
+            if value.locate(&[]) != Ok(Location::Primitive) {
+                return value.locate(tokens);
+            }
+        }
+        Ok(Location::Register {
+            path: self.path.to_owned(),
+            value: self.view().into(),
+            inner: tokens.to_vec(),
+        })
     }
 
     fn view(&self) -> Value {
         self.view().into()
     }
 
+    fn view_at(&self, ops: &HashSet<OpId>) -> Value {
+        self.view_at(ops)
+    }
+
+    fn blame(&self) -> Blame {
+        Blame::Register {
+            writer: self.last_writer(),
+            value: self.view().into(),
+        }
+    }
+
+    fn check_all(&self, ops: &[Op<Value>]) -> Result<Option<OpId>, ApplyError> {
+        self.check_all(ops)
+    }
+
+    /// Our own sets are bounded by `O`, ops for the value of a set by that value
+    fn max_seq(&self, op: &Op<Value>, justified: SequenceNumber) -> Option<SequenceNumber> {
+        if op.path.len() <= self.path.len() + 1 {
+            return O::max_seq(justified);
+        }
+        let value = match op.path.get(self.path.len()) {
+            Some(PathSegment::Index(id)) => self
+                .index
+                .get(id)
+                .and_then(|&idx| self.history[idx].content.as_ref()),
+            _ => None,
+        };
+        match value {
+            Some(value) => value.max_seq(op, justified),
+            None => Lamport::max_seq(justified),
+        }
+    }
+
     fn new(id: AuthorId, path: Vec<PathSegment>) -> Self {
-        Self::new(id, path)
+        Self::with_order(id, path)
     }
 }
 
-impl<T> DebugView for LwwRegisterCrdt<T>
+impl<T, O> DebugView for LwwRegisterCrdt<T, O>
 where
+    O: LwwOrder,
     T: CrdtNode + DebugView,
 {
     fn debug_view(&self, indent: usize) -> String {
         let spacing = " ".repeat(indent);
         let path_str = print_path(self.path.clone());
-        let inner = self.value.debug_view(indent + 2);
+        let inner = match self.current() {
+            Some(op) => op.debug_view(indent + 2),
+            None => Op::<T>::make_root().debug_view(indent + 2),
+        };
         format!("LWW Register CRDT @ /{path_str}\n{spacing}{inner}")
     }
 }
 
-impl<T> Debug for LwwRegisterCrdt<T>
+impl<T, O> Debug for LwwRegisterCrdt<T, O>
 where
+    O: LwwOrder,
     T: CrdtNode,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.value.id)
+        write!(f, "{:?}", self.current().map_or(ROOT_ID, |op| op.id))
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::LwwRegisterCrdt;
-    use crate::{json_crdt::OpState, keypair::make_author};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::{Hybrid, LwwRegisterCrdt, WallClock};
+    use crate::{
+        json_crdt::{add_crdt_fields, ApplyOutcome, BaseCrdt, CrdtNode, OpState, Value},
+        keypair::{make_author, make_keypair},
+        list_crdt::ListCrdt,
+        op::ROOT_ID,
+    };
+    use serde_json::json;
 
     #[test]
     fn test_lww_simple() {
@@ -157,19 +712,41 @@ mod test {
         let _b = register1.set('b');
         let _c = register2.set('c');
         assert_eq!(register2.view(), Some('c'));
-        assert_eq!(register1.apply(_c), OpState::Ok);
-        assert_eq!(register2.apply(_b), OpState::Ok);
-        assert_eq!(register2.apply(_a), OpState::Ok);
+        assert_eq!(register1.apply(_c), Ok(ApplyOutcome::Applied));
+        assert_eq!(register2.apply(_b), Ok(ApplyOutcome::Applied));
+        assert_eq!(register2.apply(_a), Ok(ApplyOutcome::Applied));
         assert_eq!(register1.view(), Some('b'));
         assert_eq!(register2.view(), Some('b'));
     }
 
+    #[test]
+    fn test_lww_last_writer() {
+        let mut register1 = LwwRegisterCrdt::<char>::new(make_author(1), vec![]);
+        let mut register2 = LwwRegisterCrdt::<char>::new(make_author(2), vec![]);
+        assert_eq!(register1.last_writer(), None);
+        let _a = register1.set('a');
+        let _b = register2.set('b');
+        register2.apply(_a.clone()).unwrap();
+        let writer = register2.last_writer().unwrap();
+        assert_eq!(
+            (writer.id, writer.author, writer.seq),
+            (_a.id, make_author(1), 1)
+        );
+
+        // a later set from the losing author takes over
+        register1.apply(_b).unwrap();
+        let _c = register2.set('c');
+        register1.apply(_c.clone()).unwrap();
+        assert_eq!(register1.last_writer(), register2.last_writer());
+        assert_eq!(register1.last_writer().unwrap().id, _c.id);
+    }
+
     #[test]
     fn test_lww_idempotence() {
         let mut register = LwwRegisterCrdt::new(make_author(1), vec![]);
         let op = register.set(1);
         for _ in 1..10 {
-            assert_eq!(register.apply(op.clone()), OpState::Ok);
+            assert_eq!(register.apply(op.clone()), Ok(ApplyOutcome::Applied));
         }
         assert_eq!(register.view(), Some(1));
     }
@@ -180,13 +757,169 @@ mod test {
         let mut register2 = LwwRegisterCrdt::new(make_author(2), vec![]);
         let _a = register1.set('a');
         let _b = register2.set('b');
-        assert_eq!(register1.apply(_b), OpState::Ok);
-        assert_eq!(register2.apply(_a), OpState::Ok);
+        assert_eq!(register1.apply(_b), Ok(ApplyOutcome::Applied));
+        assert_eq!(register2.apply(_a), Ok(ApplyOutcome::Applied));
         let _c = register1.set('c');
         let _d = register2.set('d');
-        assert_eq!(register2.apply(_c), OpState::Ok);
-        assert_eq!(register1.apply(_d), OpState::Ok);
+        assert_eq!(register2.apply(_c), Ok(ApplyOutcome::Applied));
+        assert_eq!(register1.apply(_d), Ok(ApplyOutcome::Applied));
         assert_eq!(register1.view(), register2.view());
         assert_eq!(register1.view(), Some('c'));
     }
+
+    #[test]
+    fn test_lww_nested_values() {
+        let mut register1 = LwwRegisterCrdt::<ListCrdt<char>>::new(make_author(1), vec![]);
+        let mut register2 = LwwRegisterCrdt::<ListCrdt<char>>::new(make_author(2), vec![]);
+        let _ab = register1.set(json!(["a", "b"]));
+        register2.apply(_ab).unwrap();
+        let _c = register2.get_mut().unwrap().insert(ROOT_ID, 'c');
+        assert_eq!(register1.apply(_c), Ok(ApplyOutcome::Applied));
+        assert_eq!(register1.view().unwrap().view(), vec!['c', 'a', 'b']);
+
+        // the list is replaced while someone is still editing it, their op is kept out of sight
+        let _x = register1.set(json!(["x"]));
+        let _d = register2.get_mut().unwrap().insert(ROOT_ID, 'd');
+        assert_eq!(register1.apply(_d.clone()), Ok(ApplyOutcome::Applied));
+        assert_eq!(register2.apply(_x), Ok(ApplyOutcome::Applied));
+        assert_eq!(register1.view().unwrap().view(), vec!['x']);
+        assert_eq!(register2.view().unwrap().view(), vec!['x']);
+
+        // ops for a value that was never set go nowhere
+        let mut register3 = LwwRegisterCrdt::<ListCrdt<char>>::new(make_author(3), vec![]);
+        assert_eq!(
+            register3.apply(_d).map_err(|e| e.kind),
+            Err(OpState::ErrPathMismatch)
+        );
+        assert!(register3.get().is_none());
+    }
+
+    #[test]
+    fn test_lww_clear() {
+        let mut register1 = LwwRegisterCrdt::new(make_author(1), vec![]);
+        let mut register2 = LwwRegisterCrdt::new(make_author(2), vec![]);
+        let _a = register1.set('a');
+        register2.apply(_a.clone()).unwrap();
+
+        // register2 has set the register more often, its sets still lose to the clear
+        let _clear = register1.clear();
+        let _b = register2.set('b');
+        let _c = register2.set('c');
+        assert!(_c.seq > _clear.seq);
+        assert_eq!(register1.view(), None);
+        assert_eq!(register1.apply(_c.clone()), Ok(ApplyOutcome::Applied));
+        assert_eq!(register1.apply(_b.clone()), Ok(ApplyOutcome::Applied));
+        assert_eq!(register2.apply(_clear.clone()), Ok(ApplyOutcome::Applied));
+        assert_eq!(register1.view(), None);
+        assert_eq!(register2.view(), None);
+        assert_eq!(register2.last_writer().unwrap().id, _clear.id);
+
+        // a set made after the clear fills the register again
+        let _d = register2.set('d');
+        register1.apply(_d.clone()).unwrap();
+        assert_eq!(register1.view(), Some('d'));
+        assert_eq!(register2.view(), Some('d'));
+
+        // past versions see the clear too
+        let before = HashSet::from([_a.id]);
+        let cleared = HashSet::from([_a.id, _b.id, _c.id, _clear.id]);
+        assert_eq!(register1.view_at(&before), json!('a').into());
+        assert_eq!(register1.view_at(&cleared), Value::Null);
+    }
+
+    #[test]
+    fn test_lww_many_sets_after_clear() {
+        let mut register1 = LwwRegisterCrdt::new(make_author(1), vec![]);
+        let mut register2 = LwwRegisterCrdt::new(make_author(2), vec![]);
+        let mut ops = vec![register1.clear()];
+        ops.extend((0..5000).map(|i| register1.set(i)));
+
+        // sets that arrive before the clear they were made on top of still show once it arrives
+        for op in ops.iter().rev() {
+            assert_eq!(register2.apply(op.clone()), Ok(ApplyOutcome::Applied));
+        }
+        assert_eq!(register1.view(), Some(4999));
+        assert_eq!(register2.view(), Some(4999));
+        let all = ops.iter().map(|op| op.id).collect();
+        assert_eq!(register2.view_at(&all), json!(4999).into());
+    }
+
+    static NOW_MS: AtomicU64 = AtomicU64::new(1_000_000);
+
+    struct TestClock;
+
+    impl WallClock for TestClock {
+        const MAX_DRIFT_MS: u64 = 1_000;
+
+        fn now_ms() -> u64 {
+            NOW_MS.load(Ordering::SeqCst)
+        }
+    }
+
+    type HybridRegister = LwwRegisterCrdt<char, Hybrid<TestClock>>;
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Clocked {
+        name: HybridRegister,
+    }
+
+    #[test]
+    fn test_lww_hybrid_clock() {
+        let mut register1 = HybridRegister::with_order(make_author(1), vec![]);
+        let mut register2 = HybridRegister::with_order(make_author(2), vec![]);
+
+        // register1 sets a lot while register2 is offline, then register2 sets a bit later
+        let sets = (0..10).map(|_| register1.set('a')).collect::<Vec<_>>();
+        NOW_MS.fetch_add(10, Ordering::SeqCst);
+        let _b = register2.set('b');
+        for op in sets {
+            assert_eq!(register2.apply(op), Ok(ApplyOutcome::Applied));
+        }
+        assert_eq!(register1.apply(_b), Ok(ApplyOutcome::Applied));
+        assert_eq!(register1.view(), Some('b'));
+        assert_eq!(register2.view(), Some('b'));
+
+        // our stamps keep increasing even when the clock goes back
+        NOW_MS.fetch_sub(500, Ordering::SeqCst);
+        let _c = register1.set('c');
+        assert!(_c.seq > register2.last_writer().unwrap().seq);
+        assert_eq!(register2.apply(_c), Ok(ApplyOutcome::Applied));
+        assert_eq!(register2.view(), Some('c'));
+        NOW_MS.fetch_add(500, Ordering::SeqCst);
+
+        // a set stamped far in the future can't take the register over
+        let mut _d = register1.set('d');
+        _d.seq += 5_000 << Hybrid::<TestClock>::LOGICAL_BITS;
+        _d.id = _d.hash_to_id();
+        assert_eq!(
+            register2.apply(_d).map_err(|e| e.kind),
+            Err(OpState::ErrClockDrift)
+        );
+        assert_eq!(register2.view(), Some('c'));
+    }
+
+    #[test]
+    fn test_lww_hybrid_clock_in_document() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Clocked>::new(&kp1);
+        let mut base2 = BaseCrdt::<Clocked>::new(&kp2);
+
+        // stamps don't count past the causal past, so they are not sequence jumps
+        let _a = base1.doc.name.set('a');
+        assert!(_a.seq > 1 << Hybrid::<TestClock>::LOGICAL_BITS);
+        assert_eq!(base2.apply(base1.sign(_a, &kp1)), Ok(ApplyOutcome::Applied));
+        assert_eq!(base2.doc.name.view(), Some('a'));
+
+        // the register checks them against our clock instead
+        let mut _b = base1.doc.name.set('b');
+        _b.seq += 5_000 << Hybrid::<TestClock>::LOGICAL_BITS;
+        _b.id = _b.hash_to_id();
+        assert_eq!(
+            base2.apply(base1.sign(_b, &kp1)).map_err(|e| e.kind),
+            Err(OpState::ErrClockDrift)
+        );
+        assert_eq!(base2.doc.name.view(), Some('a'));
+    }
 }
@@ -112,6 +112,23 @@ where
 
 /// Conversion from Op<Value> -> Op<T> given that T is a CRDT that can be created from a JSON value
 impl Op<Value> {
+    /// Same as [`Op::into`] but fails instead of dropping content that has the wrong type
+    pub fn try_into_node<T: CrdtNodeFromValue + CrdtNode>(self) -> Result<Op<T>, String> {
+        let content = match self.content {
+            Some(inner_content) => Some(inner_content.into_node(self.id, self.path.clone())?),
+            None => None,
+        };
+        Ok(Op {
+            content,
+            origin: self.origin,
+            author: self.author,
+            seq: self.seq,
+            path: self.path,
+            is_deleted: self.is_deleted,
+            id: self.id,
+        })
+    }
+
     pub fn into<T: CrdtNodeFromValue + CrdtNode>(self) -> Op<T> {
         let content = if let Some(inner_content) = self.content {
             match inner_content.into_node(self.id, self.path.clone()) {
@@ -208,17 +225,27 @@ where
 
     /// Rehashes the contents to make sure it matches the ID
     pub fn is_valid_hash(&self) -> bool {
+        self.check_hash().is_ok()
+    }
+
+    /// Same as [`Op::is_valid_hash`] but explains what is wrong
+    pub fn check_hash(&self) -> Result<(), String> {
         // make sure content is only none for deletion events
         if self.content.is_none() && !self.is_deleted {
-            return false;
+            return Err("op has no content but is not a deletion".to_string());
         }
 
         // try to avoid expensive sig check if early fail
-        let res = self.hash_to_id() == self.id;
-        if !res {
+        let computed = self.hash_to_id();
+        if computed != self.id {
             self.debug_hash_failure();
+            return Err(format!(
+                "expected id {} but contents hash to {}",
+                print_hex(&self.id),
+                print_hex(&computed)
+            ));
         }
-        res
+        Ok(())
     }
 
     /// Special constructor for defining the sentinel root node
@@ -0,0 +1,376 @@
+//! A deterministic network of [`BaseCrdt`] replicas for testing convergence. Everything that
+//! is random (keys, edits, delays, duplicates, partitions and what Byzantine peers do) comes
+//! from a single seed, so a failing run can be replayed exactly by running it with the same seed.
+//!
+//! Messages are delayed by a random number of ticks, which also reorders them, and may be
+//! delivered more than once. Partitions hold messages between the groups until they heal, they
+//! are never lost. Honest peers relay every message they accept to everyone else, so messages
+//! reach every honest peer eventually as long as one honest peer got them. They also relay
+//! messages that fork their author's history, as evidence of the fork.
+//!
+//! Only compiled with the `simulator` feature, run its tests with `cargo test --features simulator`.
+
+use std::collections::HashSet;
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    debug::DebugView,
+    json_crdt::{ApplyOutcome, BaseCrdt, CrdtNode, OpState, SignedOp, Value},
+    keypair::{Ed25519KeyPair, KeyPair, SignedDigest},
+    op::Op,
+};
+
+/// How a peer treats the messages it sends. Every peer edits the document the same way,
+/// Byzantine peers only differ in what they send out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Behaviour {
+    /// Sends its ops to everyone and relays the messages it accepts
+    Honest,
+    /// Makes a second, different edit from the same state as each of its own, signs it at the
+    /// same position in its hash chain and sends each version to a different subset of peers.
+    /// Honest peers keep the version they got first, so they may not converge, but they all
+    /// catch the fork once the relayed versions reach them, see [`BaseCrdt::has_forked`]
+    Equivocate,
+    /// Also sends ops made up in the name of other peers, signed with its own key
+    Forge,
+    /// Also sends ops that depend on messages that don't exist and ops with huge sequence numbers
+    Flood,
+    /// Keeps some of its ops to itself while still sending the ops that depend on them
+    Withhold,
+}
+
+/// Settings for a [`Simulator`]
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    /// Behaviour of each peer
+    pub peers: Vec<Behaviour>,
+    /// Chance that a peer makes an edit in a tick
+    pub edit_chance: f64,
+    /// Messages arrive up to this many ticks after they are sent
+    pub max_delay: u64,
+    /// Chance that a message is delivered twice
+    pub duplicate_chance: f64,
+    /// Chance per tick that the network splits in two, or heals if it is split
+    pub partition_chance: f64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            peers: vec![Behaviour::Honest; 3],
+            edit_chance: 0.5,
+            max_delay: 5,
+            duplicate_chance: 0.1,
+            partition_chance: 0.05,
+        }
+    }
+}
+
+/// A replica in the simulated network
+pub struct Peer<T: CrdtNode> {
+    pub crdt: BaseCrdt<T>,
+    pub behaviour: Behaviour,
+    keypair: Ed25519KeyPair,
+    /// Messages we have accepted, so we relay them once
+    seen: HashSet<SignedDigest>,
+}
+
+/// A message on its way to a peer
+struct Envelope {
+    from: usize,
+    to: usize,
+    deliver_at: u64,
+    op: SignedOp,
+}
+
+/// Runs a number of [`BaseCrdt`] replicas over a seeded virtual network
+pub struct Simulator<T: CrdtNode> {
+    seed: u64,
+    rng: StdRng,
+    config: SimConfig,
+    peers: Vec<Peer<T>>,
+    in_flight: Vec<Envelope>,
+    now: u64,
+    /// The group of each peer while the network is partitioned
+    groups: Option<Vec<usize>>,
+}
+
+/// Seeds to run a simulation with. Set `SIM_SEED` to replay a single failing seed
+pub fn seeds(count: u64) -> Vec<u64> {
+    match std::env::var("SIM_SEED").ok().and_then(|s| s.parse().ok()) {
+        Some(seed) => vec![seed],
+        None => (0..count).collect(),
+    }
+}
+
+impl<T: CrdtNode + DebugView> Simulator<T> {
+    /// Create the peers of `config`, with keys drawn from `seed`
+    pub fn new(seed: u64, config: SimConfig) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let peers = config
+            .peers
+            .iter()
+            .map(|behaviour| {
+                let keypair = Ed25519KeyPair::generate(&mut rng);
+                Peer {
+                    crdt: BaseCrdt::new(&keypair),
+                    behaviour: *behaviour,
+                    keypair,
+                    seen: HashSet::new(),
+                }
+            })
+            .collect();
+        Self {
+            seed,
+            rng,
+            config,
+            peers,
+            in_flight: vec![],
+            now: 0,
+            groups: None,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn peers(&self) -> &[Peer<T>] {
+        &self.peers
+    }
+
+    /// Indices of the honest peers
+    pub fn honest(&self) -> Vec<usize> {
+        (0..self.peers.len())
+            .filter(|&i| self.peers[i].behaviour == Behaviour::Honest)
+            .collect()
+    }
+
+    /// Whether any messages are still on their way
+    pub fn is_idle(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Let a peer make a local edit and send it out. `edit` is given the document and the
+    /// random number generator of the simulation and returns the op it made, if any
+    pub fn edit(
+        &mut self,
+        peer: usize,
+        mut edit: impl FnMut(&mut T, &mut StdRng) -> Option<Op<Value>>,
+    ) {
+        // the other version is made on a copy so it starts from the same state
+        let twin = match self.peers[peer].behaviour {
+            Behaviour::Equivocate => {
+                let mut copy = self.peers[peer].crdt.doc.clone();
+                edit(&mut copy, &mut self.rng)
+            }
+            _ => None,
+        };
+        let Some(op) = edit(&mut self.peers[peer].crdt.doc, &mut self.rng) else {
+            return;
+        };
+        let p = &mut self.peers[peer];
+        let signed = p.crdt.sign(op, &p.keypair);
+        p.seen.insert(signed.signed_digest);
+        let twin = twin.map(|twin| {
+            SignedOp::from_chained_op(twin, &p.keypair, signed.depends_on.clone(), signed.chain)
+        });
+        self.send_own(peer, signed, twin);
+    }
+
+    /// Split the network into groups, one per peer. Messages between groups are held until
+    /// [`Simulator::heal`] is called
+    pub fn partition(&mut self, groups: Vec<usize>) {
+        assert_eq!(groups.len(), self.peers.len(), "one group per peer");
+        self.groups = Some(groups);
+    }
+
+    /// Undo a partition, held messages are delivered as they are due
+    pub fn heal(&mut self) {
+        self.groups = None;
+    }
+
+    /// Run for a number of ticks. Each tick every peer may make an edit, the network may split
+    /// or heal, and the messages that are due are delivered in a random order
+    pub fn run(
+        &mut self,
+        ticks: usize,
+        mut edit: impl FnMut(&mut T, &mut StdRng) -> Option<Op<Value>>,
+    ) {
+        for _ in 0..ticks {
+            if self.rng.gen_bool(self.config.partition_chance) {
+                match self.groups {
+                    Some(_) => self.heal(),
+                    None => {
+                        let groups = (0..self.peers.len())
+                            .map(|_| self.rng.gen_range(0..2))
+                            .collect();
+                        self.partition(groups);
+                    }
+                }
+            }
+            for peer in 0..self.peers.len() {
+                if self.rng.gen_bool(self.config.edit_chance) {
+                    self.edit(peer, &mut edit);
+                }
+            }
+            self.tick();
+        }
+    }
+
+    /// Deliver the messages that are due and move the clock forward
+    pub fn tick(&mut self) {
+        let mut due = vec![];
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            let envelope = &self.in_flight[i];
+            if envelope.deliver_at <= self.now && self.can_reach(envelope) {
+                due.push(self.in_flight.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        due.shuffle(&mut self.rng);
+        for envelope in due {
+            self.deliver(envelope);
+        }
+        self.now += 1;
+    }
+
+    /// Heal the network and deliver everything that is still on its way
+    pub fn settle(&mut self) {
+        self.heal();
+        while !self.is_idle() {
+            self.tick();
+        }
+    }
+
+    /// Panics with the seed of the run unless every honest peer shows the same document
+    pub fn assert_converged(&self) {
+        let honest = self.honest();
+        let Some(&first) = honest.first() else {
+            return;
+        };
+        let expected = self.peers[first].crdt.doc.view();
+        for &i in &honest[1..] {
+            let view = self.peers[i].crdt.doc.view();
+            assert!(
+                view == expected,
+                "peers {first} and {i} diverged, rerun with SIM_SEED={}\n{expected}\n{view}",
+                self.seed
+            );
+        }
+    }
+
+    /// Panics with the seed of the run unless every honest peer has caught `peer` signing two
+    /// different messages at the same height
+    pub fn assert_fork_detected(&self, peer: usize) {
+        let author = self.peers[peer].crdt.id;
+        for i in self.honest() {
+            assert!(
+                self.peers[i].crdt.has_forked(&author),
+                "peer {i} did not catch peer {peer} forking, rerun with SIM_SEED={}",
+                self.seed
+            );
+        }
+    }
+
+    fn can_reach(&self, envelope: &Envelope) -> bool {
+        self.groups
+            .as_ref()
+            .is_none_or(|groups| groups[envelope.from] == groups[envelope.to])
+    }
+
+    fn deliver(&mut self, envelope: Envelope) {
+        let peer = &mut self.peers[envelope.to];
+        let digest = envelope.op.signed_digest;
+        // a message that forks its author's history is relayed as evidence, so every honest peer
+        // catches the fork and not only those that got both versions
+        let relay = match peer.crdt.apply(envelope.op.clone()) {
+            Ok(ApplyOutcome::Applied | ApplyOutcome::Queued) => true,
+            Err(err) => err.kind == OpState::ErrForkedHistory,
+        };
+        if relay && peer.behaviour == Behaviour::Honest && peer.seen.insert(digest) {
+            self.broadcast(envelope.to, &envelope.op);
+        }
+    }
+
+    /// Queue a message for every other peer, with a random delay and maybe a duplicate
+    fn send(&mut self, from: usize, to: usize, op: SignedOp) {
+        if from == to {
+            return;
+        }
+        let copies = if self.rng.gen_bool(self.config.duplicate_chance) {
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let deliver_at = self.now + self.rng.gen_range(0..=self.config.max_delay);
+            self.in_flight.push(Envelope {
+                from,
+                to,
+                deliver_at,
+                op: op.clone(),
+            });
+        }
+    }
+
+    fn broadcast(&mut self, from: usize, op: &SignedOp) {
+        for to in 0..self.peers.len() {
+            self.send(from, to, op.clone());
+        }
+    }
+
+    /// Send out an op we made ourselves, misbehaving as the peer is meant to
+    fn send_own(&mut self, from: usize, op: SignedOp, twin: Option<SignedOp>) {
+        match (self.peers[from].behaviour, twin) {
+            (Behaviour::Equivocate, Some(twin)) => {
+                let mut others = (0..self.peers.len())
+                    .filter(|&to| to != from)
+                    .collect::<Vec<_>>();
+                others.shuffle(&mut self.rng);
+                let split = others.len() / 2;
+                for (i, to) in others.into_iter().enumerate() {
+                    let version = if i < split { &twin } else { &op };
+                    self.send(from, to, version.clone());
+                }
+            }
+            (Behaviour::Honest | Behaviour::Equivocate, _) => self.broadcast(from, &op),
+            (Behaviour::Forge, _) => {
+                self.broadcast(from, &op);
+                let victim = self.rng.gen_range(0..self.peers.len());
+                let mut forged = op.inner.clone();
+                forged.author = self.peers[victim].crdt.id;
+                forged.id = forged.hash_to_id();
+                let forged =
+                    SignedOp::from_op(forged, &self.peers[from].keypair, op.depends_on.clone());
+                self.broadcast(from, &forged);
+            }
+            (Behaviour::Flood, _) => {
+                self.broadcast(from, &op);
+                for _ in 0..3 {
+                    let mut missing = [0u8; 64];
+                    self.rng.fill(&mut missing[..]);
+                    let queued = SignedOp::from_op(
+                        op.inner.clone(),
+                        &self.peers[from].keypair,
+                        vec![missing],
+                    );
+                    self.broadcast(from, &queued);
+                }
+                let mut jump = op.inner.clone();
+                jump.seq = u64::MAX;
+                jump.id = jump.hash_to_id();
+                let jump = SignedOp::from_op(jump, &self.peers[from].keypair, op.depends_on);
+                self.broadcast(from, &jump);
+            }
+            (Behaviour::Withhold, _) => {
+                if self.rng.gen_bool(0.7) {
+                    self.broadcast(from, &op);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use crate::{
+    json_crdt::{ApplyError, ChainLink, CrdtNode, OpState, Value},
+    keypair::{sha256, sign, AuthorId, SignedDigest},
+    op::{print_hex, print_path, Op, OpId, ROOT_ID},
+};
+use fastcrypto::{
+    ed25519::{Ed25519KeyPair, Ed25519PublicKey, Ed25519Signature},
+    traits::{KeyPair, ToFromBytes},
+    Verifier,
+};
+
+/// A group of ops that should become visible all at once or not at all.
+/// Ops can target any path in the document. Build one with [`BaseCrdt::transaction`] and
+/// turn it into a [`SignedTransaction`] with [`Transaction::sign`]
+///
+/// [`BaseCrdt::transaction`]: crate::json_crdt::BaseCrdt::transaction
+#[derive(Clone, Default)]
+pub struct Transaction {
+    ops: Vec<Op<Value>>,
+    depends_on: Vec<SignedDigest>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an op to the transaction. Ops are applied in the order they are added so later ops
+    /// can build on earlier ones (e.g. insert after an element inserted in the same transaction)
+    pub fn with<T: CrdtNode>(mut self, op: Op<T>) -> Self {
+        self.ops.push(Op {
+            content: op.content.map(|c| c.view()),
+            origin: op.origin,
+            author: op.author,
+            seq: op.seq,
+            path: op.path,
+            is_deleted: op.is_deleted,
+            id: op.id,
+        });
+        self
+    }
+
+    /// Declare a causal dependency on a message that has to be delivered before this transaction
+    pub fn depends_on(mut self, dependency: SignedDigest) -> Self {
+        self.depends_on.push(dependency);
+        self
+    }
+
+    /// Sign all of the ops as a single unit
+    pub fn sign(self, keypair: &Ed25519KeyPair) -> SignedTransaction {
+        self.sign_chained(keypair, vec![], None)
+    }
+
+    /// Sign all of the ops as a single unit at a given position in the author's hash chain
+    pub(crate) fn sign_chained(
+        mut self,
+        keypair: &Ed25519KeyPair,
+        depends_on: Vec<SignedDigest>,
+        chain: Option<ChainLink>,
+    ) -> SignedTransaction {
+        let author = keypair.public().0.to_bytes();
+        for dep in depends_on {
+            if !self.depends_on.contains(&dep) {
+                self.depends_on.push(dep);
+            }
+        }
+        let mut new = SignedTransaction {
+            author,
+            signed_digest: [0u8; 64],
+            ops: self.ops,
+            depends_on: self.depends_on,
+            chain,
+        };
+        new.sign_digest(keypair);
+        new
+    }
+}
+
+/// A [`Transaction`] signed as a unit. Receivers deliver it with
+/// [`BaseCrdt::apply_transaction`], which rejects the whole transaction if any of its ops fail
+///
+/// [`BaseCrdt::apply_transaction`]: crate::json_crdt::BaseCrdt::apply_transaction
+#[derive(Clone)]
+pub struct SignedTransaction {
+    author: AuthorId,
+    /// Signed hash of all the ops using the priv key of author
+    pub signed_digest: SignedDigest,
+    pub ops: Vec<Op<Value>>,
+    /// List of causal dependencies
+    pub depends_on: Vec<SignedDigest>,
+    /// Position in the author's hash chain if this was signed through
+    /// [`BaseCrdt::sign_transaction`](crate::json_crdt::BaseCrdt::sign_transaction)
+    pub chain: Option<ChainLink>,
+}
+
+impl SignedTransaction {
+    pub fn author(&self) -> AuthorId {
+        self.author
+    }
+
+    /// Build an [`ApplyError`] for the transaction as a whole, attributed to the signer
+    pub(crate) fn error(&self, kind: OpState, reason: impl Into<String>) -> ApplyError {
+        ApplyError {
+            kind,
+            op_id: ROOT_ID,
+            author: self.author,
+            path: vec![],
+            reason: format!(
+                "transaction {}: {}",
+                &print_hex(&self.signed_digest)[..6],
+                reason.into()
+            ),
+        }
+    }
+
+    /// IDs of all the ops contained in this transaction
+    pub fn ids(&self) -> Vec<OpId> {
+        self.ops.iter().map(|op| op.id).collect()
+    }
+
+    /// Creates a digest of the id and path of every op (in order) and the dependencies.
+    /// See [`SignedOp`] for why this is enough to cover the rest of the op
+    fn digest(&self) -> [u8; 32] {
+        let op_string = self
+            .ops
+            .iter()
+            .map(|op| format!("{:?},{}", op.id, print_path(op.path.clone())))
+            .collect::<Vec<_>>()
+            .join(";");
+        let dependency_string = self
+            .depends_on
+            .iter()
+            .map(print_hex)
+            .collect::<Vec<_>>()
+            .join("");
+        let chain_string = ChainLink::digest_string(&self.chain);
+        let fmt_str = format!("{op_string},{dependency_string}{chain_string}");
+        sha256(fmt_str)
+    }
+
+    fn sign_digest(&mut self, keypair: &Ed25519KeyPair) {
+        self.signed_digest = sign(keypair, &self.digest()).sig.to_bytes()
+    }
+
+    /// Ensure digest was actually signed by the author it claims to be signed by
+    pub fn is_valid_digest(&self) -> bool {
+        let digest = Ed25519Signature::from_bytes(&self.signed_digest);
+        let pubkey = Ed25519PublicKey::from_bytes(&self.author());
+        match (digest, pubkey) {
+            (Ok(digest), Ok(pubkey)) => pubkey.verify(&self.digest(), &digest).is_ok(),
+            (_, _) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{
+        json_crdt::{add_crdt_fields, ApplyOutcome, BaseCrdt, CrdtNode, OpState, SignedOp, Value},
+        keypair::make_keypair,
+        list_crdt::ListCrdt,
+        lww_crdt::LwwRegisterCrdt,
+    };
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Item {
+        name: LwwRegisterCrdt<String>,
+        soulbound: LwwRegisterCrdt<bool>,
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Player {
+        inventory: ListCrdt<Item>,
+        balance: LwwRegisterCrdt<f64>,
+    }
+
+    fn sword() -> Value {
+        json!({
+            "name": "Sword",
+            "soulbound": true,
+        })
+        .into()
+    }
+
+    #[test]
+    fn test_transaction_atomic() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Player>::new(&kp1);
+        let mut base2 = BaseCrdt::<Player>::new(&kp2);
+
+        let _add_money = base1.doc.balance.set(5000.0).sign(&kp1);
+        let _buy_sword = base1
+            .transaction()
+            .with(base1.doc.balance.set(2000.0))
+            .with(base1.doc.inventory.insert_idx(0, sword()))
+            .depends_on(_add_money.signed_digest)
+            .sign(&kp1);
+
+        assert_eq!(
+            base2.apply_transaction(_buy_sword),
+            Ok(ApplyOutcome::Queued)
+        );
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "balance": null, "inventory": [] })
+        );
+        assert_eq!(base2.apply(_add_money), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({
+                "balance": 2000.0,
+                "inventory": [{ "name": "Sword", "soulbound": true }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_transaction_rejected_as_unit() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Player>::new(&kp1);
+        let mut base2 = BaseCrdt::<Player>::new(&kp2);
+
+        let mut tampered = base1.doc.balance.set(1_000_000.0);
+        tampered.seq = 2;
+        let tx = base1
+            .transaction()
+            .with(base1.doc.inventory.insert_idx(0, sword()))
+            .with(tampered)
+            .sign(&kp1);
+
+        assert_eq!(
+            base2.apply_transaction(tx.clone()).unwrap_err().kind,
+            OpState::ErrHashMismatch
+        );
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "balance": null, "inventory": [] })
+        );
+
+        // the signature covers every op in the transaction
+        let mut dropped = tx;
+        dropped.ops.pop();
+        assert_eq!(
+            base2.apply_transaction(dropped).unwrap_err().kind,
+            OpState::ErrDigestMismatch
+        );
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "balance": null, "inventory": [] })
+        );
+    }
+
+    #[test]
+    fn test_op_depends_on_transaction() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Player>::new(&kp1);
+        let mut base2 = BaseCrdt::<Player>::new(&kp2);
+
+        let _tx = base1
+            .transaction()
+            .with(base1.doc.inventory.insert_idx(0, sword()))
+            .with(base1.doc.inventory.insert_idx(1, sword()))
+            .sign(&kp1);
+        let first = base1.doc.inventory.id_at(1).unwrap();
+        let _sell = SignedOp::from_op(
+            base1.doc.inventory.delete(first),
+            &kp1,
+            vec![_tx.signed_digest],
+        );
+
+        assert_eq!(base2.apply(_sell), Ok(ApplyOutcome::Queued));
+        assert_eq!(base2.apply_transaction(_tx), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert_eq!(base2.doc.inventory.view().len(), 1);
+    }
+
+    #[test]
+    fn test_transaction_waits_for_elements() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Player>::new(&kp1);
+        let mut base2 = BaseCrdt::<Player>::new(&kp2);
+
+        // each one declares only the op right before it, which is all its sequence number needs
+        let _first = base1.doc.inventory.insert_idx(0, sword()).sign(&kp1);
+        let _second = SignedOp::from_op(
+            base1.doc.inventory.insert_idx(1, sword()),
+            &kp1,
+            vec![_first.signed_digest],
+        );
+        let _sell = base1
+            .transaction()
+            .with(base1.doc.inventory.delete(_second.id()))
+            .with(base1.doc.balance.set(500.0))
+            .depends_on(_second.signed_digest)
+            .sign(&kp1);
+
+        assert_eq!(base2.apply_transaction(_sell), Ok(ApplyOutcome::Queued));
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "balance": null, "inventory": [] })
+        );
+        // the element the transaction waits for is itself waiting for the one before it
+        assert_eq!(base2.apply(_second), Ok(ApplyOutcome::Queued));
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "balance": null, "inventory": [] })
+        );
+        assert_eq!(base2.apply(_first), Ok(ApplyOutcome::Applied));
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({
+                "balance": 500.0,
+                "inventory": [{ "name": "Sword", "soulbound": true }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_transaction_checked_before_applied() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Player>::new(&kp1);
+        let mut base2 = BaseCrdt::<Player>::new(&kp2);
+
+        // the second op is for the element inserted by the first, the last one doesn't fit
+        let insert = base1.doc.inventory.insert_idx(0, sword());
+        let rename = base1.doc.inventory[0].name.set("Axe".to_string());
+        let tx = base1
+            .transaction()
+            .with(insert.clone())
+            .with(rename.clone())
+            .with(base1.doc.balance.set("free".to_string()))
+            .sign(&kp1);
+        assert_eq!(
+            base2.apply_transaction(tx).unwrap_err().kind,
+            OpState::ErrMismatchedType
+        );
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({ "balance": null, "inventory": [] })
+        );
+
+        let tx = base1.transaction().with(insert).with(rename).sign(&kp1);
+        assert_eq!(base2.apply_transaction(tx), Ok(ApplyOutcome::Applied));
+        assert_eq!(
+            base2.doc.view().into_json(),
+            json!({
+                "balance": null,
+                "inventory": [{ "name": "Axe", "soulbound": true }]
+            })
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    json_crdt::{CrdtNode, Edit, Value},
+    list_crdt::ListCrdt,
+    lww_crdt::{LwwOrder, LwwRegisterCrdt},
+    op::{Op, OpId, PathSegment},
+};
+use tracing::debug;
+
+/// Edits made within this long of each other are undone together by default
+pub const DEFAULT_CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A group of edits that are undone or redone together
+type Step = Vec<(Vec<PathSegment>, Edit)>;
+
+/// Undo/redo stack for the local author. Only edits made through the manager are recorded so
+/// undoing never touches ops from other authors. Undo and redo are expressed as new ops that
+/// have to be sent to other replicas like any other local op:
+///  - undoing an insert deletes the inserted element
+///  - undoing a delete re-inserts the content right after its tombstone
+///  - undoing a set restores the previous value, unless someone else has written to the
+///    register since
+///
+/// Edits made within [`UndoManager::capture_timeout`] of each other are grouped into a single
+/// undo step. Use [`UndoManager::boundary`] to force a new step.
+pub struct UndoManager {
+    undo_stack: Vec<Step>,
+    redo_stack: Vec<Step>,
+    capture_timeout: Duration,
+    last_edit: Option<Instant>,
+    /// Re-inserting deleted content creates a new element. Older steps still refer to the
+    /// original so we keep track of what it was replaced with
+    replaced: HashMap<OpId, OpId>,
+}
+
+impl Default for UndoManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoManager {
+    pub fn new() -> Self {
+        Self::with_capture_timeout(DEFAULT_CAPTURE_TIMEOUT)
+    }
+
+    /// Create a manager that groups edits made within `capture_timeout` of each other. Use
+    /// [`Duration::ZERO`] to make every edit its own step
+    pub fn with_capture_timeout(capture_timeout: Duration) -> Self {
+        Self {
+            undo_stack: vec![],
+            redo_stack: vec![],
+            capture_timeout,
+            last_edit: None,
+            replaced: HashMap::new(),
+        }
+    }
+
+    pub fn capture_timeout(&self) -> Duration {
+        self.capture_timeout
+    }
+
+    /// Close the current undo step so the next edit starts a new one
+    pub fn boundary(&mut self) {
+        self.last_edit = None;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Same as [`ListCrdt::insert`] but recorded for undo
+    pub fn insert<T: CrdtNode, U: Into<Value>>(
+        &mut self,
+        list: &mut ListCrdt<T>,
+        after: OpId,
+        content: U,
+    ) -> Result<Op<Value>, String> {
+        let edit = Edit::Insert {
+            after,
+            value: content.into(),
+        };
+        self.record(list, list.path.clone(), edit)
+    }
+
+    /// Same as [`ListCrdt::insert_idx`] but recorded for undo
+    pub fn insert_idx<T: CrdtNode, U: Into<Value>>(
+        &mut self,
+        list: &mut ListCrdt<T>,
+        idx: usize,
+        content: U,
+    ) -> Result<Op<Value>, String> {
+        let after = list
+            .id_at(idx)
+            .ok_or_else(|| format!("index {idx} out of range"))?;
+        self.insert(list, after, content)
+    }
+
+    /// Same as [`ListCrdt::delete`] but recorded for undo
+    pub fn delete<T: CrdtNode>(
+        &mut self,
+        list: &mut ListCrdt<T>,
+        id: OpId,
+    ) -> Result<Op<Value>, String> {
+        self.record(list, list.path.clone(), Edit::Delete { id })
+    }
+
+    /// Same as [`LwwRegisterCrdt::set`] but recorded for undo
+    pub fn set<T: CrdtNode, O: LwwOrder, U: Into<Value>>(
+        &mut self,
+        register: &mut LwwRegisterCrdt<T, O>,
+        content: U,
+    ) -> Result<Op<Value>, String> {
+        let edit = Edit::Set {
+            value: content.into(),
+        };
+        self.record(register, register.path.clone(), edit)
+    }
+
+    fn record<N: CrdtNode>(
+        &mut self,
+        node: &mut N,
+        path: Vec<PathSegment>,
+        edit: Edit,
+    ) -> Result<Op<Value>, String> {
+        let (op, inverse) = node.edit(&path, edit)?;
+
+        let now = Instant::now();
+        let continues_step = self
+            .last_edit
+            .is_some_and(|last| now.duration_since(last) < self.capture_timeout);
+        match self.undo_stack.last_mut() {
+            Some(step) if continues_step => step.push((path, inverse)),
+            _ => self.undo_stack.push(vec![(path, inverse)]),
+        }
+        self.last_edit = Some(now);
+        self.redo_stack.clear();
+        Ok(op)
+    }
+
+    /// Revert the most recent undo step. Returns the ops that have to be sent to other
+    /// replicas, which is empty if there was nothing to undo
+    pub fn undo<T: CrdtNode>(&mut self, doc: &mut T) -> Vec<Op<Value>> {
+        let Some(step) = self.undo_stack.pop() else {
+            return vec![];
+        };
+        let (ops, redo) = self.revert(doc, step);
+        if !redo.is_empty() {
+            self.redo_stack.push(redo);
+        }
+        self.boundary();
+        ops
+    }
+
+    /// Reapply the most recently undone step. Returns the ops that have to be sent to other
+    /// replicas, which is empty if there was nothing to redo
+    pub fn redo<T: CrdtNode>(&mut self, doc: &mut T) -> Vec<Op<Value>> {
+        let Some(step) = self.redo_stack.pop() else {
+            return vec![];
+        };
+        let (ops, undo) = self.revert(doc, step);
+        if !undo.is_empty() {
+            self.undo_stack.push(undo);
+        }
+        self.boundary();
+        ops
+    }
+
+    /// Apply the edits of a step newest first, collecting the step that reverts it again.
+    /// Edits that no longer make sense (e.g. a register someone else has overwritten since)
+    /// are skipped
+    fn revert<T: CrdtNode>(&mut self, doc: &mut T, step: Step) -> (Vec<Op<Value>>, Step) {
+        let mut ops = vec![];
+        let mut inverse = vec![];
+        for (path, edit) in step.into_iter().rev() {
+            let edit = match edit {
+                Edit::Delete { id } => Edit::Delete {
+                    id: self.latest(id),
+                },
+                edit => edit,
+            };
+            let reinserted = match &edit {
+                Edit::Insert { after, .. } => Some(*after),
+                _ => None,
+            };
+            match doc.edit(&path, edit) {
+                Ok((op, reverted)) => {
+                    // only deletes are reverted by inserting, so this is the deleted element
+                    if let Some(original) = reinserted {
+                        self.replaced.insert(original, op.id);
+                    }
+                    ops.push(op);
+                    inverse.push((path, reverted));
+                }
+                Err(reason) => debug!(%reason, "skipping edit that can no longer be reverted"),
+            }
+        }
+        inverse.reverse();
+        (ops, inverse)
+    }
+
+    /// The element that currently stands in for `id` after any number of undos and redos
+    fn latest(&self, mut id: OpId) -> OpId {
+        while let Some(next) = self.replaced.get(&id) {
+            id = *next;
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::{
+        json_crdt::{add_crdt_fields, BaseCrdt, CrdtNode},
+        keypair::make_keypair,
+        list_crdt::ListCrdt,
+        lww_crdt::LwwRegisterCrdt,
+        op::ROOT_ID,
+        undo::UndoManager,
+    };
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Doc {
+        title: LwwRegisterCrdt<String>,
+        chars: ListCrdt<char>,
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Item {
+        name: LwwRegisterCrdt<String>,
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Todo {
+        items: ListCrdt<Item>,
+    }
+
+    #[test]
+    fn test_undo_redo_steps() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Doc>::new(&kp1);
+        let mut base2 = BaseCrdt::<Doc>::new(&kp2);
+        let mut undo = UndoManager::with_capture_timeout(Duration::MAX);
+        let mut sent = vec![];
+
+        let a = undo.insert(&mut base1.doc.chars, ROOT_ID, 'a').unwrap();
+        sent.push(a.clone());
+        sent.push(undo.insert(&mut base1.doc.chars, a.id, 'b').unwrap());
+        undo.boundary();
+        sent.push(undo.delete(&mut base1.doc.chars, a.id).unwrap());
+        assert_eq!(base1.doc.chars.view(), vec!['b']);
+
+        // the delete is undone by putting 'a' back where it was
+        sent.extend(undo.undo(&mut base1.doc));
+        assert_eq!(base1.doc.chars.view(), vec!['a', 'b']);
+        // both inserts were made in the same step
+        sent.extend(undo.undo(&mut base1.doc));
+        assert!(base1.doc.chars.view().is_empty());
+        assert!(!undo.can_undo());
+
+        sent.extend(undo.redo(&mut base1.doc));
+        assert_eq!(base1.doc.chars.view(), vec!['a', 'b']);
+        sent.extend(undo.redo(&mut base1.doc));
+        assert_eq!(base1.doc.chars.view(), vec!['b']);
+        assert!(!undo.can_redo());
+
+        // undoing after a redo still finds the re-inserted element
+        sent.extend(undo.undo(&mut base1.doc));
+        sent.extend(undo.undo(&mut base1.doc));
+        assert!(base1.doc.chars.view().is_empty());
+
+        // undo and redo are regular ops that replicate
+        for op in sent {
+            base2.apply(base1.sign(op, &kp1)).unwrap();
+        }
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+    }
+
+    #[test]
+    fn test_capture_timeout() {
+        let kp1 = make_keypair();
+        let mut base1 = BaseCrdt::<Doc>::new(&kp1);
+        let mut undo = UndoManager::with_capture_timeout(Duration::ZERO);
+
+        undo.set(&mut base1.doc.title, "one".to_string()).unwrap();
+        undo.set(&mut base1.doc.title, "two".to_string()).unwrap();
+        undo.insert_idx(&mut base1.doc.chars, 0, 'x').unwrap();
+
+        undo.undo(&mut base1.doc);
+        assert_eq!(
+            base1.doc.view().into_json(),
+            json!({ "title": "two", "chars": [] })
+        );
+        undo.undo(&mut base1.doc);
+        assert_eq!(
+            base1.doc.view().into_json(),
+            json!({ "title": "one", "chars": [] })
+        );
+
+        // a new edit clears the redo stack
+        undo.insert_idx(&mut base1.doc.chars, 0, 'y').unwrap();
+        assert!(!undo.can_redo());
+    }
+
+    #[test]
+    fn test_undo_skips_remote_changes() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Doc>::new(&kp1);
+        let mut base2 = BaseCrdt::<Doc>::new(&kp2);
+        let mut undo = UndoManager::new();
+
+        let _first = base1.doc.title.set("first".to_string());
+        base2.apply(base1.sign(_first, &kp1)).unwrap();
+        let _ours = undo.set(&mut base1.doc.title, "ours".to_string()).unwrap();
+        let _x = undo.insert_idx(&mut base1.doc.chars, 0, 'x').unwrap();
+        base2.apply(base1.sign(_ours, &kp1)).unwrap();
+        base2.apply(base1.sign(_x.clone(), &kp1)).unwrap();
+
+        // someone else writes the title and types after our 'x'
+        let _theirs = base2.doc.title.set("theirs".to_string());
+        let _theirs = base2.sign(_theirs, &kp2);
+        let _y = base2.doc.chars.insert(_x.id, 'y');
+        let _y = base2.sign(_y, &kp2);
+        base1.apply(_theirs).unwrap();
+        base1.apply(_y).unwrap();
+
+        let ops = undo.undo(&mut base1.doc);
+        assert_eq!(ops.len(), 1);
+        assert_eq!(
+            base1.doc.view().into_json(),
+            json!({ "title": "theirs", "chars": ["y"] })
+        );
+        for op in ops {
+            base2.apply(base1.sign(op, &kp1)).unwrap();
+        }
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+    }
+
+    #[test]
+    fn test_undo_skips_remote_changes_to_nested_registers() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Todo>::new(&kp1);
+        let mut base2 = BaseCrdt::<Todo>::new(&kp2);
+        let mut undo = UndoManager::new();
+
+        let op = base1.doc.items.insert(ROOT_ID, json!({ "name": "orig" }));
+        let _item = base1.sign(op, &kp1);
+        base2.apply(_item).unwrap();
+
+        // both replicas share the author of the nested register, only the op IDs differ
+        let op = undo
+            .set(&mut base1.doc.items[0].name, "ours".to_string())
+            .unwrap();
+        let _ours = base1.sign(op, &kp1);
+        base2.apply(_ours).unwrap();
+        let op = base2.doc.items[0].name.set("theirs".to_string());
+        let _theirs = base2.sign(op, &kp2);
+        base1.apply(_theirs).unwrap();
+
+        assert!(undo.undo(&mut base1.doc).is_empty());
+        assert_eq!(
+            base1.doc.view().into_json(),
+            json!({ "items": [{ "name": "theirs" }] })
+        );
+        assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+    }
+}
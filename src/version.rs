@@ -0,0 +1,167 @@
+use crate::{
+    debug::DebugView,
+    json_crdt::{BaseCrdt, CrdtNode, Value},
+    keypair::SignedDigest,
+};
+
+/// A point in the history of a document, identified by the messages that nothing else had
+/// built on yet at that point. Everything in their causal past is part of the version.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Version {
+    heads: Vec<SignedDigest>,
+}
+
+impl Version {
+    pub fn new(mut heads: Vec<SignedDigest>) -> Self {
+        heads.sort();
+        heads.dedup();
+        Self { heads }
+    }
+
+    pub fn heads(&self) -> &[SignedDigest] {
+        &self.heads
+    }
+}
+
+impl From<Vec<SignedDigest>> for Version {
+    fn from(heads: Vec<SignedDigest>) -> Self {
+        Self::new(heads)
+    }
+}
+
+impl<T: CrdtNode + DebugView> BaseCrdt<T> {
+    /// The version of the document as we currently see it. Local ops are only part of it once
+    /// they have been signed through [`BaseCrdt::sign`]
+    pub fn version(&self) -> Version {
+        Version::new(self.heads())
+    }
+
+    /// What [`CrdtNode::view`] returned at the given version. Nothing is replayed: lists keep
+    /// their tombstones and registers keep every value they were set to, so we only have to
+    /// leave out what came after. Fails if we have not received all of the version's heads
+    pub fn view_at(&self, version: &Version) -> Result<Value, String> {
+        let ops = self.ops_before(version.heads())?;
+        Ok(self.doc.view_at(&ops))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{
+        json_crdt::{add_crdt_fields, BaseCrdt, CrdtNode},
+        keypair::make_keypair,
+        list_crdt::ListCrdt,
+        lww_crdt::LwwRegisterCrdt,
+        op::ROOT_ID,
+        version::Version,
+    };
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Item {
+        name: LwwRegisterCrdt<String>,
+        done: LwwRegisterCrdt<bool>,
+    }
+
+    #[add_crdt_fields]
+    #[derive(Clone, CrdtNode)]
+    struct Todo {
+        title: LwwRegisterCrdt<String>,
+        items: ListCrdt<Item>,
+    }
+
+    #[test]
+    fn test_view_at_past_versions() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Todo>::new(&kp1);
+        let mut base2 = BaseCrdt::<Todo>::new(&kp2);
+        let empty = base2.version();
+
+        let op = base1.doc.title.set("chores".to_string());
+        let _title = base1.sign(op, &kp1);
+        let op = base1
+            .doc
+            .items
+            .insert(ROOT_ID, json!({ "name": "dishes", "done": false }));
+        let dishes = op.id;
+        let _dishes = base1.sign(op, &kp1);
+        base2.apply(_title).unwrap();
+        base2.apply(_dishes).unwrap();
+        let v1 = base2.version();
+
+        // the nested register is overwritten and the item is deleted later on
+        let op = base2.doc.items[0].done.set(true);
+        let _done = base2.sign(op, &kp2);
+        let v2 = base2.version();
+        let op = base2.doc.title.set("weekend".to_string());
+        let _rename = base2.sign(op, &kp2);
+        let op = base2.doc.items.delete(dishes);
+        let _delete = base2.sign(op, &kp2);
+        let v3 = base2.version();
+
+        assert_eq!(
+            base2.view_at(&empty).unwrap().into_json(),
+            json!({ "title": null, "items": [] })
+        );
+        assert_eq!(
+            base2.view_at(&v1).unwrap().into_json(),
+            json!({ "title": "chores", "items": [{ "name": "dishes", "done": false }] })
+        );
+        assert_eq!(
+            base2.view_at(&v2).unwrap().into_json(),
+            json!({ "title": "chores", "items": [{ "name": "dishes", "done": true }] })
+        );
+        assert_eq!(
+            base2.view_at(&v3).unwrap().into_json(),
+            base2.doc.view().into_json()
+        );
+        assert_eq!(
+            base2.view_at(&v3).unwrap().into_json(),
+            json!({ "title": "weekend", "items": [] })
+        );
+
+        // versions from other replicas work once we have received everything in them
+        assert!(base1.view_at(&v3).is_err());
+        for signed in [_done, _rename, _delete] {
+            base1.apply(signed).unwrap();
+        }
+        assert_eq!(
+            base1.view_at(&v2).unwrap().into_json(),
+            base2.view_at(&v2).unwrap().into_json()
+        );
+    }
+
+    #[test]
+    fn test_view_at_concurrent_heads() {
+        let kp1 = make_keypair();
+        let kp2 = make_keypair();
+        let mut base1 = BaseCrdt::<Todo>::new(&kp1);
+        let mut base2 = BaseCrdt::<Todo>::new(&kp2);
+
+        // both replicas set the title without seeing each other
+        let op = base1.doc.title.set("ours".to_string());
+        let _ours = base1.sign(op, &kp1);
+        let op = base2.doc.title.set("theirs".to_string());
+        let _theirs = base2.sign(op, &kp2);
+        let only_ours = base1.version();
+        let both = Version::new(vec![_theirs.signed_digest, _ours.signed_digest]);
+        base1.apply(_theirs).unwrap();
+        base2.apply(_ours).unwrap();
+        assert_eq!(base1.version().heads().len(), 2);
+        assert_eq!(base1.version(), base2.version());
+
+        assert_eq!(
+            base2.view_at(&only_ours).unwrap().into_json(),
+            json!({ "title": "ours", "items": [] })
+        );
+        // with both heads the register resolves the same way it does live
+        assert_eq!(
+            base2.view_at(&base1.version()).unwrap().into_json(),
+            base2.doc.view().into_json()
+        );
+        assert_eq!(both, base1.version());
+    }
+}
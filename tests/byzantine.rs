@@ -1,8 +1,8 @@
 use bft_json_crdt::{
-    json_crdt::{add_crdt_fields, BaseCrdt, CrdtNode, IntoCrdtNode, OpState},
+    json_crdt::{add_crdt_fields, ApplyOutcome, BaseCrdt, CrdtNode, OpState, SignedOp},
     keypair::make_keypair,
     list_crdt::ListCrdt,
-    lww_crdt::LwwRegisterCrdt,
+    lww_crdt::{Hybrid, LwwOrder, LwwRegisterCrdt},
     op::{Op, PathSegment, ROOT_ID},
 };
 use serde_json::json;
@@ -14,10 +14,12 @@ use serde_json::json;
 //  b) send incorrect sequence number to multiple nodes (which could lead to divergent state) -- this is called equivocation
 //  c) ‘forge’ updates from another author (could happen when forwarding valid messages from peers)
 // 3. send malformed updates (e.g. missing fields)
-//      this we don't test as we assume transport layer only allows valid messages
+//      well-formed messages can still carry content that does not fit the document
 // 4. overwhelm message queue by sending many updates far into the future
 //      also untestested! currently we keep an unbounded message queue
+//  a) claim a huge sequence number to win every register and sibling ordering for good
 // 5. block actual messages from honest actors (eclipse attack)
+//      detectable when honest actors chain their ops with `BaseCrdt::sign`
 
 #[add_crdt_fields]
 #[derive(Clone, CrdtNode)]
@@ -32,8 +34,10 @@ fn test_equivocation() {
     let testkey = make_keypair();
     let mut crdt = BaseCrdt::<ListExample>::new(&key);
     let mut testcrdt = BaseCrdt::<ListExample>::new(&testkey);
-    let _a = crdt.doc.list.insert(ROOT_ID, 'a').sign(&key);
-    let _b = crdt.doc.list.insert(_a.id(), 'b').sign(&key);
+    let _a = crdt.doc.list.insert(ROOT_ID, 'a');
+    let _a = crdt.sign(_a, &key);
+    let _b = crdt.doc.list.insert(_a.id(), 'b');
+    let _b = crdt.sign(_b, &key);
 
     // make a fake operation with same id as _b but different content
     let mut fake_op = _b.clone();
@@ -44,13 +48,25 @@ fn test_equivocation() {
     fake_op_seq.inner.seq = 99;
     fake_op_seq.inner.is_deleted = true;
 
-    assert_eq!(crdt.apply(fake_op.clone()), OpState::ErrHashMismatch);
-    assert_eq!(crdt.apply(fake_op_seq.clone()), OpState::ErrHashMismatch);
+    assert_eq!(
+        crdt.apply(fake_op.clone()).unwrap_err().kind,
+        OpState::ErrHashMismatch
+    );
+    assert_eq!(
+        crdt.apply(fake_op_seq.clone()).unwrap_err().kind,
+        OpState::ErrHashMismatch
+    );
 
-    assert_eq!(testcrdt.apply(fake_op_seq), OpState::ErrHashMismatch);
-    assert_eq!(testcrdt.apply(fake_op), OpState::ErrHashMismatch);
-    assert_eq!(testcrdt.apply(_a), OpState::Ok);
-    assert_eq!(testcrdt.apply(_b), OpState::Ok);
+    assert_eq!(
+        testcrdt.apply(fake_op_seq).unwrap_err().kind,
+        OpState::ErrHashMismatch
+    );
+    assert_eq!(
+        testcrdt.apply(fake_op).unwrap_err().kind,
+        OpState::ErrHashMismatch
+    );
+    assert_eq!(testcrdt.apply(_a), Ok(ApplyOutcome::Applied));
+    assert_eq!(testcrdt.apply(_b), Ok(ApplyOutcome::Applied));
 
     // make sure it doesnt accept either of the fake operations
     assert_eq!(crdt.doc.list.view(), vec!['a', 'b']);
@@ -82,9 +98,15 @@ fn test_forge_update() {
     op.id = op.hash_to_id();
     let signed = op.sign(&fake_key);
 
-    assert_eq!(crdt.apply(signed.clone()), OpState::ErrHashMismatch);
-    assert_eq!(testcrdt.apply(signed), OpState::ErrHashMismatch);
-    assert_eq!(testcrdt.apply(_a), OpState::Ok);
+    assert_eq!(
+        crdt.apply(signed.clone()).unwrap_err().kind,
+        OpState::ErrHashMismatch
+    );
+    let err = testcrdt.apply(signed).unwrap_err();
+    assert_eq!(err.kind, OpState::ErrHashMismatch);
+    assert_eq!(err.path, vec![PathSegment::Field("list".to_string())]);
+    assert!(err.reason.starts_with("expected id"));
+    assert_eq!(testcrdt.apply(_a), Ok(ApplyOutcome::Applied));
 
     // make sure it doesnt accept fake operation
     assert_eq!(crdt.doc.list.view(), vec!['a']);
@@ -108,9 +130,11 @@ fn test_path_update() {
     let testkey = make_keypair();
     let mut crdt = BaseCrdt::<Nested>::new(&key);
     let mut testcrdt = BaseCrdt::<Nested>::new(&testkey);
+    // made on a copy so neither set needs the other to justify its sequence number
+    let mut fork = crdt.doc.clone();
     let mut _true = crdt.doc.a.b.set(true);
     _true.path = vec![PathSegment::Field("x".to_string())];
-    let mut _false = crdt.doc.a.b.set(false);
+    let mut _false = fork.a.b.set(false);
     _false.path = vec![
         PathSegment::Field("a".to_string()),
         PathSegment::Index(_false.id),
@@ -124,11 +148,337 @@ fn test_path_update() {
         PathSegment::Field("b".to_string()),
     ];
 
-    assert_eq!(testcrdt.apply(signedtrue), OpState::ErrPathMismatch);
-    assert_eq!(testcrdt.apply(signedfalse), OpState::ErrPathMismatch);
-    assert_eq!(testcrdt.apply(signedfalsefakepath), OpState::ErrDigestMismatch);
+    assert_eq!(
+        testcrdt.apply(signedtrue).unwrap_err().kind,
+        OpState::ErrPathMismatch
+    );
+    assert_eq!(
+        testcrdt.apply(signedfalse).unwrap_err().kind,
+        OpState::ErrPathMismatch
+    );
+    assert_eq!(
+        testcrdt.apply(signedfalsefakepath).unwrap_err().kind,
+        OpState::ErrDigestMismatch
+    );
 
     // make sure it doesnt accept fake operation
-    assert_eq!(crdt.doc.a.b.view(), json!(false).into());
+    assert_eq!(fork.a.b.view(), json!(false).into());
     assert_eq!(testcrdt.doc.a.b.view(), json!(null).into());
 }
+
+// case 5
+#[test]
+fn test_withheld_message() {
+    let key = make_keypair();
+    let testkey = make_keypair();
+    let mut crdt = BaseCrdt::<ListExample>::new(&key);
+    let mut testcrdt = BaseCrdt::<ListExample>::new(&testkey);
+    let _a = crdt.doc.list.insert(ROOT_ID, 'a');
+    let _a = crdt.sign(_a, &key);
+    let _b = crdt.doc.list.insert(_a.id(), 'b');
+    let _b = crdt.sign(_b, &key);
+    let _c = crdt.doc.list.insert(_b.id(), 'c');
+    let _c = crdt.sign(_c, &key);
+
+    // a malicious relay drops _b but forwards everything else
+    assert_eq!(testcrdt.apply(_a), Ok(ApplyOutcome::Applied));
+    assert_eq!(testcrdt.apply(_c), Ok(ApplyOutcome::Queued));
+    assert_eq!(testcrdt.doc.list.view(), vec!['a']);
+
+    // the receiver knows exactly what it is missing and can ask someone else for it
+    assert_eq!(testcrdt.missing_dependencies(), vec![_b.signed_digest]);
+    assert_eq!(testcrdt.history_of(&crdt.id).len(), 1);
+    assert_eq!(testcrdt.apply(_b), Ok(ApplyOutcome::Applied));
+    assert!(testcrdt.missing_dependencies().is_empty());
+    assert_eq!(testcrdt.doc.list.view(), crdt.doc.list.view());
+}
+
+// case 2a + 5, a relay tampers with a message that is still waiting on its dependencies
+#[test]
+fn test_tampered_message_not_queued() {
+    let key = make_keypair();
+    let testkey = make_keypair();
+    let mut crdt = BaseCrdt::<ListExample>::new(&key);
+    let mut testcrdt = BaseCrdt::<ListExample>::new(&testkey);
+    let _a = crdt.doc.list.insert(ROOT_ID, 'a');
+    let _a = crdt.sign(_a, &key);
+    let _b = crdt.doc.list.insert(_a.id(), 'b');
+    let _b = crdt.sign(_b, &key);
+
+    // the signature covers the op ID, not the content, so the tampering must be caught before
+    // the op waits for _a instead of once it is delivered
+    let mut fake = _b.clone();
+    fake.inner.content = Some('x'.into());
+    assert_eq!(
+        testcrdt.apply(fake).unwrap_err().kind,
+        OpState::ErrHashMismatch
+    );
+    assert!(testcrdt.missing_dependencies().is_empty());
+
+    assert_eq!(testcrdt.apply(_b), Ok(ApplyOutcome::Queued));
+    assert_eq!(testcrdt.apply(_a), Ok(ApplyOutcome::Applied));
+    assert_eq!(testcrdt.doc.list.view(), vec!['a', 'b']);
+}
+
+// case 2b, with hash chains
+#[test]
+fn test_forked_history() {
+    let key = make_keypair();
+    let testkey = make_keypair();
+    let mut crdt = BaseCrdt::<ListExample>::new(&key);
+    let mut crdt_fork = BaseCrdt::<ListExample>::new(&key);
+    let mut testcrdt = BaseCrdt::<ListExample>::new(&testkey);
+
+    // same author signs two different ops as the first op of its history
+    let _a = crdt.doc.list.insert(ROOT_ID, 'a');
+    let _a = crdt.sign(_a, &key);
+    let _x = crdt_fork.doc.list.insert(ROOT_ID, 'x');
+    let _x = crdt_fork.sign(_x, &key);
+
+    assert_eq!(testcrdt.apply(_a.clone()), Ok(ApplyOutcome::Applied));
+    assert_eq!(
+        testcrdt.apply(_x.clone()).unwrap_err().kind,
+        OpState::ErrForkedHistory
+    );
+    assert!(testcrdt.has_forked(&crdt.id));
+    assert_eq!(testcrdt.doc.list.view(), vec!['a']);
+
+    // redelivering the op we accepted is not a fork
+    assert_eq!(testcrdt.apply(_a), Ok(ApplyOutcome::Applied));
+
+    // anything built on top of the rejected fork never gets delivered
+    let _y = crdt_fork.doc.list.insert(_x.id(), 'y');
+    let _y = crdt_fork.sign(_y, &key);
+    assert_eq!(testcrdt.apply(_y), Ok(ApplyOutcome::Queued));
+    assert_eq!(testcrdt.doc.list.view(), vec!['a']);
+}
+
+#[add_crdt_fields]
+#[derive(Clone, CrdtNode)]
+struct Item {
+    name: LwwRegisterCrdt<String>,
+    tags: ListCrdt<String>,
+}
+
+#[add_crdt_fields]
+#[derive(Clone, CrdtNode)]
+struct Shop {
+    items: ListCrdt<Item>,
+}
+
+// case 3
+#[test]
+fn test_malformed_nested_content() {
+    let key = make_keypair();
+    let testkey = make_keypair();
+    let mut crdt = BaseCrdt::<Shop>::new(&key);
+    let mut testcrdt = BaseCrdt::<Shop>::new(&testkey);
+
+    // content the receiver cannot turn into an `Item`, nested as deep as it goes
+    let malformed = [
+        json!({ "name": "no tags" }),
+        json!({ "name": 5, "tags": [] }),
+        json!({ "name": "bad tags", "tags": "not a list" }),
+        json!(["not", "an", "object"]),
+    ];
+    for content in malformed {
+        let op = crdt.doc.items.insert(ROOT_ID, content).sign(&key);
+        assert_eq!(
+            testcrdt.apply(op).unwrap_err().kind,
+            OpState::ErrMismatchedType
+        );
+    }
+    assert!(testcrdt.doc.items.view().is_empty());
+
+    // the receiver is still alive and accepts honest content
+    let _ok = crdt
+        .doc
+        .items
+        .insert(ROOT_ID, json!({ "name": "apple", "tags": ["fruit"] }))
+        .sign(&key);
+    assert_eq!(testcrdt.apply(_ok), Ok(ApplyOutcome::Applied));
+    assert_eq!(
+        testcrdt.doc.view().into_json(),
+        json!({ "items": [{ "name": "apple", "tags": ["fruit"] }] })
+    );
+}
+
+#[add_crdt_fields]
+#[derive(Clone, CrdtNode)]
+struct Profile {
+    name: LwwRegisterCrdt<String>,
+    list: ListCrdt<char>,
+}
+
+// case 4a
+#[test]
+fn test_sequence_jump() {
+    let key = make_keypair();
+    let evilkey = make_keypair();
+    let testkey = make_keypair();
+    let mut crdt = BaseCrdt::<Profile>::new(&key);
+    let mut evilcrdt = BaseCrdt::<Profile>::new(&evilkey);
+    let mut testcrdt = BaseCrdt::<Profile>::new(&testkey);
+
+    let _name = crdt.doc.name.set("alice".to_string());
+    let _name = crdt.sign(_name, &key);
+    assert_eq!(evilcrdt.apply(_name.clone()), Ok(ApplyOutcome::Applied));
+    assert_eq!(testcrdt.apply(_name), Ok(ApplyOutcome::Applied));
+
+    // a properly hashed and signed set that would never lose again
+    let mut hijack = evilcrdt.doc.name.set("mallory".to_string());
+    hijack.seq = u64::MAX;
+    hijack.id = hijack.hash_to_id();
+    let hijack = evilcrdt.sign(hijack, &evilkey);
+    assert_eq!(
+        testcrdt.apply(hijack).map_err(|e| e.kind),
+        Err(OpState::ErrSequenceJump)
+    );
+
+    // same for a list element that would sort ahead of every sibling
+    let mut jump = evilcrdt.doc.list.insert(ROOT_ID, 'x');
+    jump.seq = 1 << 40;
+    jump.id = jump.hash_to_id();
+    assert_eq!(
+        testcrdt.apply(jump.sign(&evilkey)).map_err(|e| e.kind),
+        Err(OpState::ErrSequenceJump)
+    );
+
+    // honest writes still go through
+    let _rename = crdt.doc.name.set("alicia".to_string());
+    let _rename = crdt.sign(_rename, &key);
+    assert_eq!(testcrdt.apply(_rename), Ok(ApplyOutcome::Applied));
+    assert_eq!(
+        testcrdt.doc.view().into_json(),
+        json!({ "name": "alicia", "list": [] })
+    );
+}
+
+#[add_crdt_fields]
+#[derive(Clone, CrdtNode)]
+struct Stamped {
+    name: LwwRegisterCrdt<String, Hybrid>,
+    count: LwwRegisterCrdt<f64>,
+}
+
+// case 4a, registers ordered by wall clock time take stamps up to the current time instead
+#[test]
+fn test_sequence_jump_per_register() {
+    let evilkey = make_keypair();
+    let mut evilcrdt = BaseCrdt::<Stamped>::new(&evilkey);
+    let mut testcrdt = BaseCrdt::<Stamped>::new(&make_keypair());
+
+    let stamped = evilcrdt.doc.name.set("bob".to_string());
+    let stamped = evilcrdt.sign(stamped, &evilkey);
+    assert_eq!(testcrdt.apply(stamped), Ok(ApplyOutcome::Applied));
+
+    // the stamp does not justify a jump in a register that counts its causal past
+    let mut fork = evilcrdt.doc.clone();
+    let mut hijack = fork.count.set(1.0);
+    hijack.seq = <Hybrid>::next_seq(0);
+    hijack.id = hijack.hash_to_id();
+    let hijack = SignedOp::from_op(hijack, &evilkey, testcrdt.heads());
+    assert_eq!(
+        testcrdt.apply(hijack).map_err(|e| e.kind),
+        Err(OpState::ErrSequenceJump)
+    );
+    let count = evilcrdt.doc.count.set(2.0);
+    let count = evilcrdt.sign(count, &evilkey);
+    assert_eq!(testcrdt.apply(count), Ok(ApplyOutcome::Applied));
+    assert_eq!(
+        testcrdt.doc.view().into_json(),
+        json!({ "name": "bob", "count": 2.0 })
+    );
+}
+
+// case 4a, honest replicas may count past everything they declare as seen
+#[test]
+fn test_sequence_justified_by_dependencies() {
+    let key = make_keypair();
+    let otherkey = make_keypair();
+    let testkey = make_keypair();
+    let mut crdt = BaseCrdt::<Profile>::new(&key);
+    let mut othercrdt = BaseCrdt::<Profile>::new(&otherkey);
+    let mut testcrdt = BaseCrdt::<Profile>::new(&testkey);
+
+    let mut sent = vec![];
+    for name in ["a", "b", "c", "d"] {
+        let op = crdt.doc.name.set(name.to_string());
+        sent.push(crdt.sign(op, &key));
+    }
+    for op in &sent {
+        othercrdt.apply(op.clone()).unwrap();
+    }
+
+    // the set is at seq 5 which is only justified by the sets it depends on
+    let _e = othercrdt.doc.name.set("e".to_string());
+    assert_eq!(_e.seq, 5);
+    let _e = othercrdt.sign(_e, &otherkey);
+    assert_eq!(testcrdt.apply(_e), Ok(ApplyOutcome::Queued));
+    for op in sent {
+        testcrdt.apply(op).unwrap();
+    }
+    assert_eq!(testcrdt.doc.name.view(), json!("e").into());
+
+    // without declaring them it is a jump, even to a replica that has seen them
+    let _f = othercrdt.doc.name.set("f".to_string()).sign(&otherkey);
+    assert_eq!(
+        testcrdt.apply(_f).map_err(|e| e.kind),
+        Err(OpState::ErrSequenceJump)
+    );
+    assert_eq!(testcrdt.doc.name.view(), json!("e").into());
+}
+
+// case 4a, the bound must not depend on the order messages arrive in
+#[test]
+fn test_sequence_jump_in_any_order() {
+    let key = make_keypair();
+    let mut crdt = BaseCrdt::<Profile>::new(&key);
+    let mut inorder = BaseCrdt::<Profile>::new(&make_keypair());
+    let mut reversed = BaseCrdt::<Profile>::new(&make_keypair());
+
+    // signed without declaring any dependencies, so nothing justifies the second set
+    let _x = crdt.doc.name.set("x".to_string()).sign(&key);
+    let _y = crdt.doc.name.set("y".to_string()).sign(&key);
+    assert_eq!(_y.inner.seq, 2);
+
+    assert_eq!(inorder.apply(_x.clone()), Ok(ApplyOutcome::Applied));
+    assert_eq!(
+        inorder.apply(_y.clone()).map_err(|e| e.kind),
+        Err(OpState::ErrSequenceJump)
+    );
+    assert_eq!(
+        reversed.apply(_y).map_err(|e| e.kind),
+        Err(OpState::ErrSequenceJump)
+    );
+    assert_eq!(reversed.apply(_x), Ok(ApplyOutcome::Applied));
+    assert_eq!(inorder.doc.name.view(), json!("x").into());
+    assert_eq!(reversed.doc.view(), inorder.doc.view());
+}
+
+// case 2b, an author that signs two different ops with the same sequence number
+#[test]
+fn test_reused_sequence_number() {
+    let evilkey = make_keypair();
+    let mut evilcrdt = BaseCrdt::<Profile>::new(&evilkey);
+    let mut crdt1 = BaseCrdt::<Profile>::new(&make_keypair());
+    let mut crdt2 = BaseCrdt::<Profile>::new(&make_keypair());
+
+    let mut fork = evilcrdt.doc.clone();
+    let x = evilcrdt.doc.list.insert(ROOT_ID, 'x').sign(&evilkey);
+    let y = fork.list.insert(ROOT_ID, 'y').sign(&evilkey);
+    let alice = evilcrdt.doc.name.set("alice".to_string()).sign(&evilkey);
+    let bob = fork.name.set("bob".to_string()).sign(&evilkey);
+    assert_eq!(x.inner.seq, y.inner.seq);
+    assert_eq!(alice.inner.seq, bob.inner.seq);
+
+    // both are valid on their own, so replicas have to agree on an order no matter which
+    // one they see first
+    for op in [&x, &y, &alice, &bob] {
+        assert_eq!(crdt1.apply(op.clone()), Ok(ApplyOutcome::Applied));
+    }
+    for op in [bob, alice, y, x] {
+        assert_eq!(crdt2.apply(op), Ok(ApplyOutcome::Applied));
+    }
+    assert_eq!(crdt1.doc.view(), crdt2.doc.view());
+}
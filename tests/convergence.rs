@@ -0,0 +1,351 @@
+use bft_json_crdt::{
+    debug::DebugView,
+    json_crdt::{crdt, BaseCrdt, CrdtNode, SignedOp, Value},
+    keypair::{Ed25519KeyPair, KeyPair},
+    list_crdt::ListCrdt,
+    lww_crdt::LwwRegisterCrdt,
+    op::Op,
+};
+use proptest::{prelude::*, sample::Index};
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::json;
+
+// Replicas edit concurrently and catch up with each other at random points of a script. The
+// messages they sign must then give the same document no matter how they are delivered:
+// reordered (commutativity), repeated (idempotence) or merged in different groupings
+// (associativity). proptest shrinks a failing script to the fewest steps that still diverge.
+
+const REPLICAS: usize = 3;
+const MAX_STEPS: usize = 24;
+
+/// One step of a script
+#[derive(Clone, Debug)]
+enum Step {
+    /// A replica makes a local edit, `kind` picks what kind of edit and `at` and `inner` where
+    Edit {
+        replica: usize,
+        kind: u8,
+        at: Index,
+        inner: Index,
+        value: u8,
+    },
+    /// A replica receives everything another replica has seen so far
+    Sync { from: usize, to: usize },
+}
+
+#[derive(Clone, Debug)]
+struct Case {
+    steps: Vec<Step>,
+    /// Swaps for shuffling the messages
+    order: Vec<Index>,
+    /// Messages to deliver a second time
+    dups: Vec<Index>,
+    /// Where to split the shuffled messages into three groups
+    cuts: (Index, Index),
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        3 => (0..REPLICAS, any::<u8>(), any::<Index>(), any::<Index>(), any::<u8>()).prop_map(
+            |(replica, kind, at, inner, value)| Step::Edit {
+                replica,
+                kind,
+                at,
+                inner,
+                value,
+            }
+        ),
+        1 => (0..REPLICAS, 0..REPLICAS).prop_map(|(from, to)| Step::Sync { from, to }),
+    ]
+}
+
+fn case() -> impl Strategy<Value = Case> {
+    (
+        prop::collection::vec(step(), 0..MAX_STEPS),
+        prop::collection::vec(any::<Index>(), MAX_STEPS),
+        prop::collection::vec(any::<Index>(), 0..4),
+        any::<(Index, Index)>(),
+    )
+        .prop_map(|(steps, order, dups, cuts)| Case {
+            steps,
+            order,
+            dups,
+            cuts,
+        })
+}
+
+/// A document that scripts can edit
+trait Scripted: CrdtNode + DebugView {
+    fn make_edit(&mut self, kind: u8, at: Index, inner: Index, value: u8) -> Option<Op<Value>>;
+}
+
+/// Insert `value` at a position picked by `at`, or delete the element there
+fn edit_list<T: CrdtNode>(
+    list: &mut ListCrdt<T>,
+    delete: bool,
+    at: Index,
+    value: impl Into<Value> + Clone,
+) -> Op<Value> {
+    let len = list.iter().count();
+    if delete && len > 0 {
+        let id = list.id_at(at.index(len) + 1).unwrap();
+        list.delete(id)
+    } else {
+        list.insert_idx(at.index(len + 1), value)
+    }
+}
+
+fn letter(value: u8) -> char {
+    (b'a' + value % 26) as char
+}
+
+#[crdt]
+struct Text {
+    text: ListCrdt<char>,
+}
+
+impl Scripted for Text {
+    fn make_edit(&mut self, kind: u8, at: Index, _: Index, value: u8) -> Option<Op<Value>> {
+        Some(edit_list(
+            &mut self.text,
+            kind.is_multiple_of(3),
+            at,
+            letter(value),
+        ))
+    }
+}
+
+#[crdt]
+struct Counter {
+    count: LwwRegisterCrdt<i64>,
+}
+
+impl Scripted for Counter {
+    fn make_edit(&mut self, kind: u8, _: Index, _: Index, value: u8) -> Option<Op<Value>> {
+        Some(match kind % 4 {
+            0 => self.count.clear(),
+            _ => self.count.set(value as i64),
+        })
+    }
+}
+
+// same shape as `test_2d_grid`
+#[crdt]
+struct Grid {
+    grid: ListCrdt<ListCrdt<LwwRegisterCrdt<bool>>>,
+}
+
+impl Scripted for Grid {
+    fn make_edit(&mut self, kind: u8, at: Index, inner: Index, value: u8) -> Option<Op<Value>> {
+        let rows = self.grid.iter().count();
+        if rows == 0 || kind % 5 < 2 {
+            let row = json!([value.is_multiple_of(2)]);
+            return Some(edit_list(&mut self.grid, kind % 5 == 1, at, row));
+        }
+        let row = &mut self.grid[at.index(rows)];
+        let cells = row.iter().count();
+        Some(match kind % 5 {
+            4 if cells > 0 => row[inner.index(cells)].set(value.is_multiple_of(2)),
+            kind => edit_list(row, kind == 3, inner, value.is_multiple_of(2)),
+        })
+    }
+}
+
+#[crdt]
+struct Contact {
+    name: LwwRegisterCrdt<String>,
+    tags: ListCrdt<String>,
+}
+
+#[crdt]
+struct Book {
+    title: LwwRegisterCrdt<String>,
+    owner: Contact,
+    contacts: ListCrdt<Contact>,
+}
+
+impl Scripted for Book {
+    fn make_edit(&mut self, kind: u8, at: Index, inner: Index, value: u8) -> Option<Op<Value>> {
+        let name = letter(value).to_string();
+        let contacts = self.contacts.iter().count();
+        Some(match kind % 6 {
+            0 => self.title.set(name),
+            1 => self.owner.name.set(name),
+            2 => edit_list(&mut self.owner.tags, value.is_multiple_of(3), at, name),
+            4 if contacts > 0 => self.contacts[at.index(contacts)].name.set(name),
+            5 if contacts > 0 => {
+                let tags = &mut self.contacts[at.index(contacts)].tags;
+                edit_list(tags, value.is_multiple_of(3), inner, name)
+            }
+            _ => {
+                let contact = json!({ "name": name, "tags": [] });
+                edit_list(&mut self.contacts, value.is_multiple_of(3), at, contact)
+            }
+        })
+    }
+}
+
+fn keypair(i: usize) -> Ed25519KeyPair {
+    Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(i as u64))
+}
+
+/// A replica along with every message it has received, in the order it received them
+struct Replica<T: CrdtNode> {
+    crdt: BaseCrdt<T>,
+    log: Vec<SignedOp>,
+}
+
+impl<T: Scripted> Replica<T> {
+    fn new(keypair: &Ed25519KeyPair) -> Self {
+        Self {
+            crdt: BaseCrdt::new(keypair),
+            log: vec![],
+        }
+    }
+
+    /// Apply the messages we haven't received yet
+    fn receive(&mut self, ops: &[SignedOp]) {
+        for op in ops {
+            if self
+                .log
+                .iter()
+                .all(|seen| seen.signed_digest != op.signed_digest)
+            {
+                let result = self.crdt.apply(op.clone());
+                assert!(result.is_ok(), "{result:?}");
+                self.log.push(op.clone());
+            }
+        }
+    }
+
+    /// Apply the messages of `other` that we haven't received yet
+    fn merge(&mut self, other: &Self) {
+        self.receive(&other.log);
+    }
+
+    fn view(&self) -> Value {
+        self.crdt.doc.view()
+    }
+}
+
+/// Run the script and return every message that was signed, in the order they were signed.
+/// Replicas that have heard of each other's edits make them on top of each other
+fn run<T: Scripted>(steps: &[Step]) -> Vec<SignedOp> {
+    let keys: Vec<_> = (0..REPLICAS).map(keypair).collect();
+    let mut replicas: Vec<_> = keys.iter().map(Replica::<T>::new).collect();
+    let mut sent = vec![];
+    for step in steps {
+        match *step {
+            Step::Edit {
+                replica,
+                kind,
+                at,
+                inner,
+                value,
+            } => {
+                let r = &mut replicas[replica];
+                if let Some(op) = r.crdt.doc.make_edit(kind, at, inner, value) {
+                    let signed = r.crdt.sign(op, &keys[replica]);
+                    r.log.push(signed.clone());
+                    sent.push(signed);
+                }
+            }
+            Step::Sync { from, to } => {
+                let log = replicas[from].log.clone();
+                replicas[to].receive(&log);
+            }
+        }
+    }
+
+    // the replicas that made the edits end up where everyone else does
+    for r in &mut replicas {
+        r.receive(&sent);
+    }
+    let expected = replicas[0].view();
+    for r in &replicas[1..] {
+        assert_eq!(r.view(), expected);
+    }
+    sent
+}
+
+/// View of a fresh replica after it has been sent `ops`, duplicates included
+fn deliver<T: Scripted>(ops: &[SignedOp]) -> Value {
+    let mut crdt = BaseCrdt::<T>::new(&keypair(REPLICAS));
+    for op in ops {
+        let result = crdt.apply(op.clone());
+        assert!(result.is_ok(), "{result:?}");
+    }
+    crdt.doc.view()
+}
+
+fn check<T: Scripted>(case: Case) {
+    let sent = run::<T>(&case.steps);
+    let expected = deliver::<T>(&sent);
+
+    // commutativity: any delivery order, messages that arrive before their dependencies wait
+    let mut shuffled = sent.clone();
+    for i in (1..shuffled.len()).rev() {
+        shuffled.swap(i, case.order[i].index(i + 1));
+    }
+    assert_eq!(deliver::<T>(&shuffled), expected);
+
+    // idempotence: delivering a message again changes nothing, whether or not it was applied
+    // or is still waiting the first time
+    let mut repeated = shuffled.clone();
+    if !sent.is_empty() {
+        for dup in &case.dups {
+            let op = sent[dup.index(sent.len())].clone();
+            repeated.insert(dup.index(repeated.len() + 1), op);
+        }
+    }
+    assert_eq!(deliver::<T>(&repeated), expected);
+
+    // associativity: three replicas each hear of some of the messages, then
+    // (x ⊔ y) ⊔ z = x ⊔ (y ⊔ z)
+    let (i, j) = (
+        case.cuts.0.index(sent.len() + 1),
+        case.cuts.1.index(sent.len() + 1),
+    );
+    let (i, j) = (i.min(j), i.max(j));
+    let replica = |ops: &[SignedOp]| {
+        let mut r = Replica::<T>::new(&keypair(REPLICAS));
+        r.receive(ops);
+        r
+    };
+    let (a, b, c) = (&shuffled[..i], &shuffled[i..j], &shuffled[j..]);
+
+    let (mut x, y, z) = (replica(a), replica(b), replica(c));
+    x.merge(&y);
+    x.merge(&z);
+    let left = x.view();
+
+    let (x, mut y, z) = (replica(a), replica(b), replica(c));
+    y.merge(&z);
+    y.merge(&x);
+    assert_eq!(left, y.view());
+    assert_eq!(left, expected);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn test_list_converges(case in case()) {
+        check::<Text>(case);
+    }
+
+    #[test]
+    fn test_register_converges(case in case()) {
+        check::<Counter>(case);
+    }
+
+    #[test]
+    fn test_nested_lists_converge(case in case()) {
+        check::<Grid>(case);
+    }
+
+    #[test]
+    fn test_derived_structs_converge(case in case()) {
+        check::<Book>(case);
+    }
+}
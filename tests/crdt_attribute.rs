@@ -0,0 +1,86 @@
+use bft_json_crdt::{
+    enum_crdt::CrdtEnum,
+    json_crdt::{crdt, BaseCrdt, CrdtNode},
+    keypair::make_keypair,
+    list_crdt::ListCrdt,
+    lww_crdt::LwwRegisterCrdt,
+    op::ROOT_ID,
+};
+use serde_json::json;
+
+// `path` and `id` are ordinary fields once the metadata is out of the way
+#[crdt]
+struct Route {
+    id: LwwRegisterCrdt<String>,
+    path: ListCrdt<String>,
+}
+
+#[crdt]
+struct Pair<T: CrdtNode> {
+    left: T,
+    right: T,
+}
+
+#[crdt(tag = "kind")]
+enum Stop {
+    Station {
+        #[crdt(rename = "stationName")]
+        name: LwwRegisterCrdt<String>,
+    },
+    // `state` is free to use as well
+    Depot {
+        state: LwwRegisterCrdt<String>,
+    },
+}
+
+#[crdt]
+#[derive(Clone)]
+struct Network {
+    routes: ListCrdt<Route>,
+    ends: Pair<LwwRegisterCrdt<String>>,
+    stops: ListCrdt<Stop>,
+}
+
+#[test]
+fn test_crdt_attribute() {
+    let kp1 = make_keypair();
+    let kp2 = make_keypair();
+    let mut base1 = BaseCrdt::<Network>::new(&kp1);
+    let mut base2 = BaseCrdt::<Network>::new(&kp2);
+
+    let _route = base1
+        .doc
+        .routes
+        .insert(ROOT_ID, json!({ "id": "r1", "path": ["a", "b"] }));
+    let _route = base1.sign(_route, &kp1);
+    let _left = base1.doc.ends.left.set("north".to_string());
+    let _left = base1.sign(_left, &kp1);
+    let _stop = base1.doc.stops.insert(
+        ROOT_ID,
+        json!({ "kind": "Station", "stationName": "central" }),
+    );
+    let _stop = base1.sign(_stop, &kp1);
+    base2.apply(_route).unwrap();
+    base2.apply(_left).unwrap();
+    base2.apply(_stop).unwrap();
+
+    let _depot = base2.doc.stops[0].set_variant("Depot").unwrap();
+    let _depot = base2.sign(_depot, &kp2);
+    let Stop::Depot { state, .. } = &mut base2.doc.stops[0] else {
+        panic!("expected a depot");
+    };
+    let _state = state.set("closed".to_string());
+    let _state = base2.sign(_state, &kp2);
+    base1.apply(_depot).unwrap();
+    base1.apply(_state).unwrap();
+
+    assert_eq!(base1.doc.view().into_json(), base2.doc.view().into_json());
+    assert_eq!(
+        base1.doc.view().into_json(),
+        json!({
+            "routes": [{ "id": "r1", "path": ["a", "b"] }],
+            "ends": { "left": "north", "right": null },
+            "stops": [{ "kind": "Depot", "state": "closed" }],
+        })
+    );
+}
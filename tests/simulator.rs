@@ -0,0 +1,107 @@
+use bft_json_crdt::{
+    json_crdt::{add_crdt_fields, CrdtNode, Value},
+    list_crdt::ListCrdt,
+    lww_crdt::LwwRegisterCrdt,
+    op::Op,
+    simulator::{seeds, Behaviour, SimConfig, Simulator},
+};
+use rand::{rngs::StdRng, Rng};
+
+#[add_crdt_fields]
+#[derive(Clone, CrdtNode)]
+struct Doc {
+    text: ListCrdt<char>,
+    count: LwwRegisterCrdt<i64>,
+}
+
+fn random_edit(doc: &mut Doc, rng: &mut StdRng) -> Option<Op<Value>> {
+    let len = doc.text.view().len();
+    Some(match rng.gen_range(0..10) {
+        0..=5 => doc
+            .text
+            .insert_idx(rng.gen_range(0..=len), rng.gen_range(b'a'..=b'z') as char),
+        6 | 7 if len > 0 => doc.text.delete(doc.text.id_at(rng.gen_range(1..=len))?),
+        8 => doc.count.clear(),
+        _ => doc.count.set(rng.gen_range(0..100)),
+    })
+}
+
+#[test]
+fn test_honest_peers_converge() {
+    for seed in seeds(10) {
+        let config = SimConfig {
+            peers: vec![Behaviour::Honest; 4],
+            ..SimConfig::default()
+        };
+        let mut sim = Simulator::<Doc>::new(seed, config);
+        sim.run(40, random_edit);
+        sim.settle();
+        sim.assert_converged();
+    }
+}
+
+#[test]
+fn test_partitioned_peers_converge() {
+    for seed in seeds(10) {
+        let mut sim = Simulator::<Doc>::new(seed, SimConfig::default());
+        sim.partition(vec![0, 0, 1]);
+        sim.run(30, random_edit);
+        sim.heal();
+        sim.run(10, random_edit);
+        sim.settle();
+        sim.assert_converged();
+    }
+}
+
+#[test]
+fn test_byzantine_peers_cannot_split_honest_peers() {
+    let behaviours = [Behaviour::Forge, Behaviour::Flood, Behaviour::Withhold];
+    for behaviour in behaviours {
+        for seed in seeds(5) {
+            let config = SimConfig {
+                peers: vec![
+                    Behaviour::Honest,
+                    Behaviour::Honest,
+                    Behaviour::Honest,
+                    behaviour,
+                ],
+                ..SimConfig::default()
+            };
+            let mut sim = Simulator::<Doc>::new(seed, config);
+            sim.run(30, random_edit);
+            sim.settle();
+            sim.assert_converged();
+        }
+    }
+}
+
+#[test]
+fn test_honest_peers_catch_equivocation() {
+    for seed in seeds(5) {
+        let config = SimConfig {
+            peers: vec![
+                Behaviour::Honest,
+                Behaviour::Honest,
+                Behaviour::Honest,
+                Behaviour::Equivocate,
+            ],
+            ..SimConfig::default()
+        };
+        let mut sim = Simulator::<Doc>::new(seed, config);
+        sim.run(30, random_edit);
+        sim.settle();
+        sim.assert_fork_detected(3);
+    }
+}
+
+#[test]
+fn test_runs_are_reproducible() {
+    let run = |seed| {
+        let mut sim = Simulator::<Doc>::new(seed, SimConfig::default());
+        sim.run(30, random_edit);
+        sim.settle();
+        sim.peers()[0].crdt.doc.view()
+    };
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+}